@@ -22,7 +22,11 @@ pub(crate) fn handle_request(sender: std::sync::mpsc::Sender<PlayerCommand>) {
                     if pieces.len() > 1 {
                         log::debug!(target: LOG_TARGET,"sending play command");
                         let path = pieces[1..].join(" ");
-                        _ = sender.send(PlayerCommand::Play(path));
+                        _ = sender.send(PlayerCommand::Play {
+                            path,
+                            track_id: None,
+                            replay_gain: Default::default(),
+                        });
                     }
                 }
                 "pause" => {
@@ -33,6 +37,20 @@ pub(crate) fn handle_request(sender: std::sync::mpsc::Sender<PlayerCommand>) {
                     log::debug!(target: LOG_TARGET,"sending resume command");
                     _ = sender.send(PlayerCommand::Resume)
                 }
+                "seek" => {
+                    // "seek 30" seeks to 30 seconds, "seek +10" and "seek -10" seek relative to
+                    // the current position
+                    if let Some(value) = pieces.get(1) {
+                        if let Ok(time) = value.parse::<f64>() {
+                            log::debug!(target: LOG_TARGET,"sending seek command");
+                            if value.starts_with('+') || value.starts_with('-') {
+                                _ = sender.send(PlayerCommand::SeekBy(time));
+                            } else {
+                                _ = sender.send(PlayerCommand::Seek(time));
+                            }
+                        }
+                    }
+                }
                 "exit" => std::process::exit(0),
                 _ => (),
             }
@@ -1,5 +1,8 @@
 #![allow(dead_code)]
 
+use crate::output::OutputKind;
+use crate::player::ReplayGainMode;
+
 #[derive(Debug, Clone)]
 pub(crate) struct Config {
     enable_cli: bool,
@@ -12,6 +15,18 @@ pub(crate) struct Config {
     audio_format: String,
     video_format: String,
     photo_format: String,
+    crossfade: f64,
+    fade: u64,
+    audio_output: String,
+    audio_output_file: String,
+    replay_gain: String,
+    skip_unplayable: bool,
+    output_sample_rate: u32,
+    output_channels: usize,
+    transcode_cache_size: u64,
+    library_paths: String,
+    watch_library: bool,
+    scan_workers: usize,
     // TODO: Add entry to disable developer documentation endpoints
 }
 
@@ -57,10 +72,72 @@ impl Default for Config {
             } else {
                 "jpg,png,gif".to_string()
             },
+            crossfade: if let Ok(seconds) = std::env::var("PARTY_CROSSFADE_SECONDS") {
+                seconds.parse().unwrap_or_default()
+            } else {
+                0.0
+            },
+            fade: if let Ok(milliseconds) = std::env::var("PARTY_FADE_MILLISECONDS") {
+                milliseconds.parse().unwrap_or(300)
+            } else {
+                300
+            },
+            audio_output: if let Ok(output) = std::env::var("PARTY_AUDIO_OUTPUT") {
+                output
+            } else {
+                "device".to_string()
+            },
+            audio_output_file: if let Ok(path) = std::env::var("PARTY_AUDIO_OUTPUT_FILE") {
+                path
+            } else {
+                "./recording.wav".to_string()
+            },
+            replay_gain: if let Ok(mode) = std::env::var("PARTY_REPLAY_GAIN") {
+                mode
+            } else {
+                "track".to_string()
+            },
+            skip_unplayable: if let Ok(skip) = std::env::var("PARTY_SKIP_UNPLAYABLE") {
+                skip.parse().unwrap_or(true)
+            } else {
+                true
+            },
+            output_sample_rate: if let Ok(rate) = std::env::var("PARTY_OUTPUT_SAMPLE_RATE") {
+                rate.parse().unwrap_or(44100)
+            } else {
+                44100
+            },
+            output_channels: if let Ok(channels) = std::env::var("PARTY_OUTPUT_CHANNELS") {
+                channels.parse().unwrap_or(2)
+            } else {
+                2
+            },
+            transcode_cache_size: if let Ok(size) = std::env::var("PARTY_TRANSCODE_CACHE_MB") {
+                size.parse().unwrap_or(1024)
+            } else {
+                1024
+            },
+            library_paths: std::env::var("PARTY_LIBRARY_PATHS").unwrap_or_default(),
+            watch_library: if let Ok(watch) = std::env::var("PARTY_WATCH_LIBRARY") {
+                watch.parse().unwrap_or_default()
+            } else {
+                false
+            },
+            scan_workers: if let Ok(workers) = std::env::var("PARTY_SCAN_WORKERS") {
+                workers.parse().unwrap_or_else(|_| default_scan_workers())
+            } else {
+                default_scan_workers()
+            },
         }
     }
 }
 
+fn default_scan_workers() -> usize {
+    std::thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(4)
+}
+
 impl Config {
     pub(crate) fn is_cli_enabled(&self) -> bool {
         self.enable_cli
@@ -93,6 +170,10 @@ impl Config {
         format!("{}/{}", self.static_path, "artwork")
     }
 
+    pub(crate) fn transcode_cache_path(&self) -> String {
+        format!("{}/{}", self.static_path, "transcode")
+    }
+
     pub(crate) fn audio_format(&self) -> Vec<&str> {
         self.audio_format.split(',').collect::<Vec<&str>>()
     }
@@ -103,6 +184,73 @@ impl Config {
     pub(crate) fn photo_format(&self) -> Vec<&str> {
         self.photo_format.split(',').collect::<Vec<&str>>()
     }
+
+    /// Number of seconds two tracks overlap when changing track
+    pub(crate) fn crossfade(&self) -> f64 {
+        self.crossfade
+    }
+
+    /// Duration in seconds of the fade applied on pause, resume and skip
+    pub(crate) fn fade(&self) -> f64 {
+        self.fade as f64 / 1000.0
+    }
+
+    /// Where the server player sends its audio: "device", "null" or "wav"
+    pub(crate) fn audio_output(&self) -> OutputKind {
+        match self.audio_output.trim().to_lowercase().as_str() {
+            "null" => OutputKind::Null,
+            "wav" => OutputKind::Wav(self.audio_output_file.clone()),
+            _ => OutputKind::Device,
+        }
+    }
+
+    /// Which ReplayGain values the server player normalizes with: "track", "album" or "off"
+    pub(crate) fn replay_gain(&self) -> ReplayGainMode {
+        match self.replay_gain.trim().to_lowercase().as_str() {
+            "album" => ReplayGainMode::Album,
+            "off" => ReplayGainMode::Off,
+            _ => ReplayGainMode::Track,
+        }
+    }
+
+    /// Whether the queue moves on to the next entry when a track cannot be played
+    pub(crate) fn skip_unplayable(&self) -> bool {
+        self.skip_unplayable
+    }
+
+    /// Sample rate every track is converted to before it is played
+    pub(crate) fn output_sample_rate(&self) -> u32 {
+        self.output_sample_rate.clamp(8000, 192000)
+    }
+
+    /// Number of channels every track is mixed to before it is played
+    pub(crate) fn output_channels(&self) -> usize {
+        self.output_channels.clamp(1, 8)
+    }
+
+    /// Size in bytes the transcoded files are allowed to take on disk
+    pub(crate) fn transcode_cache_size(&self) -> u64 {
+        self.transcode_cache_size * 1024 * 1024
+    }
+
+    /// The folders the music is in
+    pub(crate) fn library_paths(&self) -> Vec<&str> {
+        self.library_paths
+            .split(',')
+            .map(|path| path.trim())
+            .filter(|path| !path.is_empty())
+            .collect::<Vec<&str>>()
+    }
+
+    /// Whether the web application watches the library folders for new music
+    pub(crate) fn watch_library(&self) -> bool {
+        self.watch_library
+    }
+
+    /// Number of files scanned at the same time. Defaults to the number of CPUs
+    pub(crate) fn scan_workers(&self) -> usize {
+        self.scan_workers.max(1)
+    }
 }
 
 #[derive(Debug, Default)]
@@ -117,6 +265,18 @@ pub(crate) struct ConfigBuilder {
     audio_format: Option<String>,
     video_format: Option<String>,
     photo_format: Option<String>,
+    crossfade: Option<f64>,
+    fade: Option<u64>,
+    audio_output: Option<String>,
+    audio_output_file: Option<String>,
+    replay_gain: Option<String>,
+    skip_unplayable: Option<bool>,
+    output_sample_rate: Option<u32>,
+    output_channels: Option<usize>,
+    transcode_cache_size: Option<u64>,
+    library_paths: Option<String>,
+    watch_library: Option<bool>,
+    scan_workers: Option<usize>,
 }
 
 impl ConfigBuilder {
@@ -192,6 +352,66 @@ impl ConfigBuilder {
         self
     }
 
+    pub(crate) fn crossfade(mut self, seconds: f64) -> Self {
+        self.crossfade = Some(seconds);
+        self
+    }
+
+    pub(crate) fn fade(mut self, milliseconds: u64) -> Self {
+        self.fade = Some(milliseconds);
+        self
+    }
+
+    pub(crate) fn audio_output(mut self, output: &str) -> Self {
+        self.audio_output = Some(output.to_string());
+        self
+    }
+
+    pub(crate) fn audio_output_file(mut self, path: &str) -> Self {
+        self.audio_output_file = Some(path.to_string());
+        self
+    }
+
+    pub(crate) fn replay_gain(mut self, mode: &str) -> Self {
+        self.replay_gain = Some(mode.to_string());
+        self
+    }
+
+    pub(crate) fn skip_unplayable(mut self, skip: bool) -> Self {
+        self.skip_unplayable = Some(skip);
+        self
+    }
+
+    pub(crate) fn output_sample_rate(mut self, rate: u32) -> Self {
+        self.output_sample_rate = Some(rate);
+        self
+    }
+
+    pub(crate) fn output_channels(mut self, channels: usize) -> Self {
+        self.output_channels = Some(channels);
+        self
+    }
+
+    pub(crate) fn transcode_cache_size(mut self, megabytes: u64) -> Self {
+        self.transcode_cache_size = Some(megabytes);
+        self
+    }
+
+    pub(crate) fn library_paths(mut self, paths: &[String]) -> Self {
+        self.library_paths = Some(paths.join(","));
+        self
+    }
+
+    pub(crate) fn watch_library(mut self, watch: bool) -> Self {
+        self.watch_library = Some(watch);
+        self
+    }
+
+    pub(crate) fn scan_workers(mut self, workers: usize) -> Self {
+        self.scan_workers = Some(workers);
+        self
+    }
+
     pub(crate) fn build(self) -> Config {
         let mut the_config = Config::default();
 
@@ -205,6 +425,24 @@ impl ConfigBuilder {
         the_config.audio_format = self.audio_format.unwrap_or(the_config.audio_format);
         the_config.video_format = self.video_format.unwrap_or(the_config.video_format);
         the_config.photo_format = self.photo_format.unwrap_or(the_config.photo_format);
+        the_config.crossfade = self.crossfade.unwrap_or(the_config.crossfade);
+        the_config.fade = self.fade.unwrap_or(the_config.fade);
+        the_config.audio_output = self.audio_output.unwrap_or(the_config.audio_output);
+        the_config.audio_output_file = self
+            .audio_output_file
+            .unwrap_or(the_config.audio_output_file);
+        the_config.replay_gain = self.replay_gain.unwrap_or(the_config.replay_gain);
+        the_config.skip_unplayable = self.skip_unplayable.unwrap_or(the_config.skip_unplayable);
+        the_config.output_sample_rate = self
+            .output_sample_rate
+            .unwrap_or(the_config.output_sample_rate);
+        the_config.output_channels = self.output_channels.unwrap_or(the_config.output_channels);
+        the_config.transcode_cache_size = self
+            .transcode_cache_size
+            .unwrap_or(the_config.transcode_cache_size);
+        the_config.library_paths = self.library_paths.unwrap_or(the_config.library_paths);
+        the_config.watch_library = self.watch_library.unwrap_or(the_config.watch_library);
+        the_config.scan_workers = self.scan_workers.unwrap_or(the_config.scan_workers);
 
         the_config
     }
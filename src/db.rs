@@ -11,8 +11,8 @@ use crate::{
     entity::{
         album::AlbumRepo, album_artist::AlbumArtistRepo, album_track::AlbumTrackRepo,
         artist::ArtistRepo, artist_track::ArtistTrackRepo, client::ClientRepo, media::MediaRepo,
-        playlist::PlaylistRepo, playlist_tracks::PlaylistTracksRepo, search::SearchRepo,
-        track::TrackRepo,
+        playlist::PlaylistRepo, playlist_tracks::PlaylistTracksRepo, queue::QueueRepo,
+        search::SearchRepo, setting::SettingRepo, track::TrackRepo, waveform::WaveformRepo,
     },
     helper::{base64_decode_to_string, base64_encode},
 };
@@ -98,6 +98,18 @@ impl DbManager {
         SearchRepo::new(self.pool.clone())
     }
 
+    pub(crate) fn setting_repo(&self) -> SettingRepo {
+        SettingRepo::new(self.pool.clone())
+    }
+
+    pub(crate) fn queue_repo(&self) -> QueueRepo {
+        QueueRepo::new(self.pool.clone())
+    }
+
+    pub(crate) fn waveform_repo(&self) -> WaveformRepo {
+        WaveformRepo::new(self.pool.clone())
+    }
+
     pub(crate) async fn setup_db(&self) {
         // clients table
         if self.client_repo().setup_table().await && !self.client_repo().has_admin().await {
@@ -142,6 +154,15 @@ impl DbManager {
 
         // search tables
         self.search_repo().setup_table().await;
+
+        // settings table
+        self.setting_repo().setup_table().await;
+
+        // queue table
+        self.queue_repo().setup_table().await;
+
+        // waveforms table
+        self.waveform_repo().setup_table().await;
     }
 }
 
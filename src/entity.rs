@@ -9,8 +9,11 @@ pub(crate) mod client;
 pub(crate) mod media;
 pub(crate) mod playlist;
 pub(crate) mod playlist_tracks;
+pub(crate) mod queue;
 pub(crate) mod search;
+pub(crate) mod setting;
 pub(crate) mod track;
+pub(crate) mod waveform;
 
 use sqlx::sqlite::SqliteRow;
 
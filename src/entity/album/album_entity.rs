@@ -64,6 +64,8 @@ pub(crate) struct OutAlbumEntityDto {
     pub(crate) title: String,
     pub(crate) year: u32,
     pub(crate) metadata: AlbumMetadata,
+    /// Total duration of the album tracks, in seconds
+    pub(crate) duration: f64,
 }
 
 impl From<AlbumEntity> for OutAlbumEntityDto {
@@ -73,6 +75,7 @@ impl From<AlbumEntity> for OutAlbumEntityDto {
             title: value.title,
             year: value.year,
             metadata: value.metadata,
+            duration: 0.0,
         }
     }
 }
@@ -43,16 +43,22 @@ impl AlbumRepo {
         let sql = "INSERT OR IGNORE INTO albums (id, title, metadata, year) values (?, ?, ?, ?)";
 
         let id = Ulid::new().to_string().to_lowercase();
+        let title = album.title.clone();
+        let year = album.year.unwrap_or_default();
 
-        if sqlx::query(sql)
+        if let Ok(inserted) = sqlx::query(sql)
             .bind(&id)
             .bind(album.title)
             .bind(album.metadata.unwrap_or_default().to_string())
-            .bind(album.year.unwrap_or_default())
+            .bind(year)
             .execute(self.pool())
             .await
-            .is_ok()
         {
+            // The album is already there, added for another track of it
+            if inserted.rows_affected() == 0 {
+                return self.find_by_title_and_year(&title, year).await;
+            }
+
             let result = self.find_by_id(&id).await;
 
             // Dispatch album added event
@@ -162,6 +168,37 @@ impl AlbumRepo {
         }
     }
 
+    pub(crate) async fn find_by_title_and_year(
+        &self,
+        title: &str,
+        year: u32,
+    ) -> Option<AlbumEntity> {
+        let sql = "SELECT * FROM albums WHERE title = ? AND year = ?";
+
+        if let Ok(row) = sqlx::query(sql)
+            .bind(title)
+            .bind(year)
+            .map(AlbumEntity::from_row)
+            .fetch_one(self.pool())
+            .await
+        {
+            return row;
+        }
+
+        None
+    }
+
+    /// Total duration of the album tracks, in seconds
+    pub(crate) async fn duration(&self, id: &str) -> f64 {
+        let sql = "SELECT COALESCE(SUM(json_extract(tracks.metadata, '$.duration')), 0.0) FROM album_tracks LEFT JOIN tracks on tracks.id = album_tracks.track_id WHERE album_tracks.album_id = ?";
+
+        sqlx::query_scalar::<_, f64>(sql)
+            .bind(id)
+            .fetch_one(self.pool())
+            .await
+            .unwrap_or_default()
+    }
+
     pub(crate) async fn find_by_track_id(&self, track_id: &str) -> Vec<AlbumEntity> {
         let sql = "SELECT albums.internal_id, albums.id, albums.title, albums.metadata FROM album_tracks LEFT JOIN albums on albums.id = album_tracks.album_id WHERE album_tracks.track_id = ?";
         let mut results = Vec::new();
@@ -209,4 +246,52 @@ impl AlbumRepo {
 
         results
     }
+
+    /// The albums left without tracks
+    pub(crate) async fn find_without_tracks(&self) -> Vec<AlbumEntity> {
+        let sql = "SELECT * FROM albums WHERE id NOT IN (SELECT album_id FROM album_tracks)";
+        let mut results = Vec::new();
+
+        let mut result_stream = sqlx::query(sql)
+            .map(AlbumEntity::from_row)
+            .fetch(self.pool());
+
+        while let Ok(Some(Some(row))) = result_stream.try_next().await {
+            results.push(row);
+        }
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        entity::album::InAlbumEntityDto,
+        testing::{setup_test_db, temp_dir},
+    };
+
+    fn album(title: &str, year: u32) -> InAlbumEntityDto {
+        InAlbumEntityDto {
+            title: title.to_string(),
+            year: Some(year),
+            metadata: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn create_returns_the_album_already_there() {
+        let dir = temp_dir();
+        let albums = setup_test_db(&dir).await.album_repo();
+
+        let first = albums.create(album("Blue Train", 1957)).await.unwrap();
+        let again = albums.create(album("Blue Train", 1957)).await.unwrap();
+        assert_eq!(again.id, first.id);
+
+        // The same title another year is another album
+        let other = albums.create(album("Blue Train", 2003)).await.unwrap();
+        assert_ne!(other.id, first.id);
+
+        _ = std::fs::remove_dir_all(dir);
+    }
 }
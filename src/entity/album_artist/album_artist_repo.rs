@@ -83,4 +83,18 @@ CREATE TABLE "album_artists" (
 
         results
     }
+
+    pub(crate) async fn delete_by_album_id(&self, album_id: &str) {
+        _ = sqlx::query("DELETE FROM album_artists WHERE album_id = ?")
+            .bind(album_id)
+            .execute(self.pool())
+            .await;
+    }
+
+    pub(crate) async fn delete_by_artist_id(&self, artist_id: &str) {
+        _ = sqlx::query("DELETE FROM album_artists WHERE artist_id = ?")
+            .bind(artist_id)
+            .execute(self.pool())
+            .await;
+    }
 }
@@ -60,4 +60,12 @@ CREATE TABLE "album_tracks" (
 
         None
     }
+
+    /// Takes the track out of its albums
+    pub(crate) async fn delete_by_track_id(&self, track_id: &str) {
+        _ = sqlx::query("DELETE FROM album_tracks WHERE track_id = ?")
+            .bind(track_id)
+            .execute(self.pool())
+            .await;
+    }
 }
@@ -12,3 +12,10 @@ pub(crate) struct ArtistUpdatedEvent {
     pub(crate) artist_id: String,
 }
 impl orsomafo::Dispatchable for ArtistUpdatedEvent {}
+
+/// Dispatched when a artist is deleted
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub(crate) struct ArtistDeletedEvent {
+    pub(crate) artist_id: String,
+}
+impl orsomafo::Dispatchable for ArtistDeletedEvent {}
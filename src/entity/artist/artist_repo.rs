@@ -8,7 +8,9 @@ use crate::{
     entity::FromSqliteRow,
 };
 
-use super::{ArtistAddedEvent, ArtistEntity, ArtistUpdatedEvent, InArtistEntityDto};
+use super::{
+    ArtistAddedEvent, ArtistDeletedEvent, ArtistEntity, ArtistUpdatedEvent, InArtistEntityDto,
+};
 
 pub(crate) struct ArtistRepo {
     pool: DbConnection,
@@ -93,16 +95,37 @@ impl ArtistRepo {
     }
 
     pub async fn create_or_update(&self, artist: InArtistEntityDto) -> Option<ArtistEntity> {
-        if let Ok(Some(existing)) = sqlx::query("SELECT * FROM artists WHERE name = ?")
-            .bind(&artist.name)
-            .map(ArtistEntity::from_row)
-            .fetch_one(self.pool())
-            .await
-        {
+        if let Some(existing) = self.find_by_name(&artist.name).await {
             self.update(&existing.id, artist).await
         } else {
-            self.create(artist).await
+            let name = artist.name.clone();
+            match self.create(artist).await {
+                // The files are scanned concurrently, another one may have added the artist
+                None => self.find_by_name(&name).await,
+                created => created,
+            }
+        }
+    }
+
+    pub(crate) async fn delete(&self, id: &str) -> Option<ArtistEntity> {
+        if let Some(entity) = self.find_by_id(id).await {
+            if sqlx::query("DELETE FROM artists WHERE id = ?")
+                .bind(id)
+                .execute(self.pool())
+                .await
+                .is_ok()
+            {
+                // Dispatch artist deleted event
+                (ArtistDeletedEvent {
+                    artist_id: entity.id.clone(),
+                })
+                .dispatch_event();
+
+                return Some(entity);
+            }
         }
+
+        None
     }
 
     pub(crate) async fn paginate(&self, paginator: &mut Paginator) -> Vec<ArtistEntity> {
@@ -150,6 +173,21 @@ impl ArtistRepo {
         None
     }
 
+    pub(crate) async fn find_by_name(&self, name: &str) -> Option<ArtistEntity> {
+        let sql = "SELECT * FROM artists WHERE name = ?";
+
+        if let Ok(row) = sqlx::query(sql)
+            .bind(name)
+            .map(ArtistEntity::from_row)
+            .fetch_one(self.pool())
+            .await
+        {
+            return row;
+        }
+
+        None
+    }
+
     pub(crate) async fn find_by_track_id(&self, track_id: &str) -> Vec<ArtistEntity> {
         let sql = "SELECT artists.internal_id, artists.id, artists.name, artists.metadata FROM artist_tracks LEFT JOIN artists on artists.id = artist_tracks.artist_id WHERE artist_tracks.track_id = ?";
         let mut results = Vec::new();
@@ -182,6 +220,22 @@ impl ArtistRepo {
         results
     }
 
+    /// The artists none of the tracks are from
+    pub(crate) async fn find_without_tracks(&self) -> Vec<ArtistEntity> {
+        let sql = "SELECT * FROM artists WHERE id NOT IN (SELECT artist_id FROM artist_tracks)";
+        let mut results = Vec::new();
+
+        let mut result_stream = sqlx::query(sql)
+            .map(ArtistEntity::from_row)
+            .fetch(self.pool());
+
+        while let Ok(Some(Some(row))) = result_stream.try_next().await {
+            results.push(row);
+        }
+
+        results
+    }
+
     pub(crate) async fn select_random(&self, limit: i64) -> Vec<ArtistEntity> {
         let sql = "SELECT * FROM artists ORDER BY RANDOM() LIMIT ?";
         let mut results = Vec::new();
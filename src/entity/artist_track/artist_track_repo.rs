@@ -61,4 +61,12 @@ CREATE TABLE "artist_tracks" (
 
         None
     }
+
+    /// Takes the track away from its artists
+    pub(crate) async fn delete_by_track_id(&self, track_id: &str) {
+        _ = sqlx::query("DELETE FROM artist_tracks WHERE track_id = ?")
+            .bind(track_id)
+            .execute(self.pool())
+            .await;
+    }
 }
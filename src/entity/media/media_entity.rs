@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use actix_web::body::BoxBody;
 use actix_web::http::header::ContentType;
 use actix_web::{HttpResponse, Responder};
-use lofty::{Accessor, Tag};
+use lofty::{Accessor, ItemKey, Tag};
 use sqlx::Column;
 use sqlx::Row;
 
@@ -59,12 +59,23 @@ pub(crate) struct MediaEntity {
     pub(crate) filename: String,
     pub(crate) path: String,
     pub(crate) metadata: MediaMetadata,
+    /// Last modification time of the file when it was scanned, in milliseconds since the epoch
+    pub(crate) mtime: i64,
+    /// Size of the file in bytes when it was scanned
+    pub(crate) size: i64,
+    /// SHA-256 of the content of the file. Used to find files that moved
+    pub(crate) hash: String,
 }
 
 impl MediaEntity {
     pub(crate) fn is_audio(&self) -> bool {
         self.media_type == MediaType::Audio
     }
+
+    /// True when the file has not changed since it was scanned
+    pub(crate) fn is_unchanged(&self, mtime: i64, size: i64) -> bool {
+        !self.hash.is_empty() && self.mtime == mtime && self.size == size
+    }
 }
 
 impl TryInto<InTrackEntityDto> for MediaEntity {
@@ -113,6 +124,9 @@ pub(crate) struct InMediaEntityDto {
     pub(crate) media_type: Option<MediaType>,
     pub(crate) path: Option<String>,
     pub(crate) metadata: Option<MediaMetadata>,
+    pub(crate) mtime: Option<i64>,
+    pub(crate) size: Option<i64>,
+    pub(crate) hash: Option<String>,
 }
 
 impl InMediaEntityDto {
@@ -132,6 +146,9 @@ impl InMediaEntityDto {
             }),
             path,
             metadata,
+            mtime: None,
+            size: None,
+            hash: None,
         }
     }
 }
@@ -147,6 +164,9 @@ impl From<MediaEntity> for InMediaEntityDto {
                 Some(entity.path)
             },
             metadata: Some(entity.metadata),
+            mtime: Some(entity.mtime),
+            size: Some(entity.size),
+            hash: Some(entity.hash),
         }
     }
 }
@@ -161,6 +181,55 @@ pub(crate) struct MediaMetadata {
     pub(crate) disk: u32,
     pub(crate) year: u32,
     pub(crate) pictures: HashMap<String, String>,
+    /// Duration in seconds
+    #[serde(default)]
+    pub(crate) duration: f64,
+    #[serde(default)]
+    pub(crate) sample_rate: u32,
+    #[serde(default)]
+    pub(crate) channels: u32,
+    /// Average bitrate, in bits per second
+    #[serde(default)]
+    pub(crate) bitrate: u32,
+    /// EBU R128 integrated loudness, in LUFS
+    #[serde(default)]
+    pub(crate) loudness: Option<f32>,
+    #[serde(default)]
+    pub(crate) replay_gain: ReplayGain,
+}
+
+/// ReplayGain values of a media file. Gains are in dB, peaks are linear
+/// sample values where 1.0 is full scale
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq)]
+pub(crate) struct ReplayGain {
+    pub(crate) track_gain: Option<f32>,
+    pub(crate) track_peak: Option<f32>,
+    pub(crate) album_gain: Option<f32>,
+    pub(crate) album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// Parses values such as `-6.54 dB` or `0.988525`
+    fn parse(value: Option<&str>) -> Option<f32> {
+        value?
+            .trim()
+            .trim_end_matches(|c: char| c.is_alphabetic())
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|value| value.is_finite())
+    }
+}
+
+impl From<&Tag> for ReplayGain {
+    fn from(tag: &Tag) -> Self {
+        Self {
+            track_gain: Self::parse(tag.get_string(&ItemKey::ReplayGainTrackGain)),
+            track_peak: Self::parse(tag.get_string(&ItemKey::ReplayGainTrackPeak)),
+            album_gain: Self::parse(tag.get_string(&ItemKey::ReplayGainAlbumGain)),
+            album_peak: Self::parse(tag.get_string(&ItemKey::ReplayGainAlbumPeak)),
+        }
+    }
 }
 
 impl From<&Tag> for MediaMetadata {
@@ -188,6 +257,8 @@ impl From<&Tag> for MediaMetadata {
         if let Some(genre) = tag.genre() {
             metadata.genre = genre.to_string();
         }
+
+        metadata.replay_gain = ReplayGain::from(tag);
         metadata
     }
 }
@@ -252,6 +323,9 @@ impl FromSqliteRow for MediaEntity {
                         entity.metadata = metadata;
                     }
                 }
+                "mtime" => entity.mtime = row.get(column.name()),
+                "size" => entity.size = row.get(column.name()),
+                "hash" => entity.hash = row.get(column.name()),
 
                 _ => panic!("New field added to the media table: {:?}", column.name()),
             }
@@ -28,6 +28,9 @@ impl MediaRepo {
 	"media_type"	TEXT NOT NULL,
 	"path"	TEXT NOT NULL,
 	"metadata"	TEXT,
+	"mtime"	INTEGER NOT NULL DEFAULT 0,
+	"size"	INTEGER NOT NULL DEFAULT 0,
+	"hash"	TEXT NOT NULL DEFAULT '',
 	PRIMARY KEY("internal_id" AUTOINCREMENT),
   UNIQUE("filename", "path")
 );"#;
@@ -35,10 +38,26 @@ impl MediaRepo {
         if let Err(e) = sqlx::query(sql).execute(self.pool()).await {
             dbg!(e);
         }
+
+        // Tables created before files were fingerprinted get the columns added. The
+        // statements fail once the columns exist
+        for column in [
+            r#""mtime" INTEGER NOT NULL DEFAULT 0"#,
+            r#""size" INTEGER NOT NULL DEFAULT 0"#,
+            r#""hash" TEXT NOT NULL DEFAULT ''"#,
+        ] {
+            _ = sqlx::query(&format!("ALTER TABLE media ADD COLUMN {}", column))
+                .execute(self.pool())
+                .await;
+        }
+
+        _ = sqlx::query(r#"CREATE INDEX IF NOT EXISTS "media_hash" ON media ("hash")"#)
+            .execute(self.pool())
+            .await;
     }
 
     pub(crate) async fn create(&self, entity: InMediaEntityDto) -> Option<MediaEntity> {
-        let sql = "INSERT INTO media (id , filename , path , metadata, media_type) values (?, ?, ?, ?, ?)";
+        let sql = "INSERT INTO media (id , filename , path , metadata, media_type, mtime, size, hash) values (?, ?, ?, ?, ?, ?, ?, ?)";
 
         let id = Ulid::new().to_string().to_lowercase();
 
@@ -48,6 +67,9 @@ impl MediaRepo {
             .bind(entity.path.unwrap_or_default())
             .bind(entity.metadata.unwrap_or_default().to_string())
             .bind(entity.media_type.unwrap_or_default().to_string())
+            .bind(entity.mtime.unwrap_or_default())
+            .bind(entity.size.unwrap_or_default())
+            .bind(entity.hash.unwrap_or_default())
             .execute(self.pool())
             .await
         {
@@ -74,14 +96,16 @@ impl MediaRepo {
     }
 
     pub(crate) async fn update(&self, id: &str, entity: InMediaEntityDto) -> Option<MediaEntity> {
-        let sql =
-            "UPDATE media SET filename = ?, path = ?, media_type = ?, metadata = ? WHERE id = ?";
+        let sql = "UPDATE media SET filename = ?, path = ?, media_type = ?, metadata = ?, mtime = ?, size = ?, hash = ? WHERE id = ?";
         if let Some(existing) = self.find_by_id(id).await {
             if sqlx::query(sql)
                 .bind(entity.filename)
                 .bind(entity.path.unwrap_or(existing.path))
                 .bind(entity.media_type.unwrap_or(existing.media_type).to_string())
                 .bind(entity.metadata.unwrap_or(existing.metadata).to_string())
+                .bind(entity.mtime.unwrap_or(existing.mtime))
+                .bind(entity.size.unwrap_or(existing.size))
+                .bind(entity.hash.unwrap_or(existing.hash))
                 .bind(id)
                 .execute(self.pool())
                 .await
@@ -127,8 +151,82 @@ impl MediaRepo {
         None
     }
 
+    /// The media with the given content hash
+    pub(crate) async fn find_by_hash(&self, hash: &str) -> Vec<MediaEntity> {
+        let sql = "SELECT * FROM media WHERE hash = ?";
+
+        sqlx::query(sql)
+            .bind(hash)
+            .map(MediaEntity::from_row)
+            .fetch_all(self.pool())
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Records where the file of the media is and its fingerprint, leaving its metadata alone
+    pub(crate) async fn update_file(
+        &self,
+        id: &str,
+        filename: &str,
+        path: &str,
+        mtime: i64,
+        size: i64,
+        hash: &str,
+    ) -> Option<MediaEntity> {
+        let sql =
+            "UPDATE media SET filename = ?, path = ?, mtime = ?, size = ?, hash = ? WHERE id = ?";
+
+        if let Err(e) = sqlx::query(sql)
+            .bind(filename)
+            .bind(path)
+            .bind(mtime)
+            .bind(size)
+            .bind(hash)
+            .bind(id)
+            .execute(self.pool())
+            .await
+        {
+            dbg!(e.to_string());
+            return None;
+        }
+
+        self.find_by_id(id).await
+    }
+
+    /// All the audio media, whose files the scanner checks still exist
+    pub(crate) async fn find_audio(&self) -> Vec<MediaEntity> {
+        let sql = "SELECT * FROM media WHERE media_type = 'audio'";
+
+        sqlx::query(sql)
+            .map(MediaEntity::from_row)
+            .fetch_all(self.pool())
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    pub(crate) async fn delete(&self, id: &str) -> Option<MediaEntity> {
+        let entity = self.find_by_id(id).await?;
+
+        if let Err(e) = sqlx::query("DELETE FROM media WHERE id = ?")
+            .bind(id)
+            .execute(self.pool())
+            .await
+        {
+            dbg!(e.to_string());
+            return None;
+        }
+
+        Some(entity)
+    }
+
     pub(crate) async fn find_media_by_track(&self, track_id: &str) -> Option<MediaEntity> {
-        let sql = r#"SELECT media.internal_id as internal_id, media.id as "id", media.filename as filename, media.media_type as media_type, media.path as path  FROM media LEFT JOIN tracks on tracks.media_id = media.id WHERE tracks.id = ?"#;
+        let sql = r#"SELECT media.internal_id as internal_id, media.id as "id", media.filename as filename, media.media_type as media_type, media.path as path, media.metadata as metadata FROM media LEFT JOIN tracks on tracks.media_id = media.id WHERE tracks.id = ?"#;
         if let Ok(row) = sqlx::query(sql)
             .bind(track_id)
             .map(MediaEntity::from_row)
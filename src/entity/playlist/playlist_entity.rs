@@ -85,6 +85,8 @@ pub(crate) struct OutPlaylistEntityDto {
     pub(crate) name: String,
     pub(crate) is_default: bool,
     pub(crate) description: String,
+    /// Total duration of the playlist tracks, in seconds
+    pub(crate) duration: f64,
 }
 
 impl From<PlaylistEntity> for OutPlaylistEntityDto {
@@ -94,6 +96,7 @@ impl From<PlaylistEntity> for OutPlaylistEntityDto {
             name: value.name,
             is_default: value.is_default,
             description: value.description,
+            duration: 0.0,
         }
     }
 }
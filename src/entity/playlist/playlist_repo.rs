@@ -182,6 +182,17 @@ impl PlaylistRepo {
         }
     }
 
+    /// Total duration of the playlist tracks, in seconds
+    pub(crate) async fn duration(&self, id: &str) -> f64 {
+        let sql = "SELECT COALESCE(SUM(json_extract(tracks.metadata, '$.duration')), 0.0) FROM playlist_tracks LEFT JOIN tracks on tracks.id = playlist_tracks.track_id WHERE playlist_tracks.playlist_id = ?";
+
+        sqlx::query_scalar::<_, f64>(sql)
+            .bind(id)
+            .fetch_one(self.pool())
+            .await
+            .unwrap_or_default()
+    }
+
     pub(crate) async fn get_default_playlist(&self) -> Option<PlaylistEntity> {
         if let Ok(row) = sqlx::query("SELECT * FROM playlists WHERE is_default = ? ")
             .bind(1_i64)
@@ -107,4 +107,23 @@ CREATE TABLE "playlist_tracks" (
 
         existing
     }
+
+    /// Removes the track from every playlist it is in
+    pub(crate) async fn delete_by_track_id(&self, track_id: &str) -> Vec<PlaylistTrackEntity> {
+        let mut removed = Vec::new();
+        let entries = sqlx::query("SELECT * FROM playlist_tracks WHERE track_id = ?")
+            .bind(track_id)
+            .map(PlaylistTrackEntity::from_row)
+            .fetch_all(self.pool())
+            .await
+            .unwrap_or_default();
+
+        for entry in entries.into_iter().flatten() {
+            if let Some(entry) = self.delete(entry.into()).await {
+                removed.push(entry);
+            }
+        }
+
+        removed
+    }
 }
@@ -0,0 +1,5 @@
+mod queue_entity;
+mod queue_repo;
+
+pub(crate) use queue_entity::*;
+pub(crate) use queue_repo::*;
@@ -0,0 +1,57 @@
+use sqlx::Column;
+use sqlx::Row;
+
+use crate::entity::{track::OutTrackEntityDto, FromSqliteRow};
+
+/// A track waiting in the play queue
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub(crate) struct QueueEntity {
+    pub(crate) internal_id: i64,
+    pub(crate) track_id: String,
+    /// Order of the entry in the queue
+    pub(crate) position: i64,
+    /// Order of the entry when the queue is shuffled
+    pub(crate) shuffle_position: f64,
+    /// ID of the client that queued the track. Empty when queued by the server
+    pub(crate) queued_by: String,
+    /// Unix timestamp of when the track was queued
+    pub(crate) queued_at: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct OutQueueEntityDto {
+    /// Position of the entry in the play order, starting from 1
+    pub(crate) position: usize,
+    /// True when this is the entry being played
+    pub(crate) current: bool,
+    pub(crate) queued_by: String,
+    pub(crate) queued_at: i64,
+    pub(crate) track: OutTrackEntityDto,
+}
+
+impl FromSqliteRow for QueueEntity {
+    fn from_row(row: sqlx::sqlite::SqliteRow) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let mut entity = Self::default();
+
+        for column in row.columns() {
+            match column.name() {
+                "internal_id" => entity.internal_id = row.get(column.name()),
+                "track_id" => entity.track_id = row.get(column.name()),
+                "position" => entity.position = row.get(column.name()),
+                "shuffle_position" => entity.shuffle_position = row.get(column.name()),
+                "queued_by" => entity.queued_by = row.get(column.name()),
+                "queued_at" => entity.queued_at = row.get(column.name()),
+                _ => panic!("New field added to the queue table"),
+            }
+        }
+
+        if entity.internal_id > 0 {
+            Some(entity)
+        } else {
+            None
+        }
+    }
+}
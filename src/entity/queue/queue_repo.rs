@@ -0,0 +1,257 @@
+use rand::seq::SliceRandom;
+
+use crate::{db::DbConnection, entity::FromSqliteRow};
+
+use super::QueueEntity;
+
+pub(crate) struct QueueRepo {
+    pool: DbConnection,
+}
+
+impl QueueRepo {
+    pub(crate) fn new(pool: DbConnection) -> Self {
+        Self { pool }
+    }
+
+    pub(crate) fn pool(&self) -> &DbConnection {
+        &self.pool
+    }
+
+    pub(crate) async fn setup_table(&self) {
+        let sql = r#"CREATE TABLE IF NOT EXISTS "queue" (
+	"internal_id"	INTEGER,
+	"track_id"	TEXT NOT NULL,
+	"position"	INTEGER NOT NULL,
+	"shuffle_position"	REAL NOT NULL,
+	"queued_by"	TEXT NOT NULL DEFAULT '',
+	"queued_at"	INTEGER NOT NULL,
+	PRIMARY KEY("internal_id" AUTOINCREMENT)
+);"#;
+
+        if let Err(e) = sqlx::query(sql).execute(self.pool()).await {
+            dbg!(e);
+        }
+    }
+
+    /// Adds the track at the end of the queue. When `shuffle_position` is not set, the track is
+    /// also placed last in the shuffled order
+    pub(crate) async fn add(
+        &self,
+        track_id: &str,
+        queued_by: &str,
+        shuffle_position: Option<f64>,
+    ) -> Option<QueueEntity> {
+        let shuffle_position = match shuffle_position {
+            Some(position) => position,
+            None => self.max_shuffle_position().await + 1.0,
+        };
+        let queued_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+
+        let sql = r#"INSERT INTO "queue" ("track_id", "position", "shuffle_position", "queued_by", "queued_at") VALUES (?, (SELECT COALESCE(MAX("position"), 0) + 1 FROM "queue"), ?, ?, ?)"#;
+
+        match sqlx::query(sql)
+            .bind(track_id)
+            .bind(shuffle_position)
+            .bind(queued_by)
+            .bind(queued_at)
+            .execute(self.pool())
+            .await
+        {
+            Ok(result) => self.find_by_id(result.last_insert_rowid()).await,
+            Err(e) => {
+                log::error!("could not queue track: {:?}", e.to_string());
+                None
+            }
+        }
+    }
+
+    pub(crate) async fn find_by_id(&self, id: i64) -> Option<QueueEntity> {
+        let sql = r#"SELECT * FROM "queue" WHERE "internal_id" = ?"#;
+
+        if let Ok(row) = sqlx::query(sql)
+            .bind(id)
+            .map(QueueEntity::from_row)
+            .fetch_one(self.pool())
+            .await
+        {
+            return row;
+        }
+
+        None
+    }
+
+    /// All the entries in play order
+    pub(crate) async fn all(&self, shuffled: bool) -> Vec<QueueEntity> {
+        let sql = format!(
+            r#"SELECT * FROM "queue" ORDER BY "{}" ASC"#,
+            Self::order_column(shuffled)
+        );
+
+        sqlx::query(&sql)
+            .map(QueueEntity::from_row)
+            .fetch_all(self.pool())
+            .await
+            .map(|rows| rows.into_iter().flatten().collect())
+            .unwrap_or_default()
+    }
+
+    pub(crate) async fn first(&self, shuffled: bool) -> Option<QueueEntity> {
+        self.find_one_ordered(None, shuffled, true).await
+    }
+
+    pub(crate) async fn last(&self, shuffled: bool) -> Option<QueueEntity> {
+        self.find_one_ordered(None, shuffled, false).await
+    }
+
+    /// The entry played after `entry`
+    pub(crate) async fn next_after(
+        &self,
+        entry: &QueueEntity,
+        shuffled: bool,
+    ) -> Option<QueueEntity> {
+        self.find_one_ordered(Some(entry), shuffled, true).await
+    }
+
+    /// The entry played before `entry`
+    pub(crate) async fn previous_before(
+        &self,
+        entry: &QueueEntity,
+        shuffled: bool,
+    ) -> Option<QueueEntity> {
+        self.find_one_ordered(Some(entry), shuffled, false).await
+    }
+
+    pub(crate) async fn count(&self) -> i64 {
+        sqlx::query_scalar(r#"SELECT COUNT(*) FROM "queue""#)
+            .fetch_one(self.pool())
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Gives every entry a new random shuffled position. `first` is placed at the front so that
+    /// every other entry is still ahead of it
+    pub(crate) async fn shuffle(&self, first: Option<i64>) {
+        let mut ids: Vec<i64> = sqlx::query_scalar(r#"SELECT "internal_id" FROM "queue""#)
+            .fetch_all(self.pool())
+            .await
+            .unwrap_or_default();
+
+        ids.shuffle(&mut rand::thread_rng());
+        if let Some(first) = first {
+            ids.retain(|id| *id != first);
+            ids.insert(0, first);
+        }
+
+        self.reorder(&ids, true).await;
+    }
+
+    /// Numbers the entries in the order of `ids`, starting from 1
+    pub(crate) async fn reorder(&self, ids: &[i64], shuffled: bool) {
+        let sql = format!(
+            r#"UPDATE "queue" SET "{}" = ? WHERE "internal_id" = ?"#,
+            Self::order_column(shuffled)
+        );
+
+        if let Ok(mut transaction) = self.pool().begin().await {
+            for (index, id) in ids.iter().enumerate() {
+                let position = (index + 1) as i64;
+                let query = sqlx::query(&sql);
+                let query = if shuffled {
+                    query.bind(position as f64)
+                } else {
+                    query.bind(position)
+                };
+                _ = query.bind(id).execute(&mut *transaction).await;
+            }
+            _ = transaction.commit().await;
+        }
+    }
+
+    pub(crate) async fn delete(&self, id: i64) -> Option<QueueEntity> {
+        let existing = self.find_by_id(id).await;
+
+        if existing.is_some() {
+            _ = sqlx::query(r#"DELETE FROM "queue" WHERE "internal_id" = ?"#)
+                .bind(id)
+                .execute(self.pool())
+                .await;
+        }
+
+        existing
+    }
+
+    /// Takes the track out of the queue. Returns the number of entries removed
+    pub(crate) async fn delete_by_track_id(&self, track_id: &str) -> u64 {
+        sqlx::query(r#"DELETE FROM "queue" WHERE "track_id" = ?"#)
+            .bind(track_id)
+            .execute(self.pool())
+            .await
+            .map(|result| result.rows_affected())
+            .unwrap_or_default()
+    }
+
+    pub(crate) async fn max_shuffle_position(&self) -> f64 {
+        sqlx::query_scalar::<_, Option<f64>>(r#"SELECT MAX("shuffle_position") FROM "queue""#)
+            .fetch_one(self.pool())
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    }
+
+    pub(crate) async fn clear(&self) {
+        _ = sqlx::query(r#"DELETE FROM "queue""#)
+            .execute(self.pool())
+            .await;
+    }
+
+    fn order_column(shuffled: bool) -> &'static str {
+        if shuffled {
+            "shuffle_position"
+        } else {
+            "position"
+        }
+    }
+
+    /// Finds the first entry in the requested direction, optionally starting after `from`
+    async fn find_one_ordered(
+        &self,
+        from: Option<&QueueEntity>,
+        shuffled: bool,
+        forward: bool,
+    ) -> Option<QueueEntity> {
+        let column = Self::order_column(shuffled);
+        let (operator, direction) = if forward { (">", "ASC") } else { ("<", "DESC") };
+        let condition = if from.is_some() {
+            format!(r#"WHERE "{}" {} ?"#, column, operator)
+        } else {
+            String::new()
+        };
+        let sql = format!(
+            r#"SELECT * FROM "queue" {} ORDER BY "{}" {} LIMIT 1"#,
+            condition, column, direction
+        );
+
+        let mut query = sqlx::query(&sql);
+        if let Some(entry) = from {
+            query = if shuffled {
+                query.bind(entry.shuffle_position)
+            } else {
+                query.bind(entry.position as f64)
+            };
+        }
+
+        if let Ok(row) = query
+            .map(QueueEntity::from_row)
+            .fetch_one(self.pool())
+            .await
+        {
+            return row;
+        }
+
+        None
+    }
+}
@@ -42,8 +42,9 @@ impl AlbumEntry {
     }
 
     async fn delete(&self, album_id: &str) {
-        if let Some((album, repo)) = self.album_and_repo(album_id).await {
-            repo.delete(album.into()).await;
+        // The album is gone by now, only its ID is left
+        if let Some(db_manager) = busybody::helpers::get_type::<Arc<DbManager>>() {
+            db_manager.search_repo().delete("album", album_id).await;
         }
     }
 }
@@ -0,0 +1,27 @@
+use std::sync::Arc;
+
+use orsomafo::EventDispatcherBuilder;
+
+use crate::{db::DbManager, entity::artist::ArtistDeletedEvent};
+
+// Artists are added to the search index by the scanner. Only their removal goes
+// through an event
+pub(crate) fn register(builder: EventDispatcherBuilder) -> EventDispatcherBuilder {
+    builder.listen_with::<ArtistDeletedEvent>(HandleDeleted)
+}
+
+struct HandleDeleted;
+
+#[orsomafo::async_trait]
+impl orsomafo::EventHandler for HandleDeleted {
+    async fn handle(&self, dispatched: &orsomafo::DispatchedEvent) {
+        if let Some(event) = dispatched.the_event::<ArtistDeletedEvent>() {
+            if let Some(db_manager) = busybody::helpers::get_type::<Arc<DbManager>>() {
+                db_manager
+                    .search_repo()
+                    .delete("artist", &event.artist_id)
+                    .await;
+            }
+        }
+    }
+}
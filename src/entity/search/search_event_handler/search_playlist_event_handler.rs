@@ -44,8 +44,12 @@ impl PlaylistEntry {
     }
 
     async fn delete(&self, playlist_id: &str) {
-        if let Some((playlist, repo)) = self.playlist_and_repo(playlist_id).await {
-            repo.delete(playlist.into()).await;
+        // The playlist is gone by now, only its ID is left
+        if let Some(db_manager) = busybody::helpers::get_type::<Arc<DbManager>>() {
+            db_manager
+                .search_repo()
+                .delete("playlist", playlist_id)
+                .await;
         }
     }
 }
@@ -44,8 +44,9 @@ impl TrackEntry {
     }
 
     async fn delete(&self, track_id: &str) {
-        if let Some((track, repo)) = self.track_and_repo(track_id).await {
-            repo.delete(track.into()).await;
+        // The track is gone by now, only its ID is left
+        if let Some(db_manager) = busybody::helpers::get_type::<Arc<DbManager>>() {
+            db_manager.search_repo().delete("track", track_id).await;
         }
     }
 }
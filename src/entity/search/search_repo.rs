@@ -111,14 +111,48 @@ impl SearchRepo {
         self.create(entity).await;
     }
 
-    pub(crate) async fn delete(&self, entity: InSearchHitEntityDto) {
+    /// Removes the hit of the entity. Takes the name and the id rather than the entity
+    /// as the entity is usually gone by the time its hit is removed
+    pub(crate) async fn delete(&self, entity: &str, entity_id: &str) {
+        _ = sqlx::query("DELETE FROM search_pivot WHERE hit_id IN (SELECT id FROM search_hits WHERE entity = ? AND entity_id = ?)")
+            .bind(entity)
+            .bind(entity_id)
+            .execute(self.pool())
+            .await;
         _ = sqlx::query("DELETE FROM search_hits WHERE entity = ? AND entity_id = ?")
-            .bind(&entity.entity)
-            .bind(&entity.entity_id)
+            .bind(entity)
+            .bind(entity_id)
             .execute(self.pool())
             .await
     }
 
+    /// Removes the hits of the tracks, albums and artists that no longer exist.
+    /// Returns the number of hits removed
+    pub(crate) async fn delete_orphans(&self) -> u64 {
+        let mut total = 0;
+        for (entity, table) in [
+            ("track", "tracks"),
+            ("album", "albums"),
+            ("artist", "artists"),
+        ] {
+            let sql = format!(
+                "DELETE FROM search_hits WHERE entity = ? AND entity_id NOT IN (SELECT id FROM {})",
+                table
+            );
+            if let Ok(result) = sqlx::query(&sql).bind(entity).execute(self.pool()).await {
+                total += result.rows_affected();
+            }
+        }
+
+        _ = sqlx::query(
+            "DELETE FROM search_pivot WHERE hit_id NOT IN (SELECT id FROM search_hits)",
+        )
+        .execute(self.pool())
+        .await;
+
+        total
+    }
+
     pub(crate) async fn search(&self, keyword: &str) -> Vec<SearchHitEntity> {
         let mut results = Vec::new();
         let mut result_stream = sqlx::query(
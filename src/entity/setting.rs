@@ -0,0 +1,5 @@
+mod setting_entity;
+mod setting_repo;
+
+pub(crate) use setting_entity::*;
+pub(crate) use setting_repo::*;
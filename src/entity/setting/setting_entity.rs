@@ -0,0 +1,42 @@
+use sqlx::Column;
+use sqlx::Row;
+
+use crate::entity::FromSqliteRow;
+
+/// A named application setting. The value is stored as JSON
+#[derive(Debug, Default)]
+pub(crate) struct SettingEntity {
+    pub(crate) internal_id: i64,
+    pub(crate) name: String,
+    pub(crate) value: String,
+}
+
+impl SettingEntity {
+    pub(crate) fn value_as<T: serde::de::DeserializeOwned>(&self) -> Option<T> {
+        serde_json::from_str(&self.value).ok()
+    }
+}
+
+impl FromSqliteRow for SettingEntity {
+    fn from_row(row: sqlx::sqlite::SqliteRow) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let mut entity = Self::default();
+
+        for column in row.columns() {
+            match column.name() {
+                "internal_id" => entity.internal_id = row.get(column.name()),
+                "name" => entity.name = row.get(column.name()),
+                "value" => entity.value = row.get(column.name()),
+                _ => panic!("New field added to the settings table"),
+            }
+        }
+
+        if entity.internal_id > 0 {
+            Some(entity)
+        } else {
+            None
+        }
+    }
+}
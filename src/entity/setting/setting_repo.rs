@@ -0,0 +1,70 @@
+use crate::{db::DbConnection, entity::FromSqliteRow};
+
+use super::SettingEntity;
+
+pub(crate) struct SettingRepo {
+    pool: DbConnection,
+}
+
+impl SettingRepo {
+    pub(crate) fn new(pool: DbConnection) -> Self {
+        Self { pool }
+    }
+
+    pub(crate) fn pool(&self) -> &DbConnection {
+        &self.pool
+    }
+
+    pub(crate) async fn setup_table(&self) {
+        let sql = r#"CREATE TABLE IF NOT EXISTS "settings" (
+	"internal_id"	INTEGER,
+	"name"	TEXT NOT NULL UNIQUE,
+	"value"	TEXT,
+	PRIMARY KEY("internal_id" AUTOINCREMENT)
+);"#;
+
+        if let Err(e) = sqlx::query(sql).execute(self.pool()).await {
+            dbg!(e);
+        }
+    }
+
+    pub(crate) async fn set<T: serde::Serialize>(
+        &self,
+        name: &str,
+        value: &T,
+    ) -> Option<SettingEntity> {
+        let sql = r#"INSERT INTO "settings" ("name", "value") values (?, ?) ON CONFLICT("name") DO UPDATE SET "value" = excluded."value""#;
+
+        if let Err(e) = sqlx::query(sql)
+            .bind(name)
+            .bind(serde_json::to_string(value).unwrap())
+            .execute(self.pool())
+            .await
+        {
+            log::error!("could not save the setting {}: {}", name, e)
+        } else {
+            return self.find(name).await;
+        }
+
+        None
+    }
+
+    pub(crate) async fn get<T: serde::de::DeserializeOwned>(&self, name: &str) -> Option<T> {
+        self.find(name).await.and_then(|setting| setting.value_as())
+    }
+
+    pub(crate) async fn find(&self, name: &str) -> Option<SettingEntity> {
+        let sql = r#"SELECT * FROM "settings" WHERE "name" = ?"#;
+
+        if let Ok(row) = sqlx::query(sql)
+            .bind(name)
+            .map(SettingEntity::from_row)
+            .fetch_one(self.pool())
+            .await
+        {
+            return row;
+        }
+
+        None
+    }
+}
@@ -63,6 +63,23 @@ pub(crate) struct TrackMetadata {
     pub(crate) disk: u32,
     pub(crate) year: u32,
     pub(crate) pictures: HashMap<String, String>,
+    /// Duration in seconds
+    #[serde(default)]
+    pub(crate) duration: f64,
+    #[serde(default)]
+    pub(crate) sample_rate: u32,
+    #[serde(default)]
+    pub(crate) channels: u32,
+    /// Average bitrate, in bits per second
+    #[serde(default)]
+    pub(crate) bitrate: u32,
+    /// EBU R128 integrated loudness, in LUFS
+    #[serde(default)]
+    pub(crate) loudness: Option<f32>,
+    /// Set when the server player failed to play the track, so that the queue passes over it.
+    /// Cleared once the track plays through, or when a scan reads the changed file again
+    #[serde(default)]
+    pub(crate) unplayable: bool,
 }
 
 impl From<&MediaMetadata> for TrackMetadata {
@@ -76,6 +93,12 @@ impl From<&MediaMetadata> for TrackMetadata {
             disk: entity.disk,
             year: entity.year,
             pictures: entity.pictures.clone(),
+            duration: entity.duration,
+            sample_rate: entity.sample_rate,
+            channels: entity.channels,
+            bitrate: entity.bitrate,
+            loudness: entity.loudness,
+            unplayable: false,
         }
     }
 }
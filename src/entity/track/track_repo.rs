@@ -140,6 +140,16 @@ impl TrackRepo {
         None
     }
 
+    pub(crate) async fn set_unplayable(&self, id: &str, unplayable: bool) -> Option<TrackEntity> {
+        let mut track = self.find_by_id(id).await?;
+        if track.metadata.unplayable == unplayable {
+            return Some(track);
+        }
+
+        track.metadata.unplayable = unplayable;
+        self.update(id, track.into()).await
+    }
+
     pub(crate) async fn delete(&self, id: &str) -> Option<TrackEntity> {
         let sql = "DELETE FROM tracks WHERE id = ?";
         if let Some(track) = self.find_by_id(id).await {
@@ -171,8 +181,24 @@ impl TrackRepo {
         None
     }
 
+    pub(crate) async fn find_by_media_id(&self, media_id: &str) -> Vec<TrackEntity> {
+        let sql = "SELECT * FROM tracks WHERE media_id = ?";
+        let mut results = Vec::new();
+
+        let mut result_stream = sqlx::query(sql)
+            .bind(media_id)
+            .map(TrackEntity::from_row)
+            .fetch(self.pool());
+
+        while let Ok(Some(Some(row))) = result_stream.try_next().await {
+            results.push(row)
+        }
+
+        results
+    }
+
     pub(crate) async fn find_by_album_id(&self, album_id: &str) -> Vec<TrackEntity> {
-        let sql = "SELECT tracks.internal_id, tracks.media_id, tracks.id, tracks.title, tracks.metadata FROM album_tracks LEFT JOIN tracks on tracks.id = album_tracks.track_id WHERE album_tracks.album_id = ?";
+        let sql = "SELECT tracks.internal_id, tracks.media_id, tracks.id, tracks.title, tracks.metadata FROM album_tracks LEFT JOIN tracks on tracks.id = album_tracks.track_id WHERE album_tracks.album_id = ? ORDER BY json_extract(tracks.metadata, '$.disk'), json_extract(tracks.metadata, '$.track')";
         let mut results = Vec::new();
 
         let mut result_stream = sqlx::query(sql)
@@ -188,7 +214,7 @@ impl TrackRepo {
     }
 
     pub(crate) async fn find_by_playlist_id(&self, playlist_id: &str) -> Vec<TrackEntity> {
-        let sql = "SELECT tracks.internal_id, tracks.media_id, tracks.id, tracks.title, tracks.metadata FROM playlist_tracks LEFT JOIN tracks on tracks.id = playlist_tracks.track_id WHERE playlist_tracks.playlist_id = ?";
+        let sql = "SELECT tracks.internal_id, tracks.media_id, tracks.id, tracks.title, tracks.metadata FROM playlist_tracks LEFT JOIN tracks on tracks.id = playlist_tracks.track_id WHERE playlist_tracks.playlist_id = ? ORDER BY playlist_tracks.internal_id";
         let mut results = Vec::new();
 
         let mut result_stream = sqlx::query(sql)
@@ -0,0 +1,5 @@
+mod waveform_entity;
+mod waveform_repo;
+
+pub(crate) use waveform_entity::*;
+pub(crate) use waveform_repo::*;
@@ -0,0 +1,110 @@
+use sqlx::Column;
+use sqlx::Row;
+
+use crate::entity::FromSqliteRow;
+
+/// Number of peaks stored for each media
+pub(crate) const WAVEFORM_RESOLUTION: usize = 1000;
+
+/// The peaks of a media file, drawn as its waveform.
+///
+/// Each peak is the lowest and the highest sample value over a slice of the
+/// file. They are stored as pairs of signed bytes, where 127 is full scale.
+#[derive(Debug, Default)]
+pub(crate) struct WaveformEntity {
+    pub(crate) internal_id: i64,
+    pub(crate) media_id: String,
+    pub(crate) peaks: Vec<u8>,
+}
+
+impl WaveformEntity {
+    pub(crate) fn encode(peaks: &[(f32, f32)]) -> Vec<u8> {
+        peaks
+            .iter()
+            .flat_map(|(min, max)| [to_byte(*min), to_byte(*max)])
+            .collect()
+    }
+
+    pub(crate) fn peaks(&self) -> Vec<(f32, f32)> {
+        self.peaks
+            .chunks_exact(2)
+            .map(|pair| (from_byte(pair[0]), from_byte(pair[1])))
+            .collect()
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct OutWaveformEntityDto {
+    pub(crate) media_id: String,
+    pub(crate) points: usize,
+    /// Lowest sample value of each point, from -1.0 to 0.0
+    pub(crate) min: Vec<f32>,
+    /// Highest sample value of each point, from 0.0 to 1.0
+    pub(crate) max: Vec<f32>,
+}
+
+impl OutWaveformEntityDto {
+    /// The waveform with at most `points` points
+    pub(crate) fn new(entity: &WaveformEntity, points: usize) -> Self {
+        let peaks = downsample_peaks(&entity.peaks(), points);
+
+        Self {
+            media_id: entity.media_id.clone(),
+            points: peaks.len(),
+            min: peaks.iter().map(|(min, _)| *min).collect(),
+            max: peaks.iter().map(|(_, max)| *max).collect(),
+        }
+    }
+}
+
+/// Merges the peaks down to `points` peaks. Peaks already fewer than that are
+/// returned as they are
+pub(crate) fn downsample_peaks(peaks: &[(f32, f32)], points: usize) -> Vec<(f32, f32)> {
+    if points == 0 || peaks.len() <= points {
+        return peaks.to_vec();
+    }
+
+    (0..points)
+        .map(|point| {
+            let start = point * peaks.len() / points;
+            let end = (point + 1) * peaks.len() / points;
+            peaks[start..end]
+                .iter()
+                .fold((0.0_f32, 0.0_f32), |(min, max), peak| {
+                    (min.min(peak.0), max.max(peak.1))
+                })
+        })
+        .collect()
+}
+
+fn to_byte(value: f32) -> u8 {
+    (value.clamp(-1.0, 1.0) * i8::MAX as f32).round() as i8 as u8
+}
+
+fn from_byte(byte: u8) -> f32 {
+    byte as i8 as f32 / i8::MAX as f32
+}
+
+impl FromSqliteRow for WaveformEntity {
+    fn from_row(row: sqlx::sqlite::SqliteRow) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let mut entity = Self::default();
+
+        for column in row.columns() {
+            match column.name() {
+                "internal_id" => entity.internal_id = row.get(column.name()),
+                "media_id" => entity.media_id = row.get(column.name()),
+                "peaks" => entity.peaks = row.get(column.name()),
+                _ => panic!("New field added to the waveforms table"),
+            }
+        }
+
+        if entity.internal_id > 0 {
+            Some(entity)
+        } else {
+            None
+        }
+    }
+}
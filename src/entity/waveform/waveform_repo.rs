@@ -0,0 +1,70 @@
+use crate::{db::DbConnection, entity::FromSqliteRow};
+
+use super::WaveformEntity;
+
+pub(crate) struct WaveformRepo {
+    pool: DbConnection,
+}
+
+impl WaveformRepo {
+    pub(crate) fn new(pool: DbConnection) -> Self {
+        Self { pool }
+    }
+
+    pub(crate) fn pool(&self) -> &DbConnection {
+        &self.pool
+    }
+
+    pub(crate) async fn setup_table(&self) {
+        let sql = r#"CREATE TABLE IF NOT EXISTS "waveforms" (
+	"internal_id"	INTEGER,
+	"media_id"	TEXT NOT NULL UNIQUE,
+	"peaks"	BLOB NOT NULL,
+	PRIMARY KEY("internal_id" AUTOINCREMENT)
+);"#;
+
+        if let Err(e) = sqlx::query(sql).execute(self.pool()).await {
+            dbg!(e);
+        }
+    }
+
+    /// Stores the peaks of the media, replacing the existing ones
+    pub(crate) async fn set(&self, media_id: &str, peaks: &[(f32, f32)]) -> Option<WaveformEntity> {
+        let sql = r#"INSERT INTO "waveforms" ("media_id", "peaks") values (?, ?) ON CONFLICT("media_id") DO UPDATE SET "peaks" = excluded."peaks""#;
+
+        if let Err(e) = sqlx::query(sql)
+            .bind(media_id)
+            .bind(WaveformEntity::encode(peaks))
+            .execute(self.pool())
+            .await
+        {
+            println!("waveform error: {:?}", e.to_string())
+        } else {
+            return self.find_by_media_id(media_id).await;
+        }
+
+        None
+    }
+
+    pub(crate) async fn find_by_media_id(&self, media_id: &str) -> Option<WaveformEntity> {
+        let sql = r#"SELECT * FROM "waveforms" WHERE "media_id" = ?"#;
+
+        if let Ok(row) = sqlx::query(sql)
+            .bind(media_id)
+            .map(WaveformEntity::from_row)
+            .fetch_one(self.pool())
+            .await
+        {
+            return row;
+        }
+
+        None
+    }
+
+    pub(crate) async fn delete_by_media_id(&self, media_id: &str) {
+        _ = sqlx::query(r#"DELETE FROM "waveforms" WHERE "media_id" = ?"#)
+            .bind(media_id)
+            .execute(self.pool())
+            .await;
+    }
+}
@@ -1,6 +1,7 @@
 use clap::{Parser, Subcommand};
 use config::{Config, ConfigBuilder};
 use db::setup_db_connection;
+use player::{DspSettings, PlayerCommand, Volume, CROSSFADE_SETTING_NAME};
 use thread_channels::setup_threads;
 
 mod cli;
@@ -14,15 +15,17 @@ mod queue_manager;
 mod scanner;
 mod seeder;
 mod thread_channels;
+mod transcoder;
 mod web_app;
 mod websocket;
 
 mod output;
 
-#[cfg(not(target_os = "linux"))]
-#[cfg(feature = "server-play")]
 mod resampler;
 
+#[cfg(test)]
+mod testing;
+
 const DEFAULT_DOTENV: &str = r#"
 PARTY_ADMIN_ID="admin_id"
 PARTY_ADMIN_TOKEN="admin_token"
@@ -36,6 +39,19 @@ PARTY_STATIC_LOCATION="./static"
 PARTY_AUDIO_FORMAT="mp3,aac,m4a,wav,ogg,wma,webm,flac"
 PARTY_VIDEO_FORMAT="mp4"
 PARTY_PHOTO_FORMAT="jpg,png,gif"
+PARTY_CROSSFADE_SECONDS=0
+PARTY_FADE_MILLISECONDS=300
+PARTY_AUDIO_OUTPUT=device
+PARTY_AUDIO_OUTPUT_FILE="./recording.wav"
+PARTY_REPLAY_GAIN=track
+PARTY_SKIP_UNPLAYABLE=true
+PARTY_OUTPUT_SAMPLE_RATE=44100
+PARTY_OUTPUT_CHANNELS=2
+PARTY_TRANSCODE_CACHE_MB=1024
+PARTY_LIBRARY_PATHS=""
+PARTY_WATCH_LIBRARY=false
+# Defaults to the number of CPUs
+# PARTY_SCAN_WORKERS=4
 "#;
 
 #[actix_web::main]
@@ -60,6 +76,7 @@ async fn main() {
     let cli = Cli::parse();
     let mut seeding = false;
     let mut scanning = false;
+    let mut watching = false;
     let mut path_to_scan = String::new();
     let mut seed_total = 0;
 
@@ -86,9 +103,21 @@ async fn main() {
                 config_builder = config_builder.enable_web(true);
                 config_builder = config_builder.enable_ws(true);
             }
-            Commands::Scan { path } => {
+            Commands::Scan { path, workers } => {
                 scanning = true;
                 path_to_scan = path;
+                if let Some(workers) = workers {
+                    config_builder = config_builder.scan_workers(workers);
+                }
+                config_builder = config_builder.enable_cli(false);
+                config_builder = config_builder.enable_web(false);
+                config_builder = config_builder.enable_ws(false);
+            }
+            Commands::Watch { path } => {
+                watching = true;
+                if !path.is_empty() {
+                    config_builder = config_builder.library_paths(&path);
+                }
                 config_builder = config_builder.enable_cli(false);
                 config_builder = config_builder.enable_web(false);
                 config_builder = config_builder.enable_ws(false);
@@ -112,9 +141,41 @@ async fn main() {
 
     if app_config.is_cli_enabled() || app_config.is_web_enabled() {
         // Setup all the OS threads and mpsc channels
-        let (ws_rx, cmd_tx, queue_manager_tx) = setup_threads(&app_config);
+        let (ws_rx, cmd_tx, queue_manager_tx) = setup_threads(&app_config, db_manager.clone());
+
+        // Restore the last volume level
+        if let Some(volume) = db_manager
+            .setting_repo()
+            .get::<Volume>(Volume::SETTING_NAME)
+            .await
+        {
+            _ = cmd_tx.send(PlayerCommand::Volume(volume));
+        }
+
+        // Restore the equalizer and the other DSP settings
+        if let Some(settings) = db_manager
+            .setting_repo()
+            .get::<DspSettings>(DspSettings::SETTING_NAME)
+            .await
+        {
+            _ = cmd_tx.send(PlayerCommand::Dsp(settings));
+        }
+
+        // Restore the crossfade duration set at runtime
+        if let Some(seconds) = db_manager
+            .setting_repo()
+            .get::<f64>(CROSSFADE_SETTING_NAME)
+            .await
+        {
+            _ = cmd_tx.send(PlayerCommand::Crossfade(seconds));
+        }
 
         if app_config.is_web_enabled() {
+            // Import the music dropped in the library folders
+            if app_config.watch_library() {
+                actix_web::rt::spawn(scanner::watch(db_manager.clone(), app_config.clone()));
+            }
+
             // Web application
             web_app::start_webapp(
                 &app_config,
@@ -133,6 +194,8 @@ async fn main() {
         seeder::run_seeders(&db_manager, seed_total).await;
     } else if scanning {
         scanner::scan(path_to_scan, &db_manager, &app_config).await;
+    } else if watching {
+        scanner::watch(db_manager, app_config).await;
     }
 }
 
@@ -155,6 +218,15 @@ enum Commands {
     Scan {
         #[arg(short, long)]
         path: String,
+        /// Number of files scanned at the same time. Defaults to PARTY_SCAN_WORKERS
+        #[arg(short, long)]
+        workers: Option<usize>,
+    },
+    /// Imports the files added to the library folders as they land. The folders
+    /// default to PARTY_LIBRARY_PATHS
+    Watch {
+        #[arg(short, long)]
+        path: Vec<String>,
     },
 }
 
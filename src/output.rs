@@ -29,6 +29,192 @@ pub enum AudioOutputError {
 
 pub type Result<T> = result::Result<T, AudioOutputError>;
 
+/// The audio outputs the player can write to
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputKind {
+    /// The sound card of the server
+    Device,
+    /// Discards the samples at the rate they would be played
+    Null,
+    /// Records the samples to the WAV file at the given path
+    Wav(String),
+}
+
+/// Keeps an output consuming samples at the rate they would be played by a sound card
+struct Pacer {
+    started: std::time::Instant,
+    frames: u64,
+    rate: u32,
+}
+
+impl Pacer {
+    /// How far behind the pacer can fall, after a pause for example, before it starts over
+    const MAX_LAG: std::time::Duration = std::time::Duration::from_millis(200);
+
+    fn new(rate: u32) -> Self {
+        Self {
+            started: std::time::Instant::now(),
+            frames: 0,
+            rate: rate.max(1),
+        }
+    }
+
+    fn played(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(self.frames as f64 / self.rate as f64)
+    }
+
+    /// Blocks until the frames written so far would have been played
+    fn wait(&mut self, frames: usize) {
+        if self.started.elapsed() > self.played() + Self::MAX_LAG {
+            self.started = std::time::Instant::now();
+            self.frames = 0;
+        }
+
+        self.frames += frames as u64;
+        if let Some(ahead) = self.played().checked_sub(self.started.elapsed()) {
+            std::thread::sleep(ahead);
+        }
+    }
+}
+
+mod null {
+    use super::{AudioOutput, Pacer, Result};
+
+    use symphonia::core::audio::{AudioBufferRef, SignalSpec};
+
+    pub struct NullOutput {
+        pacer: Pacer,
+    }
+
+    impl NullOutput {
+        pub fn new(spec: SignalSpec) -> Self {
+            Self {
+                pacer: Pacer::new(spec.rate),
+            }
+        }
+    }
+
+    impl AudioOutput for NullOutput {
+        fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()> {
+            self.pacer.wait(decoded.frames());
+            Ok(())
+        }
+
+        fn flush(&mut self) {}
+    }
+}
+
+mod wav {
+    use std::{fs::File, io::BufWriter, sync::Mutex};
+
+    use super::{AudioOutput, AudioOutputError, Pacer, Result};
+
+    use symphonia::core::audio::{AudioBufferRef, SampleBuffer, SignalSpec};
+    use symphonia::core::units::Duration;
+
+    use log::error;
+
+    struct Recording {
+        path: String,
+        writer: hound::WavWriter<BufWriter<File>>,
+    }
+
+    /// Every output records to the same file so that the tracks follow each other
+    static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+    pub struct WavOutput {
+        pacer: Pacer,
+        sample_buf: SampleBuffer<f32>,
+    }
+
+    impl WavOutput {
+        pub fn try_open(
+            path: &str,
+            spec: SignalSpec,
+            duration: Duration,
+        ) -> Result<Box<dyn AudioOutput>> {
+            let wav_spec = hound::WavSpec {
+                channels: spec.channels.count() as u16,
+                sample_rate: spec.rate,
+                bits_per_sample: 32,
+                sample_format: hound::SampleFormat::Float,
+            };
+
+            let mut recording = RECORDING
+                .lock()
+                .map_err(|_| AudioOutputError::OpenStreamError)?;
+
+            match recording.as_ref() {
+                Some(existing) if existing.path == path => {
+                    if existing.writer.spec() != wav_spec {
+                        error!(
+                            "cannot record {:?} into {}, which is recording {:?}",
+                            wav_spec,
+                            path,
+                            existing.writer.spec()
+                        );
+                        return Err(AudioOutputError::OpenStreamError);
+                    }
+                }
+                _ => match hound::WavWriter::create(path, wav_spec) {
+                    Ok(writer) => {
+                        if let Some(previous) = recording.take() {
+                            _ = previous.writer.finalize();
+                        }
+                        recording.replace(Recording {
+                            path: path.to_string(),
+                            writer,
+                        });
+                    }
+                    Err(err) => {
+                        error!("could not create the recording {}: {}", path, err);
+                        return Err(AudioOutputError::OpenStreamError);
+                    }
+                },
+            }
+
+            Ok(Box::new(Self {
+                pacer: Pacer::new(spec.rate),
+                sample_buf: SampleBuffer::new(duration, spec),
+            }))
+        }
+    }
+
+    impl AudioOutput for WavOutput {
+        fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()> {
+            let frames = decoded.frames();
+            if frames == 0 {
+                return Ok(());
+            }
+
+            self.sample_buf.copy_interleaved_ref(decoded);
+
+            if let Ok(mut recording) = RECORDING.lock() {
+                if let Some(recording) = recording.as_mut() {
+                    for sample in self.sample_buf.samples() {
+                        if recording.writer.write_sample(*sample).is_err() {
+                            return Err(AudioOutputError::StreamClosedError);
+                        }
+                    }
+                }
+            }
+
+            self.pacer.wait(frames);
+
+            Ok(())
+        }
+
+        fn flush(&mut self) {
+            // Updates the header so that the file is valid up to this point
+            if let Ok(mut recording) = RECORDING.lock() {
+                if let Some(recording) = recording.as_mut() {
+                    _ = recording.writer.flush();
+                }
+            }
+        }
+    }
+}
+
 #[cfg(target_os = "linux")]
 #[cfg(feature = "server-play")]
 mod pulseaudio {
@@ -382,3 +568,27 @@ pub fn try_open(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOut
 pub fn try_open(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOutput>> {
     cpal::CpalAudioOutput::try_open(spec, duration)
 }
+
+/// Opens the audio output of the given kind
+pub fn open(
+    kind: &OutputKind,
+    spec: SignalSpec,
+    duration: Duration,
+) -> Result<Box<dyn AudioOutput>> {
+    match kind {
+        OutputKind::Device => open_device(spec, duration),
+        OutputKind::Null => Ok(Box::new(null::NullOutput::new(spec))),
+        OutputKind::Wav(path) => wav::WavOutput::try_open(path, spec, duration),
+    }
+}
+
+#[cfg(feature = "server-play")]
+fn open_device(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOutput>> {
+    try_open(spec, duration)
+}
+
+#[cfg(not(feature = "server-play"))]
+fn open_device(spec: SignalSpec, _duration: Duration) -> Result<Box<dyn AudioOutput>> {
+    log::warn!("built without the server-play feature, using the null output");
+    Ok(Box::new(null::NullOutput::new(spec)))
+}
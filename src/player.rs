@@ -2,6 +2,7 @@
 
 use std::{fs::File, path::Path};
 
+use symphonia::core::audio::{AudioBuffer, Signal};
 use symphonia::core::codecs::FinalizeResult;
 use symphonia::core::errors::{Error, Result};
 use symphonia::core::formats::{SeekMode, SeekTo};
@@ -17,58 +18,294 @@ use symphonia::core::{
 
 use log::warn;
 
-use crate::output;
+use crate::config::Config;
+use crate::entity::media::ReplayGain;
 use crate::websocket::websocket_message::{PlayerEvent, WebsocketMessage};
 
+use self::dsp::DspChain;
+use self::fade::Fade;
+use self::gain::Gain;
+use self::limiter::Limiter;
+use self::mixer::{MixerCommand, SourceControl, SourceWriter};
+
+mod converter;
+mod dsp;
+mod equalizer;
+mod fade;
+mod gain;
+mod limiter;
+mod live;
+mod mixer;
+mod normalization;
+mod player_state;
+
+#[cfg(test)]
+mod tests;
+
+pub(crate) use converter::{output_spec, Converter};
+pub(crate) use dsp::DspSettings;
+pub(crate) use equalizer::{
+    equalizer_preset, EQUALIZER_BANDS, EQUALIZER_FREQUENCIES, EQUALIZER_PRESETS,
+};
+pub(crate) use gain::to_f32_buffer;
+pub(crate) use live::LiveStream;
+pub(crate) use normalization::ReplayGainMode;
+pub(crate) use player_state::*;
+
 const LOG_TARGET: &str = "player";
 
+/// The name of the setting the crossfade duration is persisted under
+pub(crate) const CROSSFADE_SETTING_NAME: &str = "player_crossfade";
+
+/// Number of seconds before the end of a track the next one is asked for, so
+/// that it is ready to play without a gap
+const PRELOAD_SECONDS: f64 = 5.0;
+
 enum InternalPlayerCommands {
     Stop,
+    /// Fade out over the given number of seconds and stop
+    FadeOut(f64),
     Pause,
     Resume,
+    Seek(f64),
+    SeekBy(f64),
+    /// The gain of the new volume level
+    Volume(f32),
+    Dsp(DspSettings),
+}
+
+/// Sent to the queue manager when the track being played ends
+#[derive(Debug, Clone)]
+pub(crate) struct TrackEnded {
+    /// Path of the track that ended
+    pub(crate) path: String,
+    pub(crate) track_id: Option<String>,
+    /// Why the track could not be played, when it ended early because of an error
+    pub(crate) error: Option<String>,
+    /// Set when the track is about to end. The next track can be queued with
+    /// `PlayerCommand::PlayNext` to follow it without a gap
+    pub(crate) upcoming: bool,
+}
+
+/// A track the player has started decoding
+struct PlayingTrack {
+    sender: std::sync::mpsc::Sender<InternalPlayerCommands>,
+    control: std::sync::Arc<SourceControl>,
+    track_id: Option<String>,
+}
+
+impl PlayingTrack {
+    fn send(&self, command: InternalPlayerCommands) {
+        _ = self.sender.send(command);
+    }
+
+    /// Stops the track without a fade, dropping what has not been played yet
+    fn cancel(&self) {
+        self.control.cancel();
+        self.send(InternalPlayerCommands::Stop);
+    }
 }
 
 pub(crate) fn handle_request(
     receiver: std::sync::mpsc::Receiver<PlayerCommand>,
     sync_sender: std::sync::mpsc::Sender<WebsocketMessage>,
+    ended_sender: std::sync::mpsc::Sender<TrackEnded>,
+    player_state: SharedPlayerState,
+    live: LiveStream,
+    config: Config,
 ) {
-    let mut current_sender: Option<std::sync::mpsc::Sender<InternalPlayerCommands>> = None;
+    let mut current: Option<PlayingTrack> = None;
+    // The track queued to follow the current one
+    let mut next: Option<PlayingTrack> = None;
+    let mut volume = Volume::default();
+    let mut dsp = DspSettings::default();
+    let mut crossfade = config.crossfade();
+    let fade = config.fade();
+    let replay_gain_mode = config.replay_gain();
+    let output_spec = live.spec();
+    let mixer = mixer::start(config.audio_output(), live);
     loop {
+        if next
+            .as_ref()
+            .map(|track| track.control.is_live())
+            .unwrap_or_default()
+        {
+            current = next.take();
+            let track_id = current.as_ref().and_then(|track| track.track_id.clone());
+            log::debug!(target: LOG_TARGET,"playing the queued track {:?}", &track_id);
+            player_state.update(|state| {
+                state.track_id = track_id;
+                state.status = PlaybackStatus::Playing;
+                state.position = 0.0;
+                state.duration = 0.0;
+            });
+        }
+
         if let Ok(command) = receiver.try_recv() {
             log::debug!(target: LOG_TARGET,"handling command: {:?}", &command);
             let sync_sender_clone = sync_sender.clone();
 
             match &command {
                 PlayerCommand::Pause => {
-                    if current_sender.is_some() {
+                    if let Some(track) = &current {
                         log::debug!(target: LOG_TARGET,"pausing play");
-                        _ = current_sender
-                            .as_ref()
-                            .unwrap()
-                            .send(InternalPlayerCommands::Pause);
+                        track.send(InternalPlayerCommands::Pause);
+                        player_state.update(|state| {
+                            if state.status == PlaybackStatus::Playing {
+                                state.status = PlaybackStatus::Paused;
+                            }
+                        });
                     }
                 }
                 PlayerCommand::Resume => {
-                    log::debug!(target: LOG_TARGET,"resuming play");
-                    _ = current_sender
-                        .as_ref()
-                        .unwrap()
-                        .send(InternalPlayerCommands::Resume);
+                    if let Some(track) = &current {
+                        log::debug!(target: LOG_TARGET,"resuming play");
+                        track.send(InternalPlayerCommands::Resume);
+                        player_state.update(|state| {
+                            if state.status == PlaybackStatus::Paused {
+                                state.status = PlaybackStatus::Playing;
+                            }
+                        });
+                    }
+                }
+                PlayerCommand::Seek(time) => {
+                    if let Some(track) = &current {
+                        log::debug!(target: LOG_TARGET,"seeking to {}s", time);
+                        track.send(InternalPlayerCommands::Seek(*time));
+                    }
+                }
+                PlayerCommand::SeekBy(offset) => {
+                    if let Some(track) = &current {
+                        log::debug!(target: LOG_TARGET,"seeking by {}s", offset);
+                        track.send(InternalPlayerCommands::SeekBy(*offset));
+                    }
+                }
+                PlayerCommand::Volume(new_volume) => {
+                    volume = *new_volume;
+                    log::debug!(target: LOG_TARGET,"setting volume to {:?}", &volume);
+                    player_state.update(|state| state.volume = volume);
+                    for track in current.iter().chain(next.iter()) {
+                        track.send(InternalPlayerCommands::Volume(volume.gain()));
+                    }
+                    _ = sync_sender.send(WebsocketMessage::PlayerEvent {
+                        event: PlayerEvent::Volume {
+                            level: volume.level,
+                            muted: volume.muted,
+                        },
+                    });
+                }
+                PlayerCommand::Dsp(settings) => {
+                    dsp = settings.clone();
+                    log::debug!(target: LOG_TARGET,"setting the DSP chain to {:?}", &dsp);
+                    for track in current.iter().chain(next.iter()) {
+                        track.send(InternalPlayerCommands::Dsp(dsp.clone()));
+                    }
+                    _ = sync_sender.send(WebsocketMessage::PlayerEvent {
+                        event: PlayerEvent::Equalizer {
+                            settings: dsp.clone(),
+                        },
+                    });
                 }
-                PlayerCommand::Play(path) => {
-                    if let Some(sender) = &current_sender {
-                        _ = sender.send(InternalPlayerCommands::Stop);
+                PlayerCommand::Crossfade(seconds) => {
+                    crossfade = seconds.max(0.0);
+                    log::debug!(target: LOG_TARGET,"setting crossfade to {}s", crossfade);
+                    _ = sync_sender.send(WebsocketMessage::PlayerEvent {
+                        event: PlayerEvent::Crossfade { seconds: crossfade },
+                    });
+                }
+                PlayerCommand::Stop(fade_duration) => {
+                    log::debug!(target: LOG_TARGET,"stopping over {}s", fade_duration);
+                    if let Some(track) = next.take() {
+                        track.cancel();
+                    }
+                    if let Some(track) = current.take() {
+                        if *fade_duration > 0.0 {
+                            track.send(InternalPlayerCommands::FadeOut(*fade_duration));
+                        } else {
+                            track.cancel();
+                        }
+                    }
+                    player_state.update(|state| {
+                        state.status = PlaybackStatus::Stopped;
+                        state.position = 0.0;
+                    });
+                }
+                PlayerCommand::ClearNext => {
+                    if let Some(track) = next.take() {
+                        log::debug!(target: LOG_TARGET,"dropping the queued track");
+                        track.cancel();
+                    }
+                }
+                PlayerCommand::Play {
+                    path,
+                    track_id,
+                    replay_gain,
+                } => {
+                    // The current track fades out while the new one fades in. When crossfading,
+                    // both tracks overlap for the whole duration of the crossfade
+                    let fade_duration = if current.is_some() && crossfade > 0.0 {
+                        crossfade
+                    } else {
+                        fade
+                    };
+
+                    if let Some(track) = next.take() {
+                        track.cancel();
+                    }
+                    if let Some(track) = current.take() {
+                        if fade_duration > 0.0 {
+                            track.send(InternalPlayerCommands::FadeOut(fade_duration));
+                        } else {
+                            track.cancel();
+                        }
                     }
-                    let (sender, receiver) = std::sync::mpsc::channel::<InternalPlayerCommands>();
-                    current_sender = Some(sender);
 
                     log::debug!(target: LOG_TARGET,"playing \"{:?}\"", &path);
 
-                    // TODO: Make the abrupt stop easy to the ears. Example cross fade or something
-                    let the_path = path.clone().to_string();
-                    _ = std::thread::spawn(move || {
-                        play_music(&the_path, receiver, sync_sender_clone);
+                    player_state.update(|state| {
+                        state.track_id = track_id.clone();
+                        state.status = PlaybackStatus::Playing;
+                        state.position = 0.0;
+                        state.duration = 0.0;
                     });
+                    let (writer, source) = mixer::source(output_spec);
+                    let mut state = PlaybackState::new(
+                        volume.gain(),
+                        replay_gain_mode.gain(replay_gain),
+                        fade_duration,
+                        fade,
+                        EndOfTrack::new(path, track_id, ended_sender.clone(), crossfade),
+                        player_state.clone(),
+                        writer,
+                    );
+                    state.dsp.configure(&dsp);
+                    current = Some(start_track(path, track_id, state, sync_sender_clone));
+                    _ = mixer.send(MixerCommand::Play(source));
+                }
+                PlayerCommand::PlayNext {
+                    path,
+                    track_id,
+                    replay_gain,
+                } => {
+                    if let Some(track) = next.take() {
+                        track.cancel();
+                    }
+
+                    log::debug!(target: LOG_TARGET,"queueing \"{:?}\"", &path);
+
+                    let (writer, source) = mixer::source(output_spec);
+                    let mut state = PlaybackState::new(
+                        volume.gain(),
+                        replay_gain_mode.gain(replay_gain),
+                        0.0,
+                        fade,
+                        EndOfTrack::new(path, track_id, ended_sender.clone(), crossfade),
+                        player_state.clone(),
+                        writer,
+                    );
+                    state.dsp.configure(&dsp);
+                    next = Some(start_track(path, track_id, state, sync_sender_clone));
+                    _ = mixer.send(MixerCommand::Queue(source));
                 }
             }
         }
@@ -76,17 +313,112 @@ pub(crate) fn handle_request(
     }
 }
 
+/// Starts decoding a track in its own thread
+fn start_track(
+    path: &str,
+    track_id: &Option<String>,
+    mut state: PlaybackState,
+    sync_sender: std::sync::mpsc::Sender<WebsocketMessage>,
+) -> PlayingTrack {
+    let (sender, receiver) = std::sync::mpsc::channel::<InternalPlayerCommands>();
+    let control = state.source.control().clone();
+    let path = path.to_string();
+
+    _ = std::thread::spawn(move || {
+        play_music(&path, receiver, sync_sender, &mut state);
+    });
+
+    PlayingTrack {
+        sender,
+        control,
+        track_id: track_id.clone(),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) enum PlayerCommand {
     Pause,
     Resume,
-    Play(String),
+    /// Play the file at `path`. `track_id` is set when the file belongs to a track in the library
+    Play {
+        path: String,
+        track_id: Option<String>,
+        /// Used to normalize the loudness of the track. Empty when it is not known
+        replay_gain: ReplayGain,
+    },
+    /// Play the file at `path` right after the current track, without a gap
+    PlayNext {
+        path: String,
+        track_id: Option<String>,
+        replay_gain: ReplayGain,
+    },
+    /// Seek to an absolute position, in seconds
+    Seek(f64),
+    /// Seek forward or backward (negative value) from the current position, in seconds
+    SeekBy(f64),
+    Volume(Volume),
+    /// Number of seconds two tracks overlap when changing track. 0 disables crossfading
+    Crossfade(f64),
+    /// Fade out over the given number of seconds and stop
+    Stop(f64),
+    /// Drop the track queued with `PlayNext`, so that playback stops after the current track
+    ClearNext,
+    /// Settings of the equalizer and the other DSP stages
+    Dsp(DspSettings),
+}
+
+/// The software volume of the server player
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Volume {
+    /// Volume level from 0 to 100
+    pub(crate) level: u8,
+    pub(crate) muted: bool,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self {
+            level: Self::MAX_LEVEL,
+            muted: false,
+        }
+    }
+}
+
+impl Volume {
+    /// The name of the setting the volume is persisted under
+    pub(crate) const SETTING_NAME: &'static str = "player_volume";
+    /// The name of the setting the volume of the clients is persisted under
+    pub(crate) const CLIENT_SETTING_NAME: &'static str = "client_volume";
+    pub(crate) const MAX_LEVEL: u8 = 100;
+
+    pub(crate) fn set_level(&mut self, level: u8) {
+        self.level = level.min(Self::MAX_LEVEL);
+    }
+
+    pub(crate) fn increase(&mut self, step: u8) {
+        self.set_level(self.level.saturating_add(step));
+    }
+
+    pub(crate) fn decrease(&mut self, step: u8) {
+        self.set_level(self.level.saturating_sub(step));
+    }
+
+    /// The linear gain for the current level. The level is mapped on a cubic
+    /// curve which is closer to how loudness is perceived than a straight line.
+    pub(crate) fn gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            (self.level as f32 / Self::MAX_LEVEL as f32).powi(3)
+        }
+    }
 }
 
 fn play_music(
     path: &str,
     receiver: std::sync::mpsc::Receiver<InternalPlayerCommands>,
     sync_sender: std::sync::mpsc::Sender<WebsocketMessage>,
+    state: &mut PlaybackState,
 ) {
     log::debug!(target: LOG_TARGET,"playing track: {}", path);
     let mut hint = Hint::new();
@@ -98,7 +430,14 @@ fn play_music(
         }
     }
 
-    let source = Box::new(File::open(path).unwrap());
+    let source = match File::open(path) {
+        Ok(file) => Box::new(file),
+        Err(err) => {
+            state.wait_until_live(&receiver);
+            state.fail(format!("could not open the file: {}", err), &sync_sender);
+            return;
+        }
+    };
 
     let mss = MediaSourceStream::new(source, Default::default());
 
@@ -119,27 +458,48 @@ fn play_music(
 
     let track = None;
 
-    if let Ok(probed) =
-        symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts)
-    {
-        // Playback mode.
-        // println!("{:?}", &mut probed);
-
-        // If present, parse the seek argument.
-        let seek_time = Some(0.0);
-
-        // Set the decoder options.
-        let decode_opts = DecoderOptions { verify: false };
+    match symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts) {
+        Ok(probed) => {
+            // Playback mode.
+            // println!("{:?}", &mut probed);
+
+            // If present, parse the seek argument.
+            let seek_time = Some(0.0);
+
+            // Set the decoder options.
+            let decode_opts = DecoderOptions { verify: false };
+
+            // Play it!
+            match play(
+                probed.format,
+                track,
+                seek_time,
+                &decode_opts,
+                &receiver,
+                &sync_sender,
+                state,
+            ) {
+                Ok(_) | Err(Error::Unsupported("stopped")) => (),
+                Err(err) => {
+                    state.wait_until_live(&receiver);
+                    state.fail(err.to_string(), &sync_sender)
+                }
+            }
+        }
+        Err(err) => {
+            state.wait_until_live(&receiver);
+            state.fail(format!("unsupported format: {}", err), &sync_sender)
+        }
+    }
 
-        // Play it!
-        _ = play(
-            probed.format,
-            track,
-            seek_time,
-            &decode_opts,
-            receiver,
-            sync_sender,
-        );
+    if !state.is_stopping() {
+        if state.source.control().is_live() {
+            state.shared.update(|state| {
+                state.status = PlaybackStatus::Stopped;
+                state.position = 0.0;
+            });
+        }
+        state.end_of_track.notify(&sync_sender);
     }
 }
 
@@ -149,13 +509,275 @@ struct PlayTrackOptions {
     seek_ts: u64,
 }
 
+/// State that outlives a single `play_track` call
+struct PlaybackState {
+    pause: bool,
+    gain: Gain,
+    /// Linear gain that brings the track to the reference loudness
+    normalization: f32,
+    /// The equalizer and the other DSP stages, run ahead of the limiter
+    dsp: DspChain,
+    limiter: Limiter,
+    fade: Option<Fade>,
+    after_fade: Option<AfterFade>,
+    /// Duration in seconds of the fade on pause and resume
+    fade_duration: f64,
+    /// Set once playback has been asked to stop
+    stopped: bool,
+    end_of_track: EndOfTrack,
+    shared: SharedPlayerState,
+    /// Where the decoded samples go to be mixed and played
+    source: SourceWriter,
+}
+
+/// Notifies the queue manager that the track has ended
+struct EndOfTrack {
+    path: String,
+    track_id: Option<String>,
+    sender: std::sync::mpsc::Sender<TrackEnded>,
+    /// Number of seconds before the end of the track the notification is sent.
+    /// This gives the next track time to crossfade in
+    ahead: f64,
+    notified: bool,
+    /// Number of seconds before the end of the track the next track is asked for,
+    /// so that it follows without a gap. 0 when crossfading
+    preload: f64,
+    upcoming: bool,
+}
+
+impl EndOfTrack {
+    fn new(
+        path: &str,
+        track_id: &Option<String>,
+        sender: std::sync::mpsc::Sender<TrackEnded>,
+        crossfade: f64,
+    ) -> Self {
+        Self {
+            path: path.to_string(),
+            track_id: track_id.clone(),
+            sender,
+            ahead: crossfade,
+            notified: false,
+            preload: if crossfade > 0.0 {
+                0.0
+            } else {
+                PRELOAD_SECONDS
+            },
+            upcoming: false,
+        }
+    }
+
+    /// Asks for the next track ahead of time
+    fn notify_upcoming(&mut self) {
+        if self.upcoming || self.notified {
+            return;
+        }
+
+        self.upcoming = true;
+        _ = self.sender.send(TrackEnded {
+            path: self.path.clone(),
+            track_id: self.track_id.clone(),
+            error: None,
+            upcoming: true,
+        });
+    }
+
+    fn notify(&mut self, sync_sender: &std::sync::mpsc::Sender<WebsocketMessage>) {
+        if self.notified {
+            return;
+        }
+
+        self.notified = true;
+        log::debug!(target: LOG_TARGET,"track ended: {}", &self.path);
+        _ = self.sender.send(TrackEnded {
+            path: self.path.clone(),
+            track_id: self.track_id.clone(),
+            error: None,
+            upcoming: false,
+        });
+        _ = sync_sender.send(WebsocketMessage::PlayerEvent {
+            event: PlayerEvent::TrackEnded {},
+        });
+    }
+
+    /// Reports that the track could not be played
+    fn fail(&mut self, reason: String, sync_sender: &std::sync::mpsc::Sender<WebsocketMessage>) {
+        self.notified = true;
+        log::error!(target: LOG_TARGET,"could not play {}: {}", &self.path, &reason);
+        _ = self.sender.send(TrackEnded {
+            path: self.path.clone(),
+            track_id: self.track_id.clone(),
+            error: Some(reason.clone()),
+            upcoming: false,
+        });
+        _ = sync_sender.send(WebsocketMessage::PlayerEvent {
+            event: PlayerEvent::Error {
+                track_id: self.track_id.clone(),
+                reason,
+            },
+        });
+    }
+}
+
+/// What to do once a fade out completes
+enum AfterFade {
+    Pause,
+    Stop,
+}
+
+impl PlaybackState {
+    fn new(
+        gain: f32,
+        normalization: f32,
+        fade_in: f64,
+        fade_duration: f64,
+        end_of_track: EndOfTrack,
+        shared: SharedPlayerState,
+        source: SourceWriter,
+    ) -> Self {
+        Self {
+            pause: false,
+            gain: Gain::new(gain * normalization),
+            normalization,
+            dsp: DspChain::new(source.spec()),
+            limiter: Limiter::new(),
+            fade: if fade_in > 0.0 {
+                Some(Fade::fade_in(0.0, fade_in))
+            } else {
+                None
+            },
+            after_fade: None,
+            fade_duration,
+            stopped: false,
+            end_of_track,
+            shared,
+            source,
+        }
+    }
+
+    fn fade_level(&self) -> f32 {
+        self.fade.map(|fade| fade.level()).unwrap_or(1.0)
+    }
+
+    fn is_stopping(&self) -> bool {
+        self.stopped
+    }
+
+    /// Stops the playback because of an error. Errors of a track that is
+    /// being replaced are not reported
+    fn fail(&mut self, reason: String, sync_sender: &std::sync::mpsc::Sender<WebsocketMessage>) {
+        if self.is_stopping() {
+            return;
+        }
+
+        self.stopped = true;
+        if self.source.control().is_live() {
+            self.shared.update(|state| {
+                state.status = PlaybackStatus::Stopped;
+                state.position = 0.0;
+            });
+        }
+        self.end_of_track.fail(reason, sync_sender);
+    }
+
+    /// Waits for a queued track to be played, so that it does not report an
+    /// error while the track before it is still playing
+    fn wait_until_live(&mut self, receiver: &std::sync::mpsc::Receiver<InternalPlayerCommands>) {
+        let control = self.source.control().clone();
+        while !control.is_live() && !control.is_dropped() && !self.is_stopping() {
+            match receiver.recv_timeout(std::time::Duration::from_millis(10)) {
+                Ok(InternalPlayerCommands::Stop) | Ok(InternalPlayerCommands::FadeOut(_)) => {
+                    self.stopped = true
+                }
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => self.stopped = true,
+                _ => (),
+            }
+        }
+    }
+
+    fn pause(&mut self) {
+        if self.pause || self.is_stopping() {
+            return;
+        }
+
+        if self.fade_duration > 0.0 {
+            self.fade = Some(Fade::fade_out(self.fade_level(), self.fade_duration));
+            self.after_fade = Some(AfterFade::Pause);
+        } else {
+            self.pause = true;
+        }
+    }
+
+    fn resume(&mut self) {
+        if self.is_stopping() {
+            return;
+        }
+
+        let level = self.fade_level();
+        self.pause = false;
+        self.after_fade = None;
+        self.fade = if self.fade_duration > 0.0 && level < 1.0 {
+            Some(Fade::fade_in(level, self.fade_duration))
+        } else {
+            None
+        };
+    }
+
+    /// Starts fading out before stopping. Returns false when playback should
+    /// stop right away
+    fn stop(&mut self, duration: f64) -> bool {
+        if self.stopped {
+            return true;
+        }
+        self.stopped = true;
+
+        if self.pause || duration <= 0.0 {
+            return false;
+        }
+
+        self.fade = Some(Fade::fade_out(self.fade_level(), duration));
+        self.after_fade = Some(AfterFade::Stop);
+
+        true
+    }
+
+    fn set_volume(&mut self, gain: f32) {
+        self.gain.set(gain * self.normalization);
+    }
+
+    /// Applies the gain, the DSP chain, the limiter and the fade in progress to the
+    /// buffer. Returns true once a fade out that ends the playback has completed
+    fn process(&mut self, buffer: &mut AudioBuffer<f32>) -> bool {
+        self.gain.apply(buffer);
+        self.dsp.process(buffer);
+        self.limiter.apply(buffer);
+
+        if let Some(fade) = &mut self.fade {
+            fade.apply(buffer);
+
+            if fade.is_done() {
+                match self.after_fade.take() {
+                    Some(AfterFade::Stop) => return true,
+                    // The silent fade is kept so that resuming fades in from silence
+                    Some(AfterFade::Pause) => self.pause = true,
+                    None if !fade.is_fading_out() => self.fade = None,
+                    None => (),
+                }
+            }
+        }
+
+        false
+    }
+}
+
 fn play(
     mut reader: Box<dyn FormatReader>,
     track_num: Option<usize>,
     seek_time: Option<f64>,
     decode_opts: &DecoderOptions,
-    receiver: std::sync::mpsc::Receiver<InternalPlayerCommands>,
-    sync_sender: std::sync::mpsc::Sender<WebsocketMessage>,
+    receiver: &std::sync::mpsc::Receiver<InternalPlayerCommands>,
+    sync_sender: &std::sync::mpsc::Sender<WebsocketMessage>,
+    state: &mut PlaybackState,
 ) -> Result<i32> {
     // If the user provided a track number, select that track if it exists, otherwise, select the
     // first track with a known codec.
@@ -165,7 +787,7 @@ fn play(
 
     let mut track_id = match track {
         Some(track) => track.id,
-        _ => return Ok(0),
+        _ => return Err(Error::Unsupported("no supported audio track")),
     };
 
     // If there is a seek time, seek the reader to the time specified and get the timestamp of the
@@ -175,47 +797,33 @@ fn play(
     // decoded and *samples* discarded up-to the exact *sample* indicated by required_ts. The
     // current approach will discard excess samples if seeking to a sample within a packet.
     let seek_ts = if let Some(time) = seek_time {
-        let seek_to = SeekTo::Time {
-            time: Time::from(time),
-            track_id: Some(track_id),
-        };
-
-        // Attempt the seek. If the seek fails, ignore the error and return a seek timestamp of 0 so
-        // that no samples are trimmed.
-        match reader.seek(SeekMode::Accurate, seek_to) {
-            Ok(seeked_to) => seeked_to.required_ts,
-            Err(Error::ResetRequired) => {
+        match seek_to_time(&mut reader, track_id, time) {
+            Ok(ts) => ts,
+            Err(_) => {
                 print_tracks(reader.tracks());
                 track_id = first_supported_track(reader.tracks()).unwrap().id;
                 0
             }
-            Err(err) => {
-                // Don't give-up on a seek error.
-                warn!("seek error: {}", err);
-                0
-            }
         }
     } else {
         // If not seeking, the seek timestamp is 0.
         0
     };
 
-    // The audio output device.
-    let mut audio_output = None;
-
     let mut track_info = PlayTrackOptions { track_id, seek_ts };
 
-    let mut pause = false;
+    // Every track is converted to the format of the output
+    let mut converter = Converter::new(state.source.spec());
 
     let result = loop {
         match play_track(
             &mut reader,
-            &mut audio_output,
+            &mut converter,
             track_info,
             decode_opts,
-            &receiver,
-            &mut pause,
-            &sync_sender,
+            receiver,
+            state,
+            sync_sender,
         ) {
             Err(Error::ResetRequired) => {
                 // The demuxer indicated that a reset is required. This is sometimes seen with
@@ -236,21 +844,48 @@ fn play(
         }
     };
 
-    // Flush the audio output to finish playing back any leftover samples.
-    if let Some(audio_output) = audio_output.as_mut() {
-        audio_output.flush()
+    // Play the samples left in the resampler
+    if result.is_ok() {
+        let buffer = converter.flush();
+        if buffer.frames() > 0 {
+            state.process(buffer);
+            _ = state.source.write(buffer);
+        }
     }
 
     result
 }
 
+/// Seeks the reader to `time` (in seconds) and returns the timestamp from which samples should
+/// be played again.
+///
+/// If the seek fails, the error is ignored and a seek timestamp of 0 is returned so that no
+/// samples are trimmed. `Error::ResetRequired` is returned as is so that the caller can select a
+/// new track.
+fn seek_to_time(reader: &mut Box<dyn FormatReader>, track_id: u32, time: f64) -> Result<u64> {
+    let seek_to = SeekTo::Time {
+        time: Time::from(time),
+        track_id: Some(track_id),
+    };
+
+    match reader.seek(SeekMode::Accurate, seek_to) {
+        Ok(seeked_to) => Ok(seeked_to.required_ts),
+        Err(Error::ResetRequired) => Err(Error::ResetRequired),
+        Err(err) => {
+            // Don't give-up on a seek error.
+            warn!("seek error: {}", err);
+            Ok(0)
+        }
+    }
+}
+
 fn play_track(
     reader: &mut Box<dyn FormatReader>,
-    audio_output: &mut Option<Box<dyn output::AudioOutput>>,
-    play_opts: PlayTrackOptions,
+    converter: &mut Converter,
+    mut play_opts: PlayTrackOptions,
     decode_opts: &DecoderOptions,
     receiver: &std::sync::mpsc::Receiver<InternalPlayerCommands>,
-    pause: &mut bool,
+    state: &mut PlaybackState,
     sync_sender: &std::sync::mpsc::Sender<WebsocketMessage>,
 ) -> Result<i32> {
     // Get the selected track using the track ID.
@@ -273,19 +908,71 @@ fn play_track(
         .n_frames
         .map(|frames| track.codec_params.start_ts + frames);
 
+    // The timestamp of the last packet sent to the audio output
+    let mut position_ts = play_opts.seek_ts;
+
+    // Decoded samples are converted to 32 bit floats before going through the gain stage
+    let mut sample_buf: Option<AudioBuffer<f32>> = None;
+
     // Decode and play the packets belonging to the selected track.
     let result = loop {
         // Jibao Loop here ....
 
         if let Ok(cmd) = receiver.try_recv() {
-            match cmd {
-                InternalPlayerCommands::Stop => break Err(Error::Unsupported("stopped")),
-                InternalPlayerCommands::Pause => *pause = true,
-                InternalPlayerCommands::Resume => *pause = false,
+            let seek_time = match cmd {
+                InternalPlayerCommands::Stop => {
+                    state.stop(0.0);
+                    break Err(Error::Unsupported("stopped"));
+                }
+                InternalPlayerCommands::FadeOut(duration) => {
+                    if !state.stop(duration) {
+                        break Err(Error::Unsupported("stopped"));
+                    }
+                    None
+                }
+                InternalPlayerCommands::Pause => {
+                    state.pause();
+                    None
+                }
+                InternalPlayerCommands::Resume => {
+                    state.resume();
+                    None
+                }
+                InternalPlayerCommands::Volume(value) => {
+                    state.set_volume(value);
+                    None
+                }
+                InternalPlayerCommands::Dsp(settings) => {
+                    state.dsp.configure(&settings);
+                    None
+                }
+                InternalPlayerCommands::Seek(time) => Some(time),
+                InternalPlayerCommands::SeekBy(offset) => {
+                    tb.map(|tb| ts_to_seconds(position_ts, tb) + offset)
+                }
+            };
+
+            if let Some(mut time) = seek_time {
+                time = time.max(0.0);
+                if let (Some(tb), Some(dur)) = (tb, dur) {
+                    time = time.min(ts_to_seconds(dur, tb));
+                }
+
+                match seek_to_time(reader, play_opts.track_id, time) {
+                    Ok(ts) => {
+                        play_opts.seek_ts = ts;
+                        position_ts = ts;
+                        decoder.reset();
+                        // What was decoded before the seek is not played
+                        state.source.control().flush();
+                    }
+                    Err(err) => break Err(err),
+                }
             }
         };
 
-        if *pause {
+        if state.pause {
+            std::thread::sleep(std::time::Duration::from_millis(10));
             continue;
         }
 
@@ -312,31 +999,52 @@ fn play_track(
         // Decode the packet into audio samples.
         match decoder.decode(&packet) {
             Ok(decoded) => {
-                // If the audio output is not open, try to open it.
-                if audio_output.is_none() {
-                    // Get the audio buffer specification. This is a description of the decoded
-                    // audio buffer's sample format and sample rate.
-                    let spec = *decoded.spec();
-
-                    // Get the capacity of the decoded buffer. Note that this is capacity, not
-                    // length! The capacity of the decoded buffer is constant for the life of the
-                    // decoder, but the length is not.
-                    let duration = decoded.capacity() as u64;
-
-                    // Try to open the audio output.
-                    #[cfg(feature = "server-play")]
-                    audio_output.replace(output::try_open(spec, duration).unwrap());
-                } else {
-                    // TODO: Check the audio spec. and duration hasn't changed.
-                }
-
                 // Write the decoded audio samples to the audio output if the presentation timestamp
                 // for the packet is >= the seeked position (0 if not seeking).
                 if packet.ts() >= play_opts.seek_ts {
-                    print_progress(packet.ts(), dur, tb, sync_sender);
+                    position_ts = packet.ts();
+
+                    // A track fading out is no longer the current track, and a queued
+                    // track is not played yet
+                    if !state.is_stopping() && state.source.control().is_live() {
+                        print_progress(packet.ts(), dur, tb, sync_sender);
+
+                        if let Some(tb) = tb {
+                            state.shared.update(|shared| {
+                                shared.position = ts_to_seconds(packet.ts(), tb);
+                                shared.duration =
+                                    dur.map(|dur| ts_to_seconds(dur, tb)).unwrap_or_default();
+                            });
+                        }
+
+                        if let (Some(tb), Some(dur)) = (tb, dur) {
+                            let remaining = ts_to_seconds(dur.saturating_sub(packet.ts()), tb);
+                            if remaining <= state.end_of_track.ahead {
+                                state.end_of_track.notify(sync_sender);
+                            } else if remaining <= state.end_of_track.preload {
+                                state.end_of_track.notify_upcoming();
+                            }
+                        }
+                    }
+
+                    let buffer = converter.convert(gain::to_f32_buffer(&decoded, &mut sample_buf));
+                    if buffer.frames() == 0 {
+                        continue;
+                    }
+                    let faded_out = state.process(buffer);
+
+                    if state.source.write(buffer).is_err() {
+                        // The mixer drops the sources that are cancelled, and all of them
+                        // when the audio output fails
+                        if state.source.control().is_cancelled() {
+                            state.stop(0.0);
+                            break Err(Error::Unsupported("stopped"));
+                        }
+                        break Err(Error::Unsupported("audio output"));
+                    }
 
-                    if let Some(audio_output) = audio_output {
-                        audio_output.write(decoded).unwrap()
+                    if faded_out {
+                        break Err(Error::Unsupported("stopped"));
                     }
                 }
             }
@@ -573,6 +1281,12 @@ fn print_tag_item(idx: usize, key: &str, value: &Value, indent: usize) -> String
     out
 }
 
+fn ts_to_seconds(ts: u64, tb: TimeBase) -> f64 {
+    let time = tb.calc_time(ts);
+
+    time.seconds as f64 + time.frac
+}
+
 fn fmt_time(ts: u64, tb: TimeBase) -> String {
     let time = tb.calc_time(ts);
 
@@ -0,0 +1,287 @@
+use symphonia::core::audio::{AsAudioBufferRef, AudioBuffer, Channels, Signal, SignalSpec};
+
+use crate::resampler::PlanarResampler;
+
+/// Number of frames the resampler works on at once
+const RESAMPLER_FRAMES: u64 = 1024;
+/// Level of a channel folded into one of the front channels, -3dB
+const FOLD_WEIGHT: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// The input channels each output channel is made of, and their level
+type Matrix = Vec<Vec<(usize, f32)>>;
+
+/// Converts decoded audio to the sample rate and the channels of the output,
+/// so that every track plays through the same output without reopening it
+pub(crate) struct Converter {
+    spec: SignalSpec,
+    /// How each output channel is made from the input channels, and the input
+    /// channels it was made for
+    remix: Option<(Channels, Matrix)>,
+    remixed: AudioBuffer<f32>,
+    /// The resampler and the sample rate it converts from
+    resampler: Option<(u32, PlanarResampler)>,
+    output: AudioBuffer<f32>,
+}
+
+impl Converter {
+    pub(crate) fn new(spec: SignalSpec) -> Self {
+        Self {
+            spec,
+            remix: None,
+            remixed: AudioBuffer::unused(),
+            resampler: None,
+            output: AudioBuffer::unused(),
+        }
+    }
+
+    /// Converts the buffer. The result can be empty while the resampler waits for more samples
+    pub(crate) fn convert(&mut self, buffer: &AudioBuffer<f32>) -> &mut AudioBuffer<f32> {
+        let input = *buffer.spec();
+        let remixed_spec = SignalSpec::new(input.rate, self.spec.channels);
+
+        if self.remix.as_ref().map(|(channels, _)| *channels) != Some(input.channels) {
+            self.remix = Some((
+                input.channels,
+                remix_matrix(input.channels, self.spec.channels),
+            ));
+        }
+
+        prepare(&mut self.remixed, remixed_spec, buffer.frames());
+        if let Some((_, matrix)) = &self.remix {
+            for (channel, sources) in matrix.iter().enumerate() {
+                let remixed = self.remixed.chan_mut(channel);
+                for (source, weight) in sources {
+                    for (sample, input) in remixed.iter_mut().zip(buffer.chan(*source)) {
+                        *sample += input * weight;
+                    }
+                }
+            }
+        }
+
+        if input.rate == self.spec.rate {
+            self.resampler = None;
+            return &mut self.remixed;
+        }
+
+        if self.resampler.as_ref().map(|(rate, _)| *rate) != Some(input.rate) {
+            self.resampler = Some((
+                input.rate,
+                PlanarResampler::new(remixed_spec, self.spec.rate as usize, RESAMPLER_FRAMES),
+            ));
+        }
+
+        let (_, resampler) = self.resampler.as_mut().unwrap();
+        let resampled = resampler.resample(self.remixed.as_audio_buffer_ref());
+        copy_planar(&mut self.output, self.spec, resampled);
+
+        &mut self.output
+    }
+
+    /// Returns the samples left in the resampler at the end of a track
+    pub(crate) fn flush(&mut self) -> &mut AudioBuffer<f32> {
+        match self.resampler.take() {
+            Some((_, mut resampler)) => copy_planar(&mut self.output, self.spec, resampler.flush()),
+            None => prepare(&mut self.output, self.spec, 0),
+        }
+
+        &mut self.output
+    }
+}
+
+/// The spec of the output for the sample rate and number of channels. The
+/// channels are taken in the usual order: front left, front right, centre, LFE...
+pub(crate) fn output_spec(rate: u32, channels: usize) -> SignalSpec {
+    let channels = Channels::from_bits_truncate((1 << channels.clamp(1, 8)) - 1);
+    SignalSpec::new(rate, channels)
+}
+
+/// Makes the buffer hold `frames` silent frames of the spec
+fn prepare(buffer: &mut AudioBuffer<f32>, spec: SignalSpec, frames: usize) {
+    if *buffer.spec() != spec || buffer.capacity() < frames {
+        *buffer = AudioBuffer::new(frames.max(1) as u64, spec);
+    }
+
+    buffer.clear();
+    buffer.render_silence(Some(frames));
+}
+
+fn copy_planar(buffer: &mut AudioBuffer<f32>, spec: SignalSpec, planar: &[Vec<f32>]) {
+    let frames = planar
+        .first()
+        .map(|channel| channel.len())
+        .unwrap_or_default();
+    prepare(buffer, spec, frames);
+
+    for (channel, samples) in planar.iter().enumerate() {
+        buffer.chan_mut(channel).copy_from_slice(samples);
+    }
+}
+
+/// How each output channel is made from the input channels.
+///
+/// Channels the output has are copied. The others are folded into the front
+/// left and right channels, and the LFE is left out. A mono output gets the
+/// average of the input channels and a mono input plays on both front channels.
+fn remix_matrix(input: Channels, output: Channels) -> Matrix {
+    let inputs = input.iter().collect::<Vec<Channels>>();
+    let outputs = output.iter().collect::<Vec<Channels>>();
+    let mut matrix = vec![Vec::new(); outputs.len()];
+
+    let lfe = Channels::LFE1 | Channels::LFE2;
+    let front = Channels::FRONT_LEFT | Channels::FRONT_RIGHT;
+    let left = Channels::REAR_LEFT
+        | Channels::SIDE_LEFT
+        | Channels::FRONT_LEFT_CENTRE
+        | Channels::REAR_LEFT_CENTRE
+        | Channels::FRONT_LEFT_WIDE
+        | Channels::FRONT_LEFT_HIGH
+        | Channels::TOP_FRONT_LEFT
+        | Channels::TOP_REAR_LEFT;
+    let right = Channels::REAR_RIGHT
+        | Channels::SIDE_RIGHT
+        | Channels::FRONT_RIGHT_CENTRE
+        | Channels::REAR_RIGHT_CENTRE
+        | Channels::FRONT_RIGHT_WIDE
+        | Channels::FRONT_RIGHT_HIGH
+        | Channels::TOP_FRONT_RIGHT
+        | Channels::TOP_REAR_RIGHT;
+
+    if outputs.len() == 1 {
+        let sources = (0..inputs.len())
+            .filter(|index| !lfe.contains(inputs[*index]))
+            .collect::<Vec<usize>>();
+        let weight = 1.0 / sources.len().max(1) as f32;
+        matrix[0] = sources.into_iter().map(|index| (index, weight)).collect();
+        return matrix;
+    }
+
+    if inputs.len() == 1 {
+        for (index, channel) in outputs.iter().enumerate() {
+            if front.contains(*channel) {
+                matrix[index].push((0, 1.0));
+            }
+        }
+        return matrix;
+    }
+
+    let position = |channel: Channels| outputs.iter().position(|output| *output == channel);
+    for (index, channel) in inputs.iter().enumerate() {
+        if let Some(output) = position(*channel) {
+            matrix[output].push((index, 1.0));
+            continue;
+        }
+
+        let targets = if left.contains(*channel) {
+            Channels::FRONT_LEFT
+        } else if right.contains(*channel) {
+            Channels::FRONT_RIGHT
+        } else if lfe.contains(*channel) {
+            Channels::empty()
+        } else {
+            front
+        };
+        for target in targets.iter() {
+            if let Some(output) = position(target) {
+                matrix[output].push((index, FOLD_WEIGHT));
+            }
+        }
+    }
+
+    // Folded channels add up. Their sum is scaled back so that it does not clip
+    for sources in matrix.iter_mut() {
+        let total = sources.iter().map(|(_, weight)| weight).sum::<f32>();
+        if total > 1.0 {
+            for (_, weight) in sources.iter_mut() {
+                *weight /= total;
+            }
+        }
+    }
+
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use symphonia::core::audio::{Channels, Signal, SignalSpec};
+
+    use crate::testing::{audio_buffer, sine};
+
+    use super::{output_spec, remix_matrix, Converter, FOLD_WEIGHT};
+
+    const STEREO: Channels = Channels::FRONT_LEFT.union(Channels::FRONT_RIGHT);
+
+    #[test]
+    fn copies_the_channels_the_output_has() {
+        assert_eq!(
+            remix_matrix(STEREO, STEREO),
+            vec![vec![(0, 1.0)], vec![(1, 1.0)]]
+        );
+        assert_eq!(
+            remix_matrix(Channels::FRONT_LEFT, STEREO),
+            vec![vec![(0, 1.0)], vec![(0, 1.0)]]
+        );
+        assert_eq!(
+            remix_matrix(STEREO, Channels::FRONT_LEFT),
+            vec![vec![(0, 0.5), (1, 0.5)]]
+        );
+    }
+
+    #[test]
+    fn folds_surround_into_the_front_channels() {
+        // 5.1: front left, front right, centre, LFE, rear left, rear right
+        let surround = STEREO
+            | Channels::FRONT_CENTRE
+            | Channels::LFE1
+            | Channels::REAR_LEFT
+            | Channels::REAR_RIGHT;
+        let matrix = remix_matrix(surround, STEREO);
+
+        let total = 1.0 + 2.0 * FOLD_WEIGHT;
+        assert_eq!(
+            matrix[0],
+            vec![
+                (0, 1.0 / total),
+                (2, FOLD_WEIGHT / total),
+                (4, FOLD_WEIGHT / total)
+            ]
+        );
+        assert_eq!(
+            matrix[1],
+            vec![
+                (1, 1.0 / total),
+                (2, FOLD_WEIGHT / total),
+                (5, FOLD_WEIGHT / total)
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_frames_at_the_same_rate() {
+        let mut converter = Converter::new(output_spec(44100, 2));
+        let samples = sine(44100, 1000, 440.0, 0.5);
+        let converted = converter.convert(&audio_buffer(44100, std::slice::from_ref(&samples)));
+
+        assert_eq!(converted.frames(), 1000);
+        assert_eq!(converted.chan(0), samples.as_slice());
+        assert_eq!(converted.chan(1), samples.as_slice());
+        assert_eq!(converter.flush().frames(), 0);
+    }
+
+    #[test]
+    fn resamples_to_the_exact_length() {
+        let spec = output_spec(48000, 2);
+        let mut converter = Converter::new(spec);
+        let samples = sine(44100, 44100, 440.0, 0.5);
+
+        let mut frames = 0;
+        for block in samples.chunks(1000) {
+            let converted = converter.convert(&audio_buffer(44100, &[block.to_vec()]));
+            assert_eq!(*converted.spec(), spec);
+            frames += converted.frames();
+        }
+        frames += converter.flush().frames();
+
+        assert_eq!(frames, 48000);
+        assert_eq!(*converter.flush().spec(), SignalSpec::new(48000, STEREO));
+    }
+}
@@ -0,0 +1,279 @@
+use symphonia::core::audio::{AudioBuffer, Signal, SignalSpec};
+
+use super::equalizer::{BassBoost, Equalizer, Preamp, EQUALIZER_BANDS};
+
+/// A stage of the DSP chain the player runs every decoded buffer through
+pub(crate) trait DspStage: Send {
+    /// Takes new settings. Stages keep their filter state so that the change does not click
+    fn configure(&mut self, settings: &DspSettings);
+
+    fn process(&mut self, buffer: &mut AudioBuffer<f32>);
+}
+
+/// The DSP settings of the server player
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub(crate) struct DspSettings {
+    pub(crate) enabled: bool,
+    /// Gain applied before the equalizer, in dB. Lowering it leaves room for boosted bands
+    pub(crate) preamp: f32,
+    /// Gain of each equalizer band, in dB
+    pub(crate) bands: [f32; EQUALIZER_BANDS],
+    /// Name of the preset the bands come from. Not set when they were set one by one
+    pub(crate) preset: Option<String>,
+    /// Gain of the low shelf lifting the bass, in dB
+    pub(crate) bass_boost: f32,
+}
+
+impl Default for DspSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            preamp: 0.0,
+            bands: [0.0; EQUALIZER_BANDS],
+            preset: Some("flat".to_string()),
+            bass_boost: 0.0,
+        }
+    }
+}
+
+impl DspSettings {
+    /// The name of the setting the DSP settings are persisted under
+    pub(crate) const SETTING_NAME: &'static str = "player_dsp";
+    /// Largest cut or boost of any stage, in dB
+    pub(crate) const MAX_GAIN: f32 = 12.0;
+
+    /// Keeps every gain within the supported range
+    pub(crate) fn clamp(&mut self) {
+        self.preamp = self.preamp.clamp(-Self::MAX_GAIN, Self::MAX_GAIN);
+        self.bass_boost = self.bass_boost.clamp(0.0, Self::MAX_GAIN);
+        for band in self.bands.iter_mut() {
+            *band = band.clamp(-Self::MAX_GAIN, Self::MAX_GAIN);
+        }
+    }
+
+    /// True when the chain leaves the audio as it is
+    pub(crate) fn is_flat(&self) -> bool {
+        !self.enabled
+            || (self.preamp == 0.0
+                && self.bass_boost == 0.0
+                && self.bands.iter().all(|band| *band == 0.0))
+    }
+}
+
+/// Runs the decoded audio through the DSP stages, in the format of the output
+pub(crate) struct DspChain {
+    stages: Vec<Box<dyn DspStage>>,
+    flat: bool,
+}
+
+impl DspChain {
+    pub(crate) fn new(spec: SignalSpec) -> Self {
+        let settings = DspSettings::default();
+        let mut chain = Self {
+            stages: vec![
+                Box::new(Preamp::new()),
+                Box::new(Equalizer::new(spec)),
+                Box::new(BassBoost::new(spec)),
+            ],
+            flat: true,
+        };
+        chain.configure(&settings);
+
+        chain
+    }
+
+    pub(crate) fn configure(&mut self, settings: &DspSettings) {
+        self.flat = settings.is_flat();
+        for stage in self.stages.iter_mut() {
+            stage.configure(settings);
+        }
+    }
+
+    pub(crate) fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
+        if self.flat || buffer.frames() == 0 {
+            return;
+        }
+
+        for stage in self.stages.iter_mut() {
+            stage.process(buffer);
+        }
+    }
+}
+
+/// A second order IIR filter with one state per channel, designed after the
+/// Audio EQ Cookbook by Robert Bristow-Johnson
+#[derive(Debug, Clone)]
+pub(crate) struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    /// State of each channel
+    z: Vec<[f32; 2]>,
+}
+
+impl Biquad {
+    pub(crate) fn new(channels: usize) -> Self {
+        Self {
+            b: [1.0, 0.0, 0.0],
+            a: [0.0, 0.0],
+            z: vec![[0.0; 2]; channels],
+        }
+    }
+
+    /// Boosts or cuts the frequencies around `frequency`
+    pub(crate) fn set_peaking(&mut self, rate: u32, frequency: f32, q: f32, gain: f32) {
+        let a = 10_f32.powf(gain / 40.0);
+        let w0 = std::f32::consts::TAU * frequency / rate as f32;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+
+        self.set(
+            [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+            [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+        );
+    }
+
+    /// Boosts or cuts the frequencies under `frequency`
+    pub(crate) fn set_low_shelf(&mut self, rate: u32, frequency: f32, gain: f32) {
+        let a = 10_f32.powf(gain / 40.0);
+        let w0 = std::f32::consts::TAU * frequency / rate as f32;
+        let cos = w0.cos();
+        // A shelf slope of 1, as steep as it gets without overshooting
+        let beta = 2.0 * a.sqrt() * w0.sin() / std::f32::consts::SQRT_2;
+
+        self.set(
+            [
+                a * ((a + 1.0) - (a - 1.0) * cos + beta),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            ],
+            [
+                (a + 1.0) + (a - 1.0) * cos + beta,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - beta,
+            ],
+        );
+    }
+
+    /// Forgets the past samples, for a filter that was not used for a while
+    pub(crate) fn reset(&mut self) {
+        self.z.iter_mut().for_each(|z| *z = [0.0; 2]);
+    }
+
+    fn set(&mut self, b: [f32; 3], a: [f32; 3]) {
+        self.b = [b[0] / a[0], b[1] / a[0], b[2] / a[0]];
+        self.a = [a[1] / a[0], a[2] / a[0]];
+    }
+
+    pub(crate) fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
+        let channels = buffer.spec().channels.count().min(self.z.len());
+
+        for channel in 0..channels {
+            let z = &mut self.z[channel];
+            for sample in buffer.chan_mut(channel).iter_mut() {
+                let input = *sample;
+                let output = self.b[0] * input + z[0];
+                z[0] = self.b[1] * input - self.a[0] * output + z[1];
+                z[1] = self.b[2] * input - self.a[1] * output;
+                *sample = output;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use symphonia::core::audio::Signal;
+
+    use crate::{
+        player::equalizer::{equalizer_preset, Equalizer, Preamp, EQUALIZER_FREQUENCIES},
+        testing::{audio_buffer, planes, sine},
+    };
+
+    use super::{DspChain, DspSettings, DspStage};
+
+    const RATE: u32 = 44100;
+
+    fn settings(preset: &str) -> DspSettings {
+        DspSettings {
+            bands: equalizer_preset(preset).unwrap(),
+            preset: Some(preset.to_string()),
+            ..DspSettings::default()
+        }
+    }
+
+    /// Root mean square of the second half of the samples, once the filters settled
+    fn rms(samples: &[f32]) -> f32 {
+        let tail = &samples[samples.len() / 2..];
+        (tail.iter().map(|sample| sample * sample).sum::<f32>() / tail.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn flat_preset_leaves_the_signal_unchanged() {
+        let samples = sine(RATE, 4096, 1000.0, 0.5);
+        let original = audio_buffer(RATE, &[samples.clone(), samples]);
+        let flat = settings("flat");
+        assert!(flat.is_flat());
+
+        let mut chain = DspChain::new(*original.spec());
+        chain.configure(&flat);
+        let mut buffer = original.clone();
+        chain.process(&mut buffer);
+        assert_eq!(planes(&buffer), planes(&original));
+
+        // The stages themselves leave the signal alone too, not only the chain
+        let mut stages: Vec<Box<dyn DspStage>> = vec![
+            Box::new(Preamp::new()),
+            Box::new(Equalizer::new(*original.spec())),
+        ];
+        let mut buffer = original.clone();
+        for stage in stages.iter_mut() {
+            stage.configure(&flat);
+            stage.process(&mut buffer);
+        }
+        assert_eq!(planes(&buffer), planes(&original));
+    }
+
+    #[test]
+    fn back_to_flat_leaves_the_signal_unchanged() {
+        let samples = sine(RATE, 4096, 1000.0, 0.5);
+        let original = audio_buffer(RATE, &[samples.clone(), samples]);
+
+        let mut chain = DspChain::new(*original.spec());
+        chain.configure(&settings("rock"));
+        chain.process(&mut original.clone());
+        chain.configure(&settings("flat"));
+
+        let mut buffer = original.clone();
+        chain.process(&mut buffer);
+        assert_eq!(planes(&buffer), planes(&original));
+    }
+
+    #[test]
+    fn boosted_band_raises_its_frequency() {
+        let band = EQUALIZER_FREQUENCIES
+            .iter()
+            .position(|f| *f == 1000.0)
+            .unwrap();
+        let mut boost = settings("flat");
+        boost.preset = None;
+        boost.bands[band] = 6.0;
+
+        let samples = sine(RATE, RATE as usize, 1000.0, 0.25);
+        let mut buffer = audio_buffer(RATE, std::slice::from_ref(&samples));
+        let mut chain = DspChain::new(*buffer.spec());
+        chain.configure(&boost);
+        chain.process(&mut buffer);
+
+        let gain = 20.0 * (rms(buffer.chan(0)) / rms(&samples)).log10();
+        assert!((gain - 6.0).abs() < 0.1, "gain of {gain} dB");
+    }
+
+    #[test]
+    fn disabled_chain_is_flat() {
+        let settings = DspSettings {
+            enabled: false,
+            ..settings("loudness")
+        };
+        assert!(settings.is_flat());
+    }
+}
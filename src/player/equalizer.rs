@@ -0,0 +1,153 @@
+use symphonia::core::audio::{AudioBuffer, Signal, SignalSpec};
+
+use super::dsp::{Biquad, DspSettings, DspStage};
+
+pub(crate) const EQUALIZER_BANDS: usize = 10;
+
+/// Centre frequency of each band, an octave apart
+pub(crate) const EQUALIZER_FREQUENCIES: [f32; EQUALIZER_BANDS] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+/// Width of the bands. About an octave, so that neighbouring bands blend smoothly
+const BAND_Q: f32 = 1.41;
+/// Frequency under which the bass boost lifts the bass
+const BASS_BOOST_FREQUENCY: f32 = 100.0;
+
+/// Band gains, in dB, for the usual kinds of music and rooms. "loudness" makes
+/// up for the bass and treble the ear loses when the music is played quietly
+pub(crate) const EQUALIZER_PRESETS: [(&str, [f32; EQUALIZER_BANDS]); 10] = [
+    ("flat", [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+    ("bass", [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+    ("treble", [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 5.0, 6.0]),
+    (
+        "vocal",
+        [-2.0, -2.0, -1.0, 1.0, 3.0, 4.0, 3.0, 1.0, 0.0, -1.0],
+    ),
+    ("rock", [5.0, 4.0, 3.0, 1.0, -1.0, -1.0, 1.0, 3.0, 4.0, 5.0]),
+    ("pop", [-1.0, 1.0, 3.0, 4.0, 3.0, 0.0, -1.0, -1.0, 1.0, 2.0]),
+    (
+        "dance",
+        [6.0, 5.0, 2.0, 0.0, 0.0, -2.0, -1.0, 0.0, 3.0, 4.0],
+    ),
+    ("jazz", [3.0, 2.0, 1.0, 2.0, -1.0, -1.0, 0.0, 1.0, 2.0, 3.0]),
+    (
+        "classical",
+        [4.0, 3.0, 2.0, 1.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0],
+    ),
+    (
+        "loudness",
+        [6.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 1.0, 3.0, 4.0],
+    ),
+];
+
+/// The band gains of the preset
+pub(crate) fn equalizer_preset(name: &str) -> Option<[f32; EQUALIZER_BANDS]> {
+    let name = name.trim().to_lowercase();
+    EQUALIZER_PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .map(|(_, bands)| *bands)
+}
+
+/// A gain applied ahead of the other stages
+pub(crate) struct Preamp {
+    gain: f32,
+}
+
+impl Preamp {
+    pub(crate) fn new() -> Self {
+        Self { gain: 1.0 }
+    }
+}
+
+impl DspStage for Preamp {
+    fn configure(&mut self, settings: &DspSettings) {
+        self.gain = 10_f32.powf(settings.preamp / 20.0);
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
+        if self.gain != 1.0 {
+            let gain = self.gain;
+            buffer.transform(|sample| sample * gain);
+        }
+    }
+}
+
+/// A graphic equalizer with one peaking filter per band
+pub(crate) struct Equalizer {
+    rate: u32,
+    /// The filter of each band, and whether the band changes the sound
+    bands: Vec<(Biquad, bool)>,
+}
+
+impl Equalizer {
+    pub(crate) fn new(spec: SignalSpec) -> Self {
+        Self {
+            rate: spec.rate,
+            bands: vec![(Biquad::new(spec.channels.count()), false); EQUALIZER_BANDS],
+        }
+    }
+}
+
+impl DspStage for Equalizer {
+    fn configure(&mut self, settings: &DspSettings) {
+        let nyquist = self.rate as f32 / 2.0;
+        for (index, (filter, active)) in self.bands.iter_mut().enumerate() {
+            let frequency = EQUALIZER_FREQUENCIES[index];
+            let gain = settings.bands[index];
+            let was_active = *active;
+            // Bands too close to the Nyquist frequency cannot be filtered at this sample rate
+            *active = gain != 0.0 && frequency < nyquist * 0.9;
+            if *active {
+                if !was_active {
+                    filter.reset();
+                }
+                filter.set_peaking(self.rate, frequency, BAND_Q, gain);
+            }
+        }
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
+        for (filter, _) in self.bands.iter_mut().filter(|(_, active)| *active) {
+            filter.process(buffer);
+        }
+    }
+}
+
+/// A low shelf that lifts the bass, for speakers that lack it
+pub(crate) struct BassBoost {
+    rate: u32,
+    filter: Biquad,
+    active: bool,
+}
+
+impl BassBoost {
+    pub(crate) fn new(spec: SignalSpec) -> Self {
+        Self {
+            rate: spec.rate,
+            filter: Biquad::new(spec.channels.count()),
+            active: false,
+        }
+    }
+}
+
+impl DspStage for BassBoost {
+    fn configure(&mut self, settings: &DspSettings) {
+        let was_active = self.active;
+        self.active = settings.bass_boost > 0.0;
+        if self.active {
+            if !was_active {
+                self.filter.reset();
+            }
+            self.filter
+                .set_low_shelf(self.rate, BASS_BOOST_FREQUENCY, settings.bass_boost);
+        }
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
+        if self.active {
+            self.filter.process(buffer);
+        }
+    }
+}
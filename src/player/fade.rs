@@ -0,0 +1,121 @@
+use symphonia::core::audio::{AudioBuffer, Signal};
+
+/// A linear volume ramp from one level to another over a duration.
+///
+/// The number of frames the fade lasts is only known once the first buffer,
+/// and with it the sample rate, goes through the fade.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Fade {
+    from: f32,
+    to: f32,
+    duration: f64,
+    total_frames: u64,
+    elapsed_frames: u64,
+}
+
+impl Fade {
+    pub(crate) fn new(from: f32, to: f32, duration: f64) -> Self {
+        Self {
+            from,
+            to,
+            duration: duration.max(0.0),
+            total_frames: 0,
+            elapsed_frames: 0,
+        }
+    }
+
+    pub(crate) fn fade_in(from: f32, duration: f64) -> Self {
+        Self::new(from, 1.0, duration)
+    }
+
+    pub(crate) fn fade_out(from: f32, duration: f64) -> Self {
+        Self::new(from, 0.0, duration)
+    }
+
+    /// The level the fade is currently at
+    pub(crate) fn level(&self) -> f32 {
+        if self.is_done() {
+            self.to
+        } else if self.total_frames == 0 {
+            self.from
+        } else {
+            let progress = self.elapsed_frames as f32 / self.total_frames as f32;
+            self.from + (self.to - self.from) * progress
+        }
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.duration == 0.0 || (self.total_frames > 0 && self.elapsed_frames >= self.total_frames)
+    }
+
+    pub(crate) fn is_fading_out(&self) -> bool {
+        self.to < self.from
+    }
+
+    pub(crate) fn apply(&mut self, buffer: &mut AudioBuffer<f32>) {
+        if self.total_frames == 0 {
+            self.total_frames = ((self.duration * buffer.spec().rate as f64) as u64).max(1);
+        }
+
+        let frames = buffer.frames();
+        let start = self.elapsed_frames;
+        let step = (self.to - self.from) / self.total_frames as f32;
+
+        for channel in 0..buffer.spec().channels.count() {
+            for (index, sample) in buffer.chan_mut(channel).iter_mut().enumerate() {
+                let elapsed = (start + index as u64 + 1).min(self.total_frames);
+                *sample *= self.from + step * elapsed as f32;
+            }
+        }
+
+        self.elapsed_frames = (start + frames as u64).min(self.total_frames);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{audio_buffer, planes};
+
+    use super::Fade;
+
+    const RATE: u32 = 1000;
+
+    #[test]
+    fn fade_out_reaches_silence() {
+        let mut fade = Fade::fade_out(1.0, 0.5);
+        let mut buffer = audio_buffer(RATE, &[vec![1.0; 800], vec![1.0; 800]]);
+        fade.apply(&mut buffer);
+
+        for plane in planes(&buffer) {
+            assert!(plane.windows(2).all(|pair| pair[1] <= pair[0]));
+            assert!(plane[499..].iter().all(|sample| *sample == 0.0));
+        }
+        assert!(fade.is_done());
+        assert_eq!(fade.level(), 0.0);
+    }
+
+    #[test]
+    fn fade_in_reaches_full_level_across_buffers() {
+        let mut fade = Fade::fade_in(0.0, 0.1);
+        let mut samples = Vec::new();
+        for _ in 0..3 {
+            let mut buffer = audio_buffer(RATE, &[vec![1.0; 40]]);
+            fade.apply(&mut buffer);
+            samples.extend(planes(&buffer).remove(0));
+        }
+
+        assert!(samples.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert!((samples[99] - 1.0).abs() < 1e-6);
+        assert!(samples[100..].iter().all(|sample| *sample == 1.0));
+        assert!(fade.is_done());
+        assert_eq!(fade.level(), 1.0);
+    }
+
+    #[test]
+    fn fade_without_duration_is_done_right_away() {
+        let fade = Fade::new(0.2, 0.8, 0.0);
+
+        assert!(fade.is_done());
+        assert_eq!(fade.level(), 0.8);
+    }
+}
@@ -0,0 +1,75 @@
+use symphonia::core::audio::{AudioBuffer, AudioBufferRef, Signal};
+
+/// A software gain stage applied to the decoded samples before they are written
+/// to the audio output.
+///
+/// Changes to the gain are ramped over the length of the next buffer to avoid
+/// audible clicks.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Gain {
+    current: f32,
+    target: f32,
+}
+
+impl Gain {
+    pub(crate) fn new(gain: f32) -> Self {
+        Self {
+            current: gain,
+            target: gain,
+        }
+    }
+
+    pub(crate) fn set(&mut self, gain: f32) {
+        self.target = gain;
+    }
+
+    pub(crate) fn get(&self) -> f32 {
+        self.target
+    }
+
+    pub(crate) fn apply(&mut self, buffer: &mut AudioBuffer<f32>) {
+        let frames = buffer.frames();
+
+        if frames == 0 {
+            return;
+        }
+
+        if self.current == self.target {
+            if self.target != 1.0 {
+                let gain = self.target;
+                buffer.transform(|sample| sample * gain);
+            }
+            return;
+        }
+
+        let step = (self.target - self.current) / frames as f32;
+        for channel in 0..buffer.spec().channels.count() {
+            for (index, sample) in buffer.chan_mut(channel).iter_mut().enumerate() {
+                *sample *= self.current + step * (index + 1) as f32;
+            }
+        }
+
+        self.current = self.target;
+    }
+}
+
+/// Converts the decoded buffer into a 32 bit float buffer, re-using the
+/// previously allocated buffer when possible.
+pub(crate) fn to_f32_buffer<'a>(
+    decoded: &AudioBufferRef<'_>,
+    buffer: &'a mut Option<AudioBuffer<f32>>,
+) -> &'a mut AudioBuffer<f32> {
+    let reusable = buffer
+        .as_ref()
+        .map(|b| b.spec() == decoded.spec() && b.capacity() >= decoded.capacity())
+        .unwrap_or_default();
+
+    if !reusable {
+        buffer.replace(decoded.make_equivalent::<f32>());
+    }
+
+    let the_buffer = buffer.as_mut().unwrap();
+    decoded.convert(the_buffer);
+
+    the_buffer
+}
@@ -0,0 +1,125 @@
+use symphonia::core::audio::{AudioBuffer, Signal};
+
+/// Keeps the samples under full scale once the gain stages have been applied.
+///
+/// The gain drops as soon as a frame would go over the threshold and recovers
+/// slowly afterwards, which keeps the limiting inaudible on short peaks.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Limiter {
+    threshold: f32,
+    release: f32,
+    gain: f32,
+}
+
+impl Limiter {
+    /// About -0.2 dBFS
+    const THRESHOLD: f32 = 0.977;
+    /// Seconds it takes the gain to mostly recover after a peak
+    const RELEASE: f32 = 0.1;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            threshold: Self::THRESHOLD,
+            release: 0.0,
+            gain: 1.0,
+        }
+    }
+
+    pub(crate) fn apply(&mut self, buffer: &mut AudioBuffer<f32>) {
+        let frames = buffer.frames();
+        let channels = buffer.spec().channels.count();
+
+        if frames == 0 {
+            return;
+        }
+
+        // Nothing to do when the whole buffer is under the threshold and the gain has recovered
+        if self.gain >= 1.0
+            && (0..channels).all(|channel| {
+                buffer
+                    .chan(channel)
+                    .iter()
+                    .all(|sample| sample.abs() <= self.threshold)
+            })
+        {
+            return;
+        }
+
+        if self.release == 0.0 {
+            let rate = buffer.spec().rate as f32;
+            self.release = 1.0 - (-1.0 / (Self::RELEASE * rate)).exp();
+        }
+
+        let mut planes = buffer.planes_mut();
+        let planes = planes.planes();
+        for frame in 0..frames {
+            let peak = planes
+                .iter()
+                .map(|plane| plane[frame].abs())
+                .fold(0.0, f32::max);
+
+            // The gain only recovers as far as the frame allows
+            let recovered = (self.gain + (1.0 - self.gain) * self.release).min(1.0);
+            self.gain = if peak * recovered > self.threshold {
+                self.threshold / peak
+            } else {
+                recovered
+            };
+
+            if self.gain < 1.0 {
+                for plane in planes.iter_mut() {
+                    plane[frame] *= self.gain;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{audio_buffer, planes, sine};
+
+    use super::Limiter;
+
+    const RATE: u32 = 44100;
+
+    #[test]
+    fn never_goes_over_the_threshold() {
+        let mut limiter = Limiter::new();
+        let loud = sine(RATE, 4096, 440.0, 2.0);
+
+        for block in loud.chunks(1024) {
+            let mut buffer = audio_buffer(RATE, &[block.to_vec(), block.to_vec()]);
+            limiter.apply(&mut buffer);
+
+            for plane in planes(&buffer) {
+                assert!(plane
+                    .iter()
+                    .all(|sample| sample.abs() <= Limiter::THRESHOLD + 1e-6));
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_quiet_samples_untouched() {
+        let mut limiter = Limiter::new();
+        let quiet = sine(RATE, 1024, 440.0, 0.5);
+        let mut buffer = audio_buffer(RATE, std::slice::from_ref(&quiet));
+        limiter.apply(&mut buffer);
+
+        assert_eq!(planes(&buffer)[0], quiet);
+    }
+
+    #[test]
+    fn recovers_after_a_peak() {
+        let mut limiter = Limiter::new();
+        let mut samples = vec![0.5; RATE as usize];
+        samples[0] = 2.0;
+        let mut buffer = audio_buffer(RATE, &[samples]);
+        limiter.apply(&mut buffer);
+
+        let limited = planes(&buffer).remove(0);
+        assert!(limited[1] < 0.5);
+        assert!((limited[limited.len() - 1] - 0.5).abs() < 1e-3);
+    }
+}
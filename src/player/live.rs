@@ -0,0 +1,83 @@
+use actix_web::web::Bytes;
+use symphonia::core::audio::{AudioBuffer, Signal, SignalSpec};
+use tokio::sync::broadcast;
+
+/// Number of chunks a listener can fall behind before it skips ahead
+const LISTENER_CAPACITY: usize = 128;
+
+/// Shares the mix played by the server with the clients listening to the live stream.
+///
+/// The samples are sent as 16 bit little endian PCM, in the format of the output.
+#[derive(Debug, Clone)]
+pub(crate) struct LiveStream {
+    sender: broadcast::Sender<Bytes>,
+    spec: SignalSpec,
+}
+
+impl LiveStream {
+    pub(crate) fn new(spec: SignalSpec) -> Self {
+        let (sender, _) = broadcast::channel(LISTENER_CAPACITY);
+
+        Self { sender, spec }
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<Bytes> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn has_listeners(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    pub(crate) fn spec(&self) -> SignalSpec {
+        self.spec
+    }
+
+    pub(crate) fn write(&self, buffer: &AudioBuffer<f32>) {
+        if !self.has_listeners() || *buffer.spec() != self.spec {
+            return;
+        }
+
+        let channels = self.spec.channels.count();
+        let mut bytes = Vec::with_capacity(buffer.frames() * channels * 2);
+        for frame in 0..buffer.frames() {
+            for channel in 0..channels {
+                let sample = buffer.chan(channel)[frame].clamp(-1.0, 1.0);
+                bytes.extend_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes());
+            }
+        }
+
+        _ = self.sender.send(Bytes::from(bytes));
+    }
+
+    /// Keeps the listeners connected while nothing is played
+    pub(crate) fn write_silence(&self, frames: usize) {
+        if self.has_listeners() && frames > 0 {
+            let size = frames * self.spec.channels.count() * 2;
+            _ = self.sender.send(Bytes::from(vec![0; size]));
+        }
+    }
+
+    /// The header of a WAV file of unknown length in the format of the stream
+    pub(crate) fn wav_header(&self) -> Bytes {
+        let channels = self.spec.channels.count() as u16;
+        let block_align = channels * 2;
+        let mut header = Vec::with_capacity(44);
+
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&u32::MAX.to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16_u32.to_le_bytes());
+        // PCM
+        header.extend_from_slice(&1_u16.to_le_bytes());
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&self.spec.rate.to_le_bytes());
+        header.extend_from_slice(&(self.spec.rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&16_u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&u32::MAX.to_le_bytes());
+
+        Bytes::from(header)
+    }
+}
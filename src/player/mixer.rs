@@ -0,0 +1,474 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use symphonia::core::audio::{AsAudioBufferRef, AudioBuffer, Signal, SignalSpec};
+
+use crate::output::{self, AudioOutput, OutputKind};
+
+use super::{LiveStream, LOG_TARGET};
+
+/// Number of decoded buffers a source holds before its decoder has to wait
+const SOURCE_CAPACITY: usize = 4;
+/// Number of frames mixed and written to the output at once
+const BLOCK_FRAMES: usize = 1024;
+/// How long the mixer waits for a decoder that is running late
+const SOURCE_TIMEOUT: Duration = Duration::from_millis(20);
+/// How long the output stays open once there is no source left
+const IDLE_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often silence is sent to the live stream while nothing is played
+const SILENCE_INTERVAL: Duration = Duration::from_millis(100);
+
+pub(crate) enum MixerCommand {
+    /// Play the source right away. The source being played keeps going until it ends,
+    /// which is how a track fading out overlaps the new one
+    Play(Source),
+    /// Play the source as soon as the current one ends, without a gap
+    Queue(Source),
+}
+
+/// Shared between the thread decoding a track and the mixer
+#[derive(Debug, Default)]
+pub(crate) struct SourceControl {
+    /// Samples decoded before the last flush are dropped
+    generation: AtomicU64,
+    /// Set by the mixer once the source is the one being played
+    live: AtomicBool,
+    cancelled: AtomicBool,
+    /// Set once the mixer no longer reads from the source
+    dropped: AtomicBool,
+}
+
+impl SourceControl {
+    pub(crate) fn is_live(&self) -> bool {
+        self.live.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn is_dropped(&self) -> bool {
+        self.dropped.load(Ordering::SeqCst)
+    }
+
+    /// Drops the samples that have not been played yet. Used when seeking
+    pub(crate) fn flush(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Stops the source right away, dropping the samples that have not been played yet
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.flush();
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+}
+
+struct Chunk {
+    generation: u64,
+    buffer: AudioBuffer<f32>,
+}
+
+/// The end of a source the decoder writes to
+pub(crate) struct SourceWriter {
+    sender: mpsc::SyncSender<Chunk>,
+    control: Arc<SourceControl>,
+    /// The format the samples are expected in
+    spec: SignalSpec,
+}
+
+impl SourceWriter {
+    pub(crate) fn control(&self) -> &Arc<SourceControl> {
+        &self.control
+    }
+
+    pub(crate) fn spec(&self) -> SignalSpec {
+        self.spec
+    }
+
+    /// Blocks while the source is full. Fails once the mixer has dropped the source
+    pub(crate) fn write(&self, buffer: &AudioBuffer<f32>) -> Result<(), ()> {
+        let mut copy = AudioBuffer::new(buffer.capacity() as u64, *buffer.spec());
+        buffer.as_audio_buffer_ref().convert(&mut copy);
+
+        self.sender
+            .send(Chunk {
+                generation: self.control.generation(),
+                buffer: copy,
+            })
+            .map_err(|_| ())
+    }
+}
+
+/// The end of a source the mixer reads from
+pub(crate) struct Source {
+    receiver: mpsc::Receiver<Chunk>,
+    control: Arc<SourceControl>,
+    /// The buffer being read and the number of frames already read from it
+    pending: Option<(AudioBuffer<f32>, usize)>,
+    ended: bool,
+}
+
+/// Creates a source for a decoder to feed the mixer with samples in the format of the output
+pub(crate) fn source(spec: SignalSpec) -> (SourceWriter, Source) {
+    let (sender, receiver) = mpsc::sync_channel(SOURCE_CAPACITY);
+    let control = Arc::new(SourceControl::default());
+
+    (
+        SourceWriter {
+            sender,
+            control: control.clone(),
+            spec,
+        },
+        Source {
+            receiver,
+            control,
+            pending: None,
+            ended: false,
+        },
+    )
+}
+
+impl Source {
+    /// Returns false when there is nothing to read right now
+    fn fill(&mut self, timeout: Duration) -> bool {
+        loop {
+            if let Some((buffer, read)) = &self.pending {
+                if *read < buffer.frames() {
+                    return true;
+                }
+            }
+            self.pending = None;
+
+            if self.ended || self.control.is_cancelled() {
+                return false;
+            }
+
+            match self.receiver.recv_timeout(timeout) {
+                Ok(chunk) if chunk.generation == self.control.generation() => {
+                    self.pending = Some((chunk.buffer, 0));
+                }
+                Ok(_) => (),
+                Err(mpsc::RecvTimeoutError::Timeout) => return false,
+                Err(mpsc::RecvTimeoutError::Disconnected) => self.ended = true,
+            }
+        }
+    }
+
+    fn spec(&self) -> Option<SignalSpec> {
+        self.pending.as_ref().map(|(buffer, _)| *buffer.spec())
+    }
+
+    /// True once every sample has been read or the source has been cancelled
+    fn is_done(&self) -> bool {
+        self.control.is_cancelled() || (self.ended && self.pending.is_none())
+    }
+
+    fn go_live(&self) {
+        self.control.live.store(true, Ordering::SeqCst);
+    }
+
+    /// Adds samples to the mix from frame `start`. Returns the number of frames added
+    fn mix_into(&mut self, mix: &mut AudioBuffer<f32>, start: usize) -> usize {
+        let mut position = start;
+
+        while position < mix.frames() && self.fill(SOURCE_TIMEOUT) {
+            let (buffer, read) = self.pending.as_mut().unwrap();
+            if buffer.spec() != mix.spec() {
+                break;
+            }
+
+            let count = (buffer.frames() - *read).min(mix.frames() - position);
+            for channel in 0..mix.spec().channels.count() {
+                let samples = &buffer.chan(channel)[*read..*read + count];
+                for (mixed, sample) in mix.chan_mut(channel)[position..position + count]
+                    .iter_mut()
+                    .zip(samples)
+                {
+                    *mixed += *sample;
+                }
+            }
+
+            *read += count;
+            position += count;
+        }
+
+        position - start
+    }
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        self.control.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Mixes the tracks being played and writes them to a single audio output
+/// that stays open from one track to the next
+struct Mixer {
+    kind: OutputKind,
+    output: Option<(Box<dyn AudioOutput>, SignalSpec)>,
+    mix: Option<AudioBuffer<f32>>,
+    current: Option<Source>,
+    next: Option<Source>,
+    /// Sources that have been replaced and are playing until they end
+    fading: Vec<Source>,
+    last_write: Instant,
+    live: LiveStream,
+    /// When the live stream started getting silence, and the number of silent frames sent
+    silence: Option<(Instant, u64)>,
+    /// When the output started getting silence, and the number of silent frames written
+    output_silence: Option<(Instant, u64)>,
+}
+
+/// Starts the mixer thread
+pub(crate) fn start(kind: OutputKind, live: LiveStream) -> mpsc::Sender<MixerCommand> {
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let mut mixer = Mixer {
+            kind,
+            output: None,
+            mix: None,
+            current: None,
+            next: None,
+            fading: Vec::new(),
+            last_write: Instant::now(),
+            live,
+            silence: None,
+            output_silence: None,
+        };
+        mixer.run(receiver);
+    });
+
+    sender
+}
+
+impl Mixer {
+    fn run(&mut self, receiver: mpsc::Receiver<MixerCommand>) {
+        loop {
+            let command = if self.is_idle() {
+                let timeout = if self.live.has_listeners() {
+                    SILENCE_INTERVAL
+                } else {
+                    IDLE_TIMEOUT
+                };
+                receiver
+                    .recv_timeout(timeout)
+                    .map_err(|err| matches!(err, mpsc::RecvTimeoutError::Disconnected))
+            } else {
+                receiver
+                    .try_recv()
+                    .map_err(|err| matches!(err, mpsc::TryRecvError::Disconnected))
+            };
+
+            match command {
+                Ok(MixerCommand::Play(source)) => {
+                    source.go_live();
+                    if let Some(current) = self.current.replace(source) {
+                        self.fading.push(current);
+                    }
+                }
+                Ok(MixerCommand::Queue(source)) => self.next = Some(source),
+                Err(true) => break,
+                Err(false) => (),
+            }
+
+            if !self.mix_block() {
+                self.feed_silence();
+                self.close_when_idle();
+            }
+        }
+
+        self.close();
+    }
+
+    fn is_idle(&self) -> bool {
+        self.current.is_none() && self.next.is_none() && self.fading.is_empty()
+    }
+
+    /// Makes the next source the current one once the current one has ended
+    fn promote(&mut self) -> bool {
+        let done = self.current.as_ref().map(Source::is_done).unwrap_or(true);
+        if done && self.next.is_some() {
+            let next = self.next.take().unwrap();
+            next.go_live();
+            self.current = Some(next);
+            return true;
+        }
+
+        if done {
+            self.current = None;
+        }
+        false
+    }
+
+    /// Mixes and writes one block. Returns false when there was nothing to play
+    fn mix_block(&mut self) -> bool {
+        self.promote();
+        if self.next.as_ref().map(Source::is_done).unwrap_or_default() {
+            self.next = None;
+        }
+        self.fading.retain(|source| !source.is_done());
+
+        // The block takes the format of the current source. A source fading out in another
+        // format cannot be mixed and is dropped
+        let spec = self
+            .current
+            .iter_mut()
+            .chain(self.fading.iter_mut())
+            .find_map(|source| source.fill(SOURCE_TIMEOUT).then(|| source.spec()))
+            .flatten();
+        let Some(spec) = spec else {
+            return false;
+        };
+        self.fading
+            .retain(|source| source.spec().map(|s| s == spec).unwrap_or(true));
+
+        if !self.open(spec) {
+            // Dropping the sources makes their decoders report the error
+            self.current = None;
+            self.next = None;
+            self.fading.clear();
+            return false;
+        }
+
+        let mut mix = match self.mix.take() {
+            Some(mix) if *mix.spec() == spec => mix,
+            _ => AudioBuffer::new(BLOCK_FRAMES as u64, spec),
+        };
+        mix.clear();
+        mix.render_silence(Some(BLOCK_FRAMES));
+
+        let mut mixed = 0;
+        if let Some(current) = self.current.as_mut() {
+            mixed = current.mix_into(&mut mix, 0);
+        }
+
+        // The next track picks up right where the current one ends
+        while mixed < BLOCK_FRAMES && self.promote() {
+            if let Some(current) = self.current.as_mut() {
+                mixed += current.mix_into(&mut mix, mixed);
+            }
+        }
+
+        for source in self.fading.iter_mut() {
+            mixed = mixed.max(source.mix_into(&mut mix, 0));
+        }
+
+        mix.truncate(mixed);
+        let written = match self.output.as_mut() {
+            Some((output, _)) if mixed > 0 => output.write(mix.as_audio_buffer_ref()),
+            _ => Ok(()),
+        };
+        self.mix = Some(mix);
+
+        if let Err(err) = written {
+            log::error!(target: LOG_TARGET, "could not write to the audio output: {:?}", err);
+            self.output = None;
+            self.current = None;
+            self.next = None;
+            self.fading.clear();
+            return false;
+        }
+
+        if mixed > 0 {
+            if let Some(mix) = &self.mix {
+                self.live.write(mix);
+            }
+            self.last_write = Instant::now();
+            self.silence = None;
+            self.output_silence = None;
+        }
+        mixed > 0
+    }
+
+    /// Sends silence to the live stream at the rate of the output while nothing
+    /// is played, so that the listeners stay connected
+    fn feed_silence(&mut self) {
+        if !self.live.has_listeners() {
+            self.silence = None;
+            return;
+        }
+
+        let rate = self.live.spec().rate as f64;
+        let (started, sent) = self.silence.get_or_insert((Instant::now(), 0));
+        let due = (started.elapsed().as_secs_f64() * rate) as u64;
+        if due > *sent {
+            self.live.write_silence((due - *sent) as usize);
+            *sent = due;
+        }
+    }
+
+    /// Opens the output, or opens it again when the format changes
+    fn open(&mut self, spec: SignalSpec) -> bool {
+        if let Some((_, opened)) = &self.output {
+            if *opened == spec {
+                return true;
+            }
+            self.close();
+        }
+
+        match output::open(&self.kind, spec, BLOCK_FRAMES as u64) {
+            Ok(output) => {
+                self.output = Some((output, spec));
+                true
+            }
+            Err(err) => {
+                log::error!(target: LOG_TARGET, "could not open the audio output: {:?}", err);
+                false
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some((mut output, _)) = self.output.take() {
+            output.flush();
+        }
+    }
+
+    /// Writes silence to the output at its rate while a source is paused, so that the output
+    /// does not have to be opened again on resume
+    fn write_silence(&mut self) {
+        let Some((output, spec)) = self.output.as_mut() else {
+            return;
+        };
+
+        let rate = spec.rate as f64;
+        let (started, written) = self.output_silence.get_or_insert((Instant::now(), 0));
+        let due = (started.elapsed().as_secs_f64() * rate) as u64;
+        while *written < due {
+            let frames = (due - *written).min(BLOCK_FRAMES as u64);
+            let mut silence = AudioBuffer::<f32>::new(frames, *spec);
+            silence.render_silence(Some(frames as usize));
+            if let Err(err) = output.write(silence.as_audio_buffer_ref()) {
+                log::error!(target: LOG_TARGET, "could not write to the audio output: {:?}", err);
+                self.output = None;
+                self.output_silence = None;
+                return;
+            }
+            *written += frames;
+        }
+        self.last_write = Instant::now();
+    }
+
+    /// Closes the output once no source has been left for a while
+    fn close_when_idle(&mut self) {
+        if !self.is_idle() {
+            self.write_silence();
+        } else {
+            self.output_silence = None;
+            if self.output.is_some() && self.last_write.elapsed() >= IDLE_TIMEOUT {
+                self.close();
+            }
+        }
+
+        if self.output.is_some() || !self.is_idle() {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
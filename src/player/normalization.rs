@@ -0,0 +1,87 @@
+use crate::entity::media::ReplayGain;
+
+/// Which ReplayGain values the player normalizes the loudness with
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReplayGainMode {
+    /// Every track is played at the same loudness
+    #[default]
+    Track,
+    /// Tracks of an album keep their relative loudness. Falls back to the
+    /// track values when the album values are not known
+    Album,
+    Off,
+}
+
+impl ReplayGainMode {
+    /// The linear gain to apply to a track. The gain is lowered when the peak
+    /// of the track would go over full scale
+    pub(crate) fn gain(&self, replay_gain: &ReplayGain) -> f32 {
+        let (gain, peak) = match self {
+            Self::Off => return 1.0,
+            Self::Track => (replay_gain.track_gain, replay_gain.track_peak),
+            Self::Album => (
+                replay_gain.album_gain.or(replay_gain.track_gain),
+                replay_gain.album_peak.or(replay_gain.track_peak),
+            ),
+        };
+
+        let Some(gain) = gain else {
+            return 1.0;
+        };
+
+        let linear = 10_f32.powf(gain / 20.0);
+        match peak {
+            Some(peak) if peak > 0.0 => linear.min(1.0 / peak),
+            _ => linear,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::media::ReplayGain;
+
+    use super::ReplayGainMode;
+
+    #[test]
+    fn converts_the_gain_to_linear() {
+        let replay_gain = ReplayGain {
+            track_gain: Some(-6.0),
+            ..Default::default()
+        };
+
+        let gain = ReplayGainMode::Track.gain(&replay_gain);
+        assert!((gain - 0.501).abs() < 1e-3);
+        assert_eq!(ReplayGainMode::Off.gain(&replay_gain), 1.0);
+    }
+
+    #[test]
+    fn keeps_the_peak_under_full_scale() {
+        let replay_gain = ReplayGain {
+            track_gain: Some(6.0),
+            track_peak: Some(0.8),
+            ..Default::default()
+        };
+
+        assert_eq!(ReplayGainMode::Track.gain(&replay_gain), 1.0 / 0.8);
+    }
+
+    #[test]
+    fn album_mode_falls_back_to_the_track_values() {
+        let track_only = ReplayGain {
+            track_gain: Some(-6.0),
+            ..Default::default()
+        };
+        let with_album = ReplayGain {
+            album_gain: Some(0.0),
+            ..track_only
+        };
+
+        assert_eq!(
+            ReplayGainMode::Album.gain(&track_only),
+            ReplayGainMode::Track.gain(&track_only)
+        );
+        assert_eq!(ReplayGainMode::Album.gain(&with_album), 1.0);
+        assert_eq!(ReplayGainMode::Track.gain(&ReplayGain::default()), 1.0);
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::{Arc, RwLock};
+
+use crate::queue_manager::{RepeatMode, SleepTimer};
+
+use super::Volume;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub(crate) enum PlaybackStatus {
+    #[default]
+    #[serde(rename = "stopped")]
+    Stopped,
+    #[serde(rename = "playing")]
+    Playing,
+    #[serde(rename = "paused")]
+    Paused,
+}
+
+/// What the server player is doing right now
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub(crate) struct PlayerState {
+    /// ID of the track being played. Not set for files played by path
+    pub(crate) track_id: Option<String>,
+    pub(crate) status: PlaybackStatus,
+    /// Position in the track, in seconds
+    pub(crate) position: f64,
+    /// Duration of the track, in seconds
+    pub(crate) duration: f64,
+    pub(crate) volume: Volume,
+    pub(crate) repeat: RepeatMode,
+    pub(crate) shuffle: bool,
+    /// When the music stops. Not set when no sleep timer is running
+    pub(crate) sleep_timer: Option<SleepTimer>,
+}
+
+/// The player state shared between the player, the queue manager and the web app
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SharedPlayerState(Arc<RwLock<PlayerState>>);
+
+impl SharedPlayerState {
+    pub(crate) fn snapshot(&self) -> PlayerState {
+        self.0.read().map(|state| state.clone()).unwrap_or_default()
+    }
+
+    pub(crate) fn update(&self, callback: impl FnOnce(&mut PlayerState)) {
+        if let Ok(mut state) = self.0.write() {
+            callback(&mut state);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Drives the player through the null and WAV outputs, without a sound card
+
+use std::{
+    path::Path,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{Config, ConfigBuilder},
+    entity::media::ReplayGain,
+    testing::{temp_dir, write_sine_wav},
+    websocket::websocket_message::WebsocketMessage,
+};
+
+use super::{
+    handle_request, output_spec, LiveStream, PlaybackStatus, PlayerCommand, SharedPlayerState,
+    TrackEnded,
+};
+
+const RATE: u32 = 44100;
+
+struct TestPlayer {
+    commands: mpsc::Sender<PlayerCommand>,
+    ended: mpsc::Receiver<TrackEnded>,
+    state: SharedPlayerState,
+    // Kept so that the player can send its updates
+    _updates: mpsc::Receiver<WebsocketMessage>,
+}
+
+impl TestPlayer {
+    fn start(output: &str, recording: &Path) -> Self {
+        let config = ConfigBuilder::new()
+            .audio_output(output)
+            .audio_output_file(recording.to_str().unwrap())
+            .fade(0)
+            .crossfade(0.0)
+            .replay_gain("off")
+            .output_sample_rate(RATE)
+            .output_channels(2)
+            .build();
+
+        Self::start_with(config)
+    }
+
+    fn start_with(config: Config) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let (updates_sender, updates) = mpsc::channel();
+        let (ended_sender, ended) = mpsc::channel();
+        let state = SharedPlayerState::default();
+        let live = LiveStream::new(output_spec(
+            config.output_sample_rate(),
+            config.output_channels(),
+        ));
+
+        let player_state = state.clone();
+        std::thread::spawn(move || {
+            handle_request(
+                receiver,
+                updates_sender,
+                ended_sender,
+                player_state,
+                live,
+                config,
+            );
+        });
+
+        Self {
+            commands,
+            ended,
+            state,
+            _updates: updates,
+        }
+    }
+
+    fn play(&self, path: &Path, track_id: &str) {
+        self.commands
+            .send(PlayerCommand::Play {
+                path: path.to_str().unwrap().to_string(),
+                track_id: Some(track_id.to_string()),
+                replay_gain: ReplayGain::default(),
+            })
+            .unwrap();
+    }
+
+    fn play_next(&self, path: &Path, track_id: &str) {
+        self.commands
+            .send(PlayerCommand::PlayNext {
+                path: path.to_str().unwrap().to_string(),
+                track_id: Some(track_id.to_string()),
+                replay_gain: ReplayGain::default(),
+            })
+            .unwrap();
+    }
+
+    /// Waits for the end, or the upcoming end, of the track
+    fn wait_for(&self, track_id: &str, upcoming: bool) -> TrackEnded {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+            let ended = self
+                .ended
+                .recv_timeout(timeout)
+                .expect("the track never ended");
+            if ended.track_id.as_deref() == Some(track_id) && ended.upcoming == upcoming {
+                return ended;
+            }
+        }
+
+        panic!("the track never ended")
+    }
+}
+
+/// Reads the samples recorded once the output has been closed
+fn read_recording(path: &Path) -> Vec<f32> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        // The header is written when the mixer closes the output after some idle time
+        if let Ok(mut reader) = hound::WavReader::open(path) {
+            if reader.duration() > 0 {
+                return reader.samples::<f32>().map(Result::unwrap).collect();
+            }
+        }
+        assert!(Instant::now() < deadline, "the recording was never closed");
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn wav_output_records_the_tracks_back_to_back() {
+    let dir = temp_dir();
+    let first = dir.join("first.wav");
+    let second = dir.join("second.wav");
+    let recording = dir.join("recording.wav");
+    let first_frames = write_sine_wav(&first, RATE, 2, 0.5, 0.25);
+    let second_frames = write_sine_wav(&second, RATE, 2, 0.3, 0.25);
+
+    let player = TestPlayer::start("wav", &recording);
+    player.play(&first, "first");
+
+    // The next track is asked for ahead of the end, the way the queue manager does
+    player.wait_for("first", true);
+    player.play_next(&second, "second");
+    player.wait_for("first", false);
+    player.wait_for("second", false);
+
+    let samples = read_recording(&recording);
+    assert_eq!(samples.len(), (first_frames + second_frames) * 2);
+
+    // Nothing stands between the file and the recording at full volume without ReplayGain
+    let original = hound::WavReader::open(&first)
+        .unwrap()
+        .samples::<i16>()
+        .map(|sample| sample.unwrap() as f32 / 32768.0)
+        .collect::<Vec<f32>>();
+    for (recorded, original) in samples.iter().zip(original.iter()) {
+        assert!((recorded - original).abs() < 1e-3);
+    }
+
+    _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn null_output_plays_in_real_time() {
+    let dir = temp_dir();
+    let track = dir.join("track.wav");
+    write_sine_wav(&track, RATE, 2, 1.0, 0.25);
+
+    let player = TestPlayer::start("null", &dir.join("unused.wav"));
+    let started = Instant::now();
+    player.play(&track, "track");
+
+    std::thread::sleep(Duration::from_millis(300));
+    let state = player.state.snapshot();
+    assert_eq!(state.status, PlaybackStatus::Playing);
+    assert_eq!(state.track_id.as_deref(), Some("track"));
+
+    let ended = player.wait_for("track", false);
+    assert!(ended.error.is_none());
+    // The end is reported once the last samples are handed to the mixer, which holds
+    // a few blocks ahead of the output
+    assert!(started.elapsed() >= Duration::from_millis(800));
+
+    _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn null_output_reports_unplayable_files() {
+    let dir = temp_dir();
+    let track = dir.join("broken.wav");
+    std::fs::write(&track, b"not a wav file").unwrap();
+
+    let player = TestPlayer::start("null", &dir.join("unused.wav"));
+    player.play(&track, "broken");
+
+    let ended = player.wait_for("broken", false);
+    assert!(ended.error.is_some());
+
+    _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn wav_output_stays_open_through_a_long_pause() {
+    let dir = temp_dir();
+    let track = dir.join("track.wav");
+    let recording = dir.join("recording.wav");
+    let frames = write_sine_wav(&track, RATE, 2, 0.5, 0.25);
+
+    let player = TestPlayer::start("wav", &recording);
+    player.play(&track, "track");
+    std::thread::sleep(Duration::from_millis(100));
+    player.commands.send(PlayerCommand::Pause).unwrap();
+
+    // Longer than the output is kept open once nothing is left to play
+    std::thread::sleep(Duration::from_millis(3000));
+    player.commands.send(PlayerCommand::Resume).unwrap();
+    player.wait_for("track", false);
+
+    // The pause is recorded as silence rather than closing the output
+    let samples = read_recording(&recording);
+    assert!(samples.len() >= (frames + 2 * RATE as usize) * 2);
+
+    _ = std::fs::remove_dir_all(dir);
+}
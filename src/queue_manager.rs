@@ -1,103 +1,709 @@
-use std::sync::{atomic::AtomicUsize, RwLock};
+use std::sync::Arc;
 
-use crate::player::PlayerCommand;
+use rand::Rng;
+
+mod sleep_timer;
+#[cfg(test)]
+mod tests;
+
+pub(crate) use sleep_timer::SleepTimer;
+
+use crate::{
+    config::Config,
+    db::DbManager,
+    entity::{
+        media::MediaEntity,
+        queue::{QueueEntity, QueueRepo},
+    },
+    player::{PlaybackStatus, PlayerCommand, SharedPlayerState, TrackEnded},
+    websocket::websocket_message::{PlayerEvent, QueueEvent, WebsocketMessage},
+};
 
 pub(crate) enum QueueManagerCommand {
     Next,
     Previous,
     Play,
     Reset,
+    /// Queue the track with the given ID
     Queue(String),
+    /// Replace the queue with the tracks and play the first one
+    PlayTracks {
+        track_ids: Vec<String>,
+        queued_by: String,
+    },
+    /// Play the first entry in the queue for the track with the given ID
+    SkipTo(String),
+    /// Add a track right after the current track and play it
+    PlayNow {
+        track_id: String,
+        queued_by: String,
+    },
+    /// Add a track at the end of the queue, or right after the current track when `next` is true
+    Add {
+        track_id: String,
+        queued_by: String,
+        next: bool,
+        reply: tokio::sync::oneshot::Sender<Option<QueueEntity>>,
+    },
+    /// Remove the entry at the given position in the play order
+    Remove {
+        position: usize,
+        reply: tokio::sync::oneshot::Sender<Option<QueueEntity>>,
+    },
+    /// Move the entry at `from` to `to`, both positions in the play order
+    Move {
+        from: usize,
+        to: usize,
+        reply: tokio::sync::oneshot::Sender<bool>,
+    },
+    Repeat(RepeatMode),
+    Shuffle(bool),
+    /// Start the sleep timer, or cancel it
+    SleepTimer(Option<SleepTimer>),
+}
+
+/// Name of the setting the queue entry being played is persisted under
+pub(crate) const CURRENT_SETTING_NAME: &str = "queue_current";
+pub(crate) const REPEAT_SETTING_NAME: &str = "queue_repeat";
+pub(crate) const SHUFFLE_SETTING_NAME: &str = "queue_shuffle";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub(crate) enum RepeatMode {
+    #[default]
+    #[serde(rename = "off")]
+    Off,
+    /// Play the current track again when it ends
+    #[serde(rename = "one")]
+    One,
+    /// Start over from the first track once the last one ends
+    #[serde(rename = "all")]
+    All,
 }
 
 pub(crate) fn setup_queue_manager(
     sender: std::sync::mpsc::Sender<PlayerCommand>,
+    ended_receiver: std::sync::mpsc::Receiver<TrackEnded>,
+    ws_sender: std::sync::mpsc::Sender<WebsocketMessage>,
+    db_manager: Arc<DbManager>,
+    player_state: SharedPlayerState,
+    config: &Config,
 ) -> std::sync::mpsc::Sender<QueueManagerCommand> {
     let (queue_sender, receiver) = std::sync::mpsc::channel::<QueueManagerCommand>();
-    let mut manager = QueueManager::new(sender);
-    std::thread::spawn(move || loop {
-        if let Ok(cmd) = receiver.try_recv() {
-            match cmd {
-                QueueManagerCommand::Next => manager.next(),
-                QueueManagerCommand::Previous => manager.previous(),
-                QueueManagerCommand::Play => manager.play_queue(),
-                QueueManagerCommand::Reset => manager.reset(),
-                QueueManagerCommand::Queue(track) => {
-                    let count = manager.queue(&track);
-                    log::debug!("total tracks queued: {}", count)
+    // The queue manager runs on its own thread but reads and writes the queue through the
+    // async repositories of the current runtime
+    let runtime = tokio::runtime::Handle::current();
+    let skip_unplayable = config.skip_unplayable();
+    std::thread::spawn(move || {
+        let mut manager = QueueManager::new(
+            sender,
+            ws_sender,
+            db_manager,
+            player_state,
+            runtime,
+            skip_unplayable,
+        );
+        loop {
+            if let Ok(cmd) = receiver.try_recv() {
+                match cmd {
+                    QueueManagerCommand::Next => manager.next(),
+                    QueueManagerCommand::Previous => manager.previous(),
+                    QueueManagerCommand::Play => manager.play_queue(),
+                    QueueManagerCommand::Reset => manager.reset(),
+                    QueueManagerCommand::Queue(track) => {
+                        let count = manager.queue(&track);
+                        log::debug!("total tracks queued: {}", count)
+                    }
+                    QueueManagerCommand::Add {
+                        track_id,
+                        queued_by,
+                        next,
+                        reply,
+                    } => {
+                        _ = reply.send(manager.add(&track_id, &queued_by, next));
+                    }
+                    QueueManagerCommand::PlayTracks {
+                        track_ids,
+                        queued_by,
+                    } => manager.play_tracks(&track_ids, &queued_by),
+                    QueueManagerCommand::SkipTo(track_id) => manager.skip_to(&track_id),
+                    QueueManagerCommand::PlayNow {
+                        track_id,
+                        queued_by,
+                    } => manager.play_now(&track_id, &queued_by),
+                    QueueManagerCommand::Remove { position, reply } => {
+                        _ = reply.send(manager.remove(position));
+                    }
+                    QueueManagerCommand::Move { from, to, reply } => {
+                        _ = reply.send(manager.move_entry(from, to));
+                    }
+                    QueueManagerCommand::Repeat(mode) => manager.set_repeat(mode),
+                    QueueManagerCommand::Shuffle(shuffle) => manager.set_shuffle(shuffle),
+                    QueueManagerCommand::SleepTimer(timer) => manager.set_sleep_timer(timer),
                 }
             }
+            if let Ok(ended) = ended_receiver.try_recv() {
+                manager.track_ended(ended);
+            }
+            manager.check_sleep_timer();
+            std::thread::sleep(std::time::Duration::from_millis(100));
         }
-        std::thread::sleep(std::time::Duration::from_millis(100));
     });
 
     queue_sender
 }
 
-#[derive(Debug)]
 pub(crate) struct QueueManager {
-    current: AtomicUsize,
-    queue: RwLock<Vec<String>>, // TODO: Fetch the queue from a persistent storage. Do not keep the queue in memory
+    /// Internal ID of the queue entry being played
+    current: Option<i64>,
+    /// Path of the last track sent to the player
+    playing: Option<String>,
+    repeat: RepeatMode,
+    shuffle: bool,
     sender: std::sync::mpsc::Sender<PlayerCommand>,
+    ws_sender: std::sync::mpsc::Sender<WebsocketMessage>,
+    db_manager: Arc<DbManager>,
+    player_state: SharedPlayerState,
+    runtime: tokio::runtime::Handle,
+    /// Move on to the next entry when a track cannot be played
+    skip_unplayable: bool,
+    /// Number of entries in a row that could not be played
+    failures: usize,
+    /// Internal ID of the queue entry sent to the player to follow the track being
+    /// played, and the path of its track
+    preloaded: Option<(i64, String)>,
+    sleep_timer: Option<SleepTimer>,
 }
 
 impl QueueManager {
-    pub(crate) fn new(sender: std::sync::mpsc::Sender<PlayerCommand>) -> Self {
+    pub(crate) fn new(
+        sender: std::sync::mpsc::Sender<PlayerCommand>,
+        ws_sender: std::sync::mpsc::Sender<WebsocketMessage>,
+        db_manager: Arc<DbManager>,
+        player_state: SharedPlayerState,
+        runtime: tokio::runtime::Handle,
+        skip_unplayable: bool,
+    ) -> Self {
+        let settings = db_manager.setting_repo();
+        let (current, repeat, shuffle) = runtime.block_on(async {
+            (
+                settings
+                    .get::<Option<i64>>(CURRENT_SETTING_NAME)
+                    .await
+                    .flatten(),
+                settings
+                    .get::<RepeatMode>(REPEAT_SETTING_NAME)
+                    .await
+                    .unwrap_or_default(),
+                settings
+                    .get::<bool>(SHUFFLE_SETTING_NAME)
+                    .await
+                    .unwrap_or_default(),
+            )
+        });
+
+        player_state.update(|state| {
+            state.repeat = repeat;
+            state.shuffle = shuffle;
+        });
+
         Self {
-            current: AtomicUsize::default(),
-            queue: RwLock::new(Vec::new()),
+            current,
+            playing: None,
+            repeat,
+            shuffle,
             sender,
+            ws_sender,
+            db_manager,
+            player_state,
+            runtime,
+            skip_unplayable,
+            failures: 0,
+            preloaded: None,
+            sleep_timer: None,
         }
     }
+
     pub(crate) fn next(&mut self) {
-        let index = self.current.load(std::sync::atomic::Ordering::Relaxed) + 1;
-        self.play_by_index_and_set(index);
+        let entry = self.runtime.block_on(self.next_entry());
+
+        match entry {
+            Some(entry) => self.play_entry(entry),
+            None => log::debug!("reached the end of the queue"),
+        }
     }
 
     pub(crate) fn previous(&mut self) {
-        let mut index = self.current.load(std::sync::atomic::Ordering::Relaxed);
-        index = index.saturating_sub(1);
-        self.play_by_index_and_set(index);
+        let repo = self.repo();
+        let entry = self.runtime.block_on(async {
+            let previous = match self.current_entry().await {
+                Some(current) => repo.previous_before(&current, self.shuffle).await,
+                None => None,
+            };
+
+            match previous {
+                None if self.repeat == RepeatMode::All => repo.last(self.shuffle).await,
+                None => repo.first(self.shuffle).await,
+                previous => previous,
+            }
+        });
+
+        if let Some(entry) = entry {
+            self.play_entry(entry);
+        }
+    }
+
+    /// Adds the track to the queue and returns the number of queued tracks
+    pub(crate) fn queue(&mut self, track_id: &str) -> usize {
+        self.add(track_id, "", false);
+        self.runtime.block_on(self.repo().count()) as usize
+    }
+
+    /// Adds the track at the end of the queue, or right after the current track when `next`
+    /// is true
+    pub(crate) fn add(
+        &mut self,
+        track_id: &str,
+        queued_by: &str,
+        next: bool,
+    ) -> Option<QueueEntity> {
+        let repo = self.repo();
+        let (entry, position) = self.runtime.block_on(async {
+            // A track queued while shuffling lands somewhere among the tracks not played yet
+            let shuffle_position = if self.shuffle && !next {
+                let start = match self.current_entry().await {
+                    Some(current) => current.shuffle_position,
+                    None => -1.0,
+                };
+                let end = repo.max_shuffle_position().await.max(start) + 1.0;
+                Some(rand::thread_rng().gen_range(start..end))
+            } else {
+                None
+            };
+
+            let entry = repo.add(track_id, queued_by, shuffle_position).await?;
+
+            let mut ids = self.ordered_ids().await;
+            if next {
+                ids.retain(|id| *id != entry.internal_id);
+                let index = self
+                    .current
+                    .and_then(|current| ids.iter().position(|id| *id == current))
+                    .map(|index| index + 1)
+                    .unwrap_or_default();
+                ids.insert(index, entry.internal_id);
+                repo.reorder(&ids, self.shuffle).await;
+            }
+
+            let position = ids.iter().position(|id| *id == entry.internal_id)? + 1;
+            Some((repo.find_by_id(entry.internal_id).await?, position))
+        })?;
+
+        self.broadcast(QueueEvent::TrackAdded {
+            position,
+            track_id: entry.track_id.clone(),
+        });
+        self.refresh_preload();
+
+        Some(entry)
+    }
+
+    /// Replaces the queue with the tracks and plays the first one
+    pub(crate) fn play_tracks(&mut self, track_ids: &[String], queued_by: &str) {
+        self.reset();
+        for track_id in track_ids {
+            self.add(track_id, queued_by, false);
+        }
+
+        let repo = self.repo();
+        if let Some(entry) = self.runtime.block_on(repo.first(self.shuffle)) {
+            self.play_entry(entry);
+        }
     }
 
-    pub(crate) fn queue(&self, track: &str) -> usize {
-        if let Ok(mut lock) = self.queue.write() {
-            lock.push(track.to_string());
-            return lock.len();
+    /// Adds the track right after the current track and plays it, the rest of the queue
+    /// follows once it ends
+    pub(crate) fn play_now(&mut self, track_id: &str, queued_by: &str) {
+        match self.add(track_id, queued_by, true) {
+            Some(entry) => self.play_entry(entry),
+            None => log::warn!("could not queue track: {}", track_id),
+        }
+    }
+
+    /// Plays the first entry in the play order for the track
+    pub(crate) fn skip_to(&mut self, track_id: &str) {
+        let repo = self.repo();
+        match self
+            .runtime
+            .block_on(repo.all(self.shuffle))
+            .into_iter()
+            .find(|entry| entry.track_id == track_id)
+        {
+            Some(entry) => self.play_entry(entry),
+            None => log::warn!("track is not in the queue: {}", track_id),
+        }
+    }
+
+    /// Removes the entry at `position` in the play order
+    pub(crate) fn remove(&mut self, position: usize) -> Option<QueueEntity> {
+        let repo = self.repo();
+        let ids = self.runtime.block_on(self.ordered_ids());
+        let index = position.checked_sub(1)?;
+        let id = *ids.get(index)?;
+
+        // The entry before the removed one becomes the current one so that the queue
+        // carries on with the entry after it
+        if self.current == Some(id) {
+            self.current = index.checked_sub(1).and_then(|i| ids.get(i)).copied();
+            self.save_setting(CURRENT_SETTING_NAME, &self.current);
+        }
+
+        let entry = self.runtime.block_on(repo.delete(id))?;
+        self.broadcast(QueueEvent::TrackRemoved {
+            position,
+            track_id: entry.track_id.clone(),
+        });
+        self.refresh_preload();
+
+        Some(entry)
+    }
+
+    /// Moves the entry at `from` to `to`, both positions in the play order
+    pub(crate) fn move_entry(&mut self, from: usize, to: usize) -> bool {
+        let repo = self.repo();
+        let mut ids = self.runtime.block_on(self.ordered_ids());
+
+        if from == 0 || to == 0 || from > ids.len() || to > ids.len() {
+            return false;
+        }
+
+        let id = ids.remove(from - 1);
+        ids.insert(to - 1, id);
+        self.runtime.block_on(repo.reorder(&ids, self.shuffle));
+        self.broadcast(QueueEvent::TrackMoved { from, to });
+        self.refresh_preload();
+
+        true
+    }
+
+    pub(crate) fn play(&self, media: &MediaEntity, track_id: &str) {
+        _ = self.sender.send(PlayerCommand::Play {
+            path: media.path.clone(),
+            track_id: Some(track_id.to_string()),
+            replay_gain: media.metadata.replay_gain,
+        })
+    }
+
+    pub(crate) fn set_repeat(&mut self, mode: RepeatMode) {
+        self.repeat = mode;
+        self.player_state.update(|state| state.repeat = mode);
+        self.save_setting(REPEAT_SETTING_NAME, &mode);
+        _ = self.ws_sender.send(WebsocketMessage::PlayerEvent {
+            event: PlayerEvent::Repeat { mode },
+        });
+        self.refresh_preload();
+    }
+
+    /// Turns shuffling on or off. The track being played stays the current one
+    pub(crate) fn set_shuffle(&mut self, shuffle: bool) {
+        if shuffle && !self.shuffle {
+            let repo = self.repo();
+            self.runtime.block_on(repo.shuffle(self.current));
+        }
+
+        self.shuffle = shuffle;
+        self.player_state.update(|state| state.shuffle = shuffle);
+        self.save_setting(SHUFFLE_SETTING_NAME, &shuffle);
+        _ = self.ws_sender.send(WebsocketMessage::PlayerEvent {
+            event: PlayerEvent::Shuffle { enabled: shuffle },
+        });
+        self.refresh_preload();
+    }
+
+    /// Starts the sleep timer, or cancels it. A track already queued in the player
+    /// to follow the current one is dropped when the timer stops after the current track
+    pub(crate) fn set_sleep_timer(&mut self, timer: Option<SleepTimer>) {
+        if timer.map(|timer| timer.is_last_track()).unwrap_or_default()
+            && self.preloaded.take().is_some()
+        {
+            _ = self.sender.send(PlayerCommand::ClearNext);
+        }
+
+        self.sleep_timer = timer;
+        self.player_state.update(|state| state.sleep_timer = timer);
+        _ = self.ws_sender.send(WebsocketMessage::PlayerEvent {
+            event: PlayerEvent::SleepTimer { timer },
+        });
+    }
+
+    /// Starts fading out once the sleep timer is due
+    pub(crate) fn check_sleep_timer(&mut self) {
+        let Some(timer) = self.sleep_timer else {
+            return;
+        };
+
+        if let Some(fade) = timer.fade_out(self.remaining()) {
+            _ = self.sender.send(PlayerCommand::Stop(fade));
+            self.expire_sleep_timer();
         }
-        0
     }
 
-    pub(crate) fn play(&self, track: &str) {
-        _ = self.sender.send(PlayerCommand::Play(track.to_string()))
+    /// Number of seconds left of the track being played
+    fn remaining(&self) -> Option<f64> {
+        let state = self.player_state.snapshot();
+        (state.status == PlaybackStatus::Playing && state.duration > 0.0)
+            .then(|| (state.duration - state.position).max(0.0))
     }
 
-    fn play_by_index(&self, index: usize) -> bool {
-        if let Ok(lock) = self.queue.read() {
-            if let Some(track) = lock.get(index) {
-                self.play(track);
+    /// Moves the queue along when the track that ended is the one the queue is on.
+    /// Tracks played outside of the queue do not move it.
+    ///
+    /// A track that could not be played is marked as unplayable, and the mark is
+    /// cleared once it plays through. The queue skips to the next entry after a failure
+    /// unless skipping is disabled or every entry has failed
+    pub(crate) fn track_ended(&mut self, ended: TrackEnded) {
+        if let (Some(track_id), false) = (&ended.track_id, ended.upcoming) {
+            if let Some(reason) = &ended.error {
+                log::warn!("marking track {} as unplayable: {}", track_id, reason);
             }
-            true
+            let track_repo = self.db_manager.track_repo();
+            self.runtime
+                .block_on(track_repo.set_unplayable(track_id, ended.error.is_some()));
+        }
+
+        if self.playing.as_deref() != Some(ended.path.as_str()) {
+            return;
+        }
+
+        if ended.upcoming {
+            self.preload();
+            return;
+        }
+
+        if let (Some(timer), None) = (&mut self.sleep_timer, &ended.error) {
+            if timer.track_played() {
+                // When crossfading the end is reported ahead of time, possibly before
+                // the fade out started. What is left of the track fades out now
+                let fade = timer.fade;
+                let fade = self
+                    .remaining()
+                    .map_or(0.0, |remaining| fade.min(remaining));
+                _ = self.sender.send(PlayerCommand::Stop(fade));
+                self.expire_sleep_timer();
+                return;
+            }
+
+            let timer = *timer;
+            self.player_state
+                .update(|state| state.sleep_timer = Some(timer));
+        }
+
+        // The player has already moved on to the preloaded entry
+        if let Some((id, path)) = self.preloaded.take() {
+            self.current = Some(id);
+            self.playing = Some(path);
+            self.save_setting(CURRENT_SETTING_NAME, &self.current);
+            if ended.error.is_none() {
+                self.failures = 0;
+            }
+            return;
+        }
+
+        if ended.error.is_none() {
+            self.failures = 0;
         } else {
-            false
+            self.failures += 1;
+            let count = self.runtime.block_on(self.repo().count()) as usize;
+            if !self.skip_unplayable || self.failures >= count {
+                self.failures = 0;
+                self.playing = None;
+                return;
+            }
         }
-    }
-    fn play_by_index_and_set(&mut self, index: usize) -> bool {
-        if self.play_by_index(index) {
-            *self.current.get_mut() = index;
-            true
+
+        if self.repeat == RepeatMode::One && ended.error.is_none() {
+            self.play_queue();
         } else {
-            false
+            self.next();
+        }
+    }
+
+    /// Sends the entry that follows the current one to the player, so that it
+    /// plays right after the current track without a gap
+    fn preload(&mut self) {
+        if self
+            .sleep_timer
+            .map(|timer| timer.is_last_track())
+            .unwrap_or_default()
+        {
+            return;
+        }
+
+        let entry = self.runtime.block_on(async {
+            if self.repeat == RepeatMode::One {
+                self.current_entry().await
+            } else {
+                self.next_entry().await
+            }
+        });
+        let Some(entry) = entry else {
+            return;
+        };
+
+        let media_repo = self.db_manager.media_repo();
+        if let Some(media) = self
+            .runtime
+            .block_on(media_repo.find_media_by_track(&entry.track_id))
+        {
+            _ = self.sender.send(PlayerCommand::PlayNext {
+                path: media.path.clone(),
+                track_id: Some(entry.track_id.clone()),
+                replay_gain: media.metadata.replay_gain,
+            });
+            self.preloaded = Some((entry.internal_id, media.path));
+        }
+    }
+
+    /// Swaps the entry queued in the player to follow the current track for the one that
+    /// follows it now, after the queue changed
+    fn refresh_preload(&mut self) {
+        if self.preloaded.take().is_some() {
+            _ = self.sender.send(PlayerCommand::ClearNext);
+            self.preload();
+        }
+    }
+
+    /// The entry after the current one, going back to the first one when repeating the queue.
+    /// Entries whose track failed to play before are passed over when skipping them is
+    /// enabled, unless no other entry is left
+    async fn next_entry(&self) -> Option<QueueEntity> {
+        let next = self
+            .entry_after(self.current_entry().await.as_ref())
+            .await?;
+        if !self.skip_unplayable {
+            return Some(next);
+        }
+
+        let mut entry = next.clone();
+        for _ in 0..self.repo().count().await {
+            if !self.is_unplayable(&entry).await {
+                return Some(entry);
+            }
+            log::debug!("skipping unplayable track: {}", &entry.track_id);
+            match self.entry_after(Some(&entry)).await {
+                Some(after) if after.internal_id != next.internal_id => entry = after,
+                _ => break,
+            }
+        }
+
+        Some(next)
+    }
+
+    async fn entry_after(&self, entry: Option<&QueueEntity>) -> Option<QueueEntity> {
+        let repo = self.repo();
+        let next = match entry {
+            Some(entry) => repo.next_after(entry, self.shuffle).await,
+            None => repo.first(self.shuffle).await,
+        };
+
+        match next {
+            None if self.repeat == RepeatMode::All => repo.first(self.shuffle).await,
+            next => next,
         }
     }
+
+    async fn is_unplayable(&self, entry: &QueueEntity) -> bool {
+        self.db_manager
+            .track_repo()
+            .find_by_id(&entry.track_id)
+            .await
+            .map(|track| track.metadata.unplayable)
+            .unwrap_or_default()
+    }
+
+    /// The sleep timer went off. The queue stays on the current entry
+    fn expire_sleep_timer(&mut self) {
+        log::debug!("the sleep timer went off");
+        self.sleep_timer = None;
+        self.preloaded = None;
+        self.playing = None;
+        self.player_state.update(|state| state.sleep_timer = None);
+        _ = self.ws_sender.send(WebsocketMessage::PlayerEvent {
+            event: PlayerEvent::SleepTimerExpired {},
+        });
+    }
+
+    fn repo(&self) -> QueueRepo {
+        self.db_manager.queue_repo()
+    }
+
+    /// IDs of the queue entries in play order
+    async fn ordered_ids(&self) -> Vec<i64> {
+        self.repo()
+            .all(self.shuffle)
+            .await
+            .into_iter()
+            .map(|entry| entry.internal_id)
+            .collect()
+    }
+
+    fn broadcast(&self, event: QueueEvent) {
+        _ = self.ws_sender.send(WebsocketMessage::QueueEvent { event });
+    }
+
+    async fn current_entry(&self) -> Option<QueueEntity> {
+        match self.current {
+            Some(id) => self.repo().find_by_id(id).await,
+            None => None,
+        }
+    }
+
+    fn save_setting<T: serde::Serialize>(&self, name: &str, value: &T) {
+        let settings = self.db_manager.setting_repo();
+        self.runtime.block_on(settings.set(name, value));
+    }
+
+    /// Sends the entry's track to the player and makes it the current entry
+    fn play_entry(&mut self, entry: QueueEntity) {
+        self.preloaded = None;
+        let media_repo = self.db_manager.media_repo();
+        match self
+            .runtime
+            .block_on(media_repo.find_media_by_track(&entry.track_id))
+        {
+            Some(media) => {
+                self.play(&media, &entry.track_id);
+                self.playing = Some(media.path);
+            }
+            None => log::warn!("no media found for queued track: {}", &entry.track_id),
+        }
+
+        self.current = Some(entry.internal_id);
+        self.save_setting(CURRENT_SETTING_NAME, &self.current);
+    }
+
     fn play_queue(&mut self) {
-        self.play_by_index(self.current.load(std::sync::atomic::Ordering::Relaxed));
+        let repo = self.repo();
+        let entry = self.runtime.block_on(async {
+            match self.current_entry().await {
+                Some(entry) => Some(entry),
+                None => repo.first(self.shuffle).await,
+            }
+        });
+
+        if let Some(entry) = entry {
+            self.play_entry(entry);
+        }
     }
 
     fn reset(&mut self) {
-        // TODO: This will do a sql "delete * from queue ...."
-        if let Ok(mut lock) = self.queue.write() {
-            lock.clear();
+        let repo = self.repo();
+        self.runtime.block_on(repo.clear());
+        if self.preloaded.take().is_some() {
+            _ = self.sender.send(PlayerCommand::ClearNext);
         }
+        self.current = None;
+        self.playing = None;
+        self.save_setting(CURRENT_SETTING_NAME, &self.current);
+        self.broadcast(QueueEvent::Cleared {});
     }
 }
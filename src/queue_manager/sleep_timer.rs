@@ -0,0 +1,76 @@
+/// Number of seconds the music fades out over when the timer does not say
+const DEFAULT_FADE_SECONDS: f64 = 10.0;
+/// Number of seconds the fade out of the last track starts early, so that it
+/// completes before the track ends even though the timer is only checked now and then
+const FADE_MARGIN_SECONDS: f64 = 0.5;
+
+/// When the sleep timer stops the music
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "mode")]
+pub(crate) enum SleepTimerMode {
+    /// Stop at the given unix timestamp, in seconds
+    #[serde(rename = "at")]
+    At { at: i64 },
+    /// Stop once the given number of tracks, counting the current one, have played
+    #[serde(rename = "after_tracks")]
+    AfterTracks { tracks: usize },
+    /// Stop once the current track has played
+    #[serde(rename = "after_current")]
+    AfterCurrent,
+}
+
+/// Stops the server player at a given time or after some tracks, fading the
+/// music out
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub(crate) struct SleepTimer {
+    #[serde(flatten)]
+    pub(crate) mode: SleepTimerMode,
+    /// Number of seconds the music fades out over. 0 stops it right away
+    #[serde(default = "default_fade")]
+    pub(crate) fade: f64,
+}
+
+fn default_fade() -> f64 {
+    DEFAULT_FADE_SECONDS
+}
+
+impl SleepTimer {
+    /// True when the timer goes off once the track being played ends
+    pub(crate) fn is_last_track(&self) -> bool {
+        matches!(
+            self.mode,
+            SleepTimerMode::AfterCurrent | SleepTimerMode::AfterTracks { tracks: 0..=1 }
+        )
+    }
+
+    /// Counts a track that has played to the end. Returns true when the timer goes off
+    pub(crate) fn track_played(&mut self) -> bool {
+        match &mut self.mode {
+            SleepTimerMode::At { .. } => false,
+            SleepTimerMode::AfterTracks { tracks } => {
+                *tracks = tracks.saturating_sub(1);
+                *tracks == 0
+            }
+            SleepTimerMode::AfterCurrent => true,
+        }
+    }
+
+    /// Number of seconds the music should start fading out before it stops,
+    /// given how much of the current track is left. `None` while it is too early
+    pub(crate) fn fade_out(&self, remaining: Option<f64>) -> Option<f64> {
+        match self.mode {
+            SleepTimerMode::At { at } => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|duration| duration.as_secs_f64())
+                    .unwrap_or_default();
+                (now >= at as f64).then_some(self.fade)
+            }
+            // The fade ends with the last track
+            _ if self.is_last_track() && self.fade > 0.0 => remaining
+                .filter(|remaining| *remaining <= self.fade + FADE_MARGIN_SECONDS)
+                .map(|remaining| self.fade.min(remaining)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Drives the queue manager against a database of its own, reading the commands it
+//! sends to the player
+
+use std::{path::PathBuf, sync::mpsc, sync::Arc};
+
+use crate::{
+    db::DbManager,
+    entity::{
+        media::{InMediaEntityDto, MediaMetadata},
+        track::InTrackEntityDto,
+    },
+    player::{PlaybackStatus, PlayerCommand, SharedPlayerState, TrackEnded},
+    testing::{setup_test_db, temp_dir},
+    websocket::websocket_message::WebsocketMessage,
+};
+
+use super::{
+    sleep_timer::{SleepTimer, SleepTimerMode},
+    QueueManager, RepeatMode,
+};
+
+struct TestQueue {
+    manager: QueueManager,
+    db_manager: Arc<DbManager>,
+    commands: mpsc::Receiver<PlayerCommand>,
+    state: SharedPlayerState,
+    dir: PathBuf,
+    // Kept so that the queue manager can send its updates
+    _updates: mpsc::Receiver<WebsocketMessage>,
+    // Dropped last, the database and the queue manager run on it
+    runtime: tokio::runtime::Runtime,
+}
+
+impl TestQueue {
+    fn new(skip_unplayable: bool) -> Self {
+        let dir = temp_dir();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let db_manager = runtime.block_on(setup_test_db(&dir));
+
+        let (sender, commands) = mpsc::channel();
+        let (ws_sender, updates) = mpsc::channel();
+        let state = SharedPlayerState::default();
+        let manager = QueueManager::new(
+            sender,
+            ws_sender,
+            db_manager.clone(),
+            state.clone(),
+            runtime.handle().clone(),
+            skip_unplayable,
+        );
+
+        Self {
+            manager,
+            db_manager,
+            commands,
+            state,
+            dir,
+            _updates: updates,
+            runtime,
+        }
+    }
+
+    /// Adds a track to the library and to the queue. Returns the ID of the track
+    fn queue(&mut self, name: &str) -> String {
+        let track_id = self.track(name);
+        self.manager.add(&track_id, "", false).unwrap();
+        track_id
+    }
+
+    /// Adds a track to the library. Returns the ID of the track
+    fn track(&self, name: &str) -> String {
+        let path = self.path(name);
+        self.runtime.block_on(async {
+            let media = self
+                .db_manager
+                .media_repo()
+                .create(InMediaEntityDto::new_from_str(
+                    &format!("{}.wav", name),
+                    "wav",
+                    Some(path),
+                    Some(MediaMetadata::default()),
+                ))
+                .await
+                .unwrap();
+            self.db_manager
+                .track_repo()
+                .create(InTrackEntityDto::new(name, Some(media.id), None))
+                .await
+                .unwrap()
+                .id
+        })
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.dir
+            .join(format!("{}.wav", name))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn set_unplayable(&self, track_id: &str, unplayable: bool) {
+        self.runtime.block_on(
+            self.db_manager
+                .track_repo()
+                .set_unplayable(track_id, unplayable),
+        );
+    }
+
+    fn is_unplayable(&self, track_id: &str) -> bool {
+        self.runtime
+            .block_on(self.db_manager.track_repo().find_by_id(track_id))
+            .unwrap()
+            .metadata
+            .unplayable
+    }
+
+    /// Tells the queue manager that the track of the file `name` ended
+    fn ended(&mut self, name: &str, track_id: &str, error: Option<&str>, upcoming: bool) {
+        self.manager.track_ended(TrackEnded {
+            path: self.path(name),
+            track_id: Some(track_id.to_string()),
+            error: error.map(str::to_string),
+            upcoming,
+        });
+    }
+
+    /// The commands sent to the player since the last call, as `play:<name>`,
+    /// `next:<name>`, `clear` and `stop:<fade>`
+    fn sent(&self) -> Vec<String> {
+        let name = |path: &str| {
+            PathBuf::from(path)
+                .file_stem()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+
+        self.commands
+            .try_iter()
+            .filter_map(|command| match command {
+                PlayerCommand::Play { path, .. } => Some(format!("play:{}", name(&path))),
+                PlayerCommand::PlayNext { path, .. } => Some(format!("next:{}", name(&path))),
+                PlayerCommand::ClearNext => Some("clear".to_string()),
+                PlayerCommand::Stop(fade) => Some(format!("stop:{}", fade)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Drop for TestQueue {
+    fn drop(&mut self) {
+        _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn passes_over_unplayable_tracks() {
+    let mut queue = TestQueue::new(true);
+    let first = queue.queue("first");
+    let second = queue.queue("second");
+    queue.queue("third");
+    queue.set_unplayable(&second, true);
+
+    queue.manager.play_queue();
+    queue.ended("first", &first, None, true);
+    assert_eq!(queue.sent(), ["play:first", "next:third"]);
+}
+
+#[test]
+fn marks_failed_tracks_and_moves_on() {
+    let mut queue = TestQueue::new(true);
+    let first = queue.queue("first");
+    queue.queue("second");
+
+    queue.manager.play_queue();
+    queue.ended("first", &first, Some("broken file"), false);
+    assert!(queue.is_unplayable(&first));
+    assert_eq!(queue.sent(), ["play:first", "play:second"]);
+}
+
+#[test]
+fn plays_unplayable_tracks_when_nothing_else_is_left() {
+    let mut queue = TestQueue::new(true);
+    let first = queue.queue("first");
+    let second = queue.queue("second");
+    queue.set_unplayable(&second, true);
+
+    queue.manager.play_queue();
+    queue.ended("first", &first, None, false);
+    assert_eq!(queue.sent(), ["play:first", "play:second"]);
+}
+
+#[test]
+fn clears_the_mark_once_the_track_plays() {
+    let mut queue = TestQueue::new(true);
+    let first = queue.queue("first");
+    queue.set_unplayable(&first, true);
+
+    queue.manager.skip_to(&first);
+    queue.ended("first", &first, None, false);
+    assert!(!queue.is_unplayable(&first));
+}
+
+#[test]
+fn keeps_unplayable_tracks_when_skipping_is_disabled() {
+    let mut queue = TestQueue::new(false);
+    let first = queue.queue("first");
+    let second = queue.queue("second");
+    queue.set_unplayable(&second, true);
+
+    queue.manager.play_queue();
+    queue.ended("first", &first, None, false);
+    assert_eq!(queue.sent(), ["play:first", "play:second"]);
+}
+
+#[test]
+fn preloads_the_next_entries_as_the_tracks_end() {
+    let mut queue = TestQueue::new(true);
+    let first = queue.queue("first");
+    let second = queue.queue("second");
+    queue.queue("third");
+
+    queue.manager.play_queue();
+    queue.ended("first", &first, None, true);
+    queue.ended("first", &first, None, false);
+    queue.ended("second", &second, None, true);
+    assert_eq!(queue.sent(), ["play:first", "next:second", "next:third"]);
+}
+
+#[test]
+fn preloads_the_same_entry_when_repeating_it() {
+    let mut queue = TestQueue::new(true);
+    let first = queue.queue("first");
+    queue.queue("second");
+
+    queue.manager.play_queue();
+    queue.manager.set_repeat(RepeatMode::One);
+    queue.ended("first", &first, None, true);
+    assert_eq!(queue.sent(), ["play:first", "next:first"]);
+}
+
+#[test]
+fn preloads_again_once_the_queue_changes() {
+    let mut queue = TestQueue::new(true);
+    let first = queue.queue("first");
+    queue.queue("second");
+    queue.queue("third");
+    queue.manager.play_queue();
+
+    // Nothing has been preloaded yet
+    let fourth = queue.queue("fourth");
+    assert_eq!(queue.sent(), ["play:first"]);
+
+    queue.ended("first", &first, None, true);
+    assert_eq!(queue.sent(), ["next:second"]);
+
+    queue.manager.add(&fourth, "", true);
+    assert_eq!(queue.sent(), ["clear", "next:fourth"]);
+
+    queue.manager.remove(2);
+    assert_eq!(queue.sent(), ["clear", "next:second"]);
+
+    queue.manager.move_entry(3, 2);
+    assert_eq!(queue.sent(), ["clear", "next:third"]);
+
+    queue.manager.reset();
+    assert_eq!(queue.sent(), ["clear"]);
+}
+
+#[test]
+fn fades_out_the_last_track_when_its_end_is_reported_early() {
+    let mut queue = TestQueue::new(true);
+    let first = queue.queue("first");
+    queue.queue("second");
+    queue.manager.play_queue();
+    queue.manager.set_sleep_timer(Some(SleepTimer {
+        mode: SleepTimerMode::AfterCurrent,
+        fade: 5.0,
+    }));
+
+    // With a 10 second crossfade the end is reported before the fade out is due
+    queue.state.update(|state| {
+        state.status = PlaybackStatus::Playing;
+        state.duration = 100.0;
+        state.position = 90.0;
+    });
+    queue.manager.check_sleep_timer();
+    queue.ended("first", &first, None, false);
+    assert_eq!(queue.sent(), ["play:first", "stop:5"]);
+
+    // The timer is gone and the queue stays on the last track
+    queue.manager.check_sleep_timer();
+    assert!(queue.sent().is_empty());
+}
+
+#[test]
+fn fades_out_over_what_is_left_of_the_last_track() {
+    let mut queue = TestQueue::new(true);
+    let first = queue.queue("first");
+    queue.manager.play_queue();
+    queue.manager.set_sleep_timer(Some(SleepTimer {
+        mode: SleepTimerMode::AfterTracks { tracks: 1 },
+        fade: 5.0,
+    }));
+
+    queue.state.update(|state| {
+        state.status = PlaybackStatus::Playing;
+        state.duration = 100.0;
+        state.position = 98.0;
+    });
+    queue.ended("first", &first, None, false);
+    assert_eq!(queue.sent(), ["play:first", "stop:2"]);
+}
+
+#[test]
+fn plays_a_track_now_and_carries_on_with_the_queue() {
+    let mut queue = TestQueue::new(true);
+    let first = queue.queue("first");
+    queue.queue("second");
+    queue.manager.play_queue();
+    queue.ended("first", &first, None, true);
+    assert_eq!(queue.sent(), ["play:first", "next:second"]);
+
+    // The preloaded entry is dropped by the player and preloaded again after the new track
+    let other = queue.track("other");
+    queue.manager.play_now(&other, "");
+    assert_eq!(queue.sent(), ["clear", "next:other", "play:other"]);
+
+    queue.ended("other", &other, None, true);
+    assert_eq!(queue.sent(), ["next:second"]);
+}
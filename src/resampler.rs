@@ -6,9 +6,14 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use symphonia::core::audio::{AudioBuffer, AudioBufferRef, Signal, SignalSpec};
-use symphonia::core::conv::{FromSample, IntoSample};
+#[cfg(not(target_os = "linux"))]
+#[cfg(feature = "server-play")]
+use symphonia::core::conv::FromSample;
+use symphonia::core::conv::IntoSample;
 use symphonia::core::sample::Sample;
 
+#[cfg(not(target_os = "linux"))]
+#[cfg(feature = "server-play")]
 pub struct Resampler<T> {
     resampler: rubato::FftFixedIn<f32>,
     input: Vec<Vec<f32>>,
@@ -17,6 +22,8 @@ pub struct Resampler<T> {
     duration: usize,
 }
 
+#[cfg(not(target_os = "linux"))]
+#[cfg(feature = "server-play")]
 impl<T> Resampler<T>
 where
     T: Sample + FromSample<f32> + IntoSample<f32>,
@@ -60,6 +67,8 @@ where
     }
 }
 
+#[cfg(not(target_os = "linux"))]
+#[cfg(feature = "server-play")]
 impl<T> Resampler<T>
 where
     T: Sample + FromSample<f32> + IntoSample<f32>,
@@ -127,6 +136,125 @@ where
     }
 }
 
+/// Resamples a stream to planar samples.
+///
+/// The delay of the resampler is trimmed from the start and the end is cut
+/// to the exact length of the input, so that streams resampled one after the
+/// other join without a gap.
+pub struct PlanarResampler {
+    resampler: rubato::FftFixedIn<f32>,
+    input: Vec<Vec<f32>>,
+    output: Vec<Vec<f32>>,
+    resampled: Vec<Vec<f32>>,
+    duration: usize,
+    ratio: f64,
+    /// Output frames still to be dropped because of the delay of the resampler
+    delay: usize,
+    frames_in: u64,
+    frames_out: u64,
+}
+
+impl PlanarResampler {
+    pub fn new(spec: SignalSpec, to_sample_rate: usize, duration: u64) -> Self {
+        let duration = duration as usize;
+        let num_channels = spec.channels.count();
+
+        let resampler = rubato::FftFixedIn::<f32>::new(
+            spec.rate as usize,
+            to_sample_rate,
+            duration,
+            2,
+            num_channels,
+        )
+        .unwrap();
+
+        let output = rubato::Resampler::output_buffer_allocate(&resampler, true);
+        let delay = rubato::Resampler::output_delay(&resampler);
+
+        Self {
+            resampler,
+            input: vec![Vec::with_capacity(duration); num_channels],
+            output,
+            resampled: vec![Vec::new(); num_channels],
+            duration,
+            ratio: to_sample_rate as f64 / spec.rate as f64,
+            delay,
+            frames_in: 0,
+            frames_out: 0,
+        }
+    }
+
+    /// Resamples a planar input. Returns the samples resampled so far, which
+    /// can be none while the resampler waits for more input.
+    pub fn resample(&mut self, input: AudioBufferRef<'_>) -> &[Vec<f32>] {
+        for channel in self.resampled.iter_mut() {
+            channel.clear();
+        }
+
+        self.frames_in += input.frames() as u64;
+        convert_samples_any(&input, &mut self.input);
+
+        while self.input[0].len() >= self.duration {
+            let (consumed, produced) = rubato::Resampler::process_into_buffer(
+                &mut self.resampler,
+                &self.input,
+                &mut self.output,
+                None,
+            )
+            .unwrap();
+
+            for channel in self.input.iter_mut() {
+                channel.drain(0..consumed);
+            }
+            self.push_output(produced, usize::MAX);
+        }
+
+        &self.resampled
+    }
+
+    /// Resamples the samples left in the resampler. Returns the end of the stream
+    pub fn flush(&mut self) -> &[Vec<f32>] {
+        for channel in self.resampled.iter_mut() {
+            channel.clear();
+        }
+
+        let expected = (self.frames_in as f64 * self.ratio).round() as u64;
+        let mut input = Some(std::mem::take(&mut self.input));
+
+        while self.frames_out < expected {
+            let (_, produced) = rubato::Resampler::process_partial_into_buffer(
+                &mut self.resampler,
+                input.take().as_deref(),
+                &mut self.output,
+                None,
+            )
+            .unwrap();
+
+            if produced == 0 {
+                break;
+            }
+            self.push_output(produced, (expected - self.frames_out) as usize);
+        }
+
+        self.input = vec![Vec::with_capacity(self.duration); self.resampled.len()];
+
+        &self.resampled
+    }
+
+    /// Moves `produced` frames from the output buffer, skipping the delay of the
+    /// resampler and keeping at most `limit` frames
+    fn push_output(&mut self, produced: usize, limit: usize) {
+        let skip = self.delay.min(produced);
+        self.delay -= skip;
+        let count = (produced - skip).min(limit);
+
+        for (resampled, output) in self.resampled.iter_mut().zip(self.output.iter()) {
+            resampled.extend_from_slice(&output[skip..skip + count]);
+        }
+        self.frames_out += count as u64;
+    }
+}
+
 fn convert_samples_any(input: &AudioBufferRef<'_>, output: &mut [Vec<f32>]) {
     match input {
         AudioBufferRef::U8(input) => convert_samples(input, output),
@@ -151,3 +279,37 @@ where
         dst.extend(src.iter().map(|&s| s.into_sample()));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use symphonia::core::audio::AsAudioBufferRef;
+
+    use crate::testing::{audio_buffer, sine};
+
+    use super::PlanarResampler;
+
+    /// Resamples one second in blocks of odd sizes and returns the number of frames
+    fn resample(from: u32, to: u32) -> usize {
+        let samples = sine(from, from as usize, 440.0, 0.5);
+        let first = audio_buffer(from, &[samples[..1].to_vec(), samples[..1].to_vec()]);
+        let mut resampler = PlanarResampler::new(*first.spec(), to as usize, 1024);
+
+        let mut frames = 0;
+        for block in samples.chunks(777) {
+            let buffer = audio_buffer(from, &[block.to_vec(), block.to_vec()]);
+            let resampled = resampler.resample(buffer.as_audio_buffer_ref());
+            assert_eq!(resampled.len(), 2);
+            assert_eq!(resampled[0].len(), resampled[1].len());
+            frames += resampled[0].len();
+        }
+
+        frames + resampler.flush()[0].len()
+    }
+
+    #[test]
+    fn resamples_to_the_exact_length() {
+        assert_eq!(resample(44100, 48000), 48000);
+        assert_eq!(resample(48000, 44100), 44100);
+        assert_eq!(resample(22050, 48000), 48000);
+    }
+}
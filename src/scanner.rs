@@ -1,7 +1,10 @@
-use async_recursion::async_recursion;
+use futures::StreamExt;
 use lofty::MimeType;
-use std::path::PathBuf;
-use tokio::fs::DirEntry;
+use orsomafo::Dispatchable;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use crate::{
     config::Config,
@@ -15,67 +18,310 @@ use crate::{
         media::{InMediaEntityDto, MediaEntity, MediaMetadata, MediaType},
         search::InSearchHitEntityDto,
         track::TrackEntity,
+        waveform::WaveformEntity,
     },
 };
 
+mod analysis;
+mod library_event;
+mod prune;
+mod report;
+mod scan_job;
+mod watch;
+
+pub(crate) use library_event::{LibraryUpdatedEvent, ScanProgressEvent};
+pub(crate) use scan_job::{ScanJob, ScanStatus};
+pub(crate) use watch::watch;
+
+use report::{FileOutcome, FileScan, ScanError, ScanErrorKind, ScanReport};
+
+/// Scans the path for the `scan` command and prints what was done
 pub(crate) async fn scan(path: String, db_manager: &DbManager, config: &Config) {
-    println!("we are about to scan this path: {:?}", path);
-    walk_dir(path.into(), db_manager, config).await
+    let mut report = ScanReport::default();
+    scan_path(PathBuf::from(path), db_manager, config, &mut report).await;
+
+    println!("{}", report);
 }
 
-#[async_recursion(?Send)]
-async fn walk_dir(path: PathBuf, db_manager: &DbManager, config: &Config) {
-    match tokio::fs::read_dir(path).await {
-        Ok(mut entries) => {
-            while let Ok(Some(an_entry)) = entries.next_entry().await {
-                let metadata = an_entry.metadata().await.unwrap();
-                if metadata.is_dir() {
-                    println!("read directory: {:?} ", an_entry.path());
-                    walk_dir(an_entry.path(), db_manager, config).await
-                } else {
-                    process_entry(an_entry, db_manager, config).await;
-                }
-            }
+/// Scans the paths in the background, reporting the progress to the job. The
+/// job must have been started
+pub(crate) async fn run_scan_job(
+    job: ScanJob,
+    paths: Vec<String>,
+    db_manager: Arc<DbManager>,
+    config: Config,
+) {
+    let mut report = ScanReport::for_job(job.clone());
+    for path in paths {
+        scan_path(PathBuf::from(path), &db_manager, &config, &mut report).await;
+    }
+
+    log::info!("{}", report);
+    job.finish(&report);
+
+    if !report.added_tracks.is_empty() || !report.removed_tracks.is_empty() {
+        LibraryUpdatedEvent {
+            added_track_ids: report.added_tracks,
+            removed_track_ids: report.removed_tracks,
         }
-        Err(e) => println!("could not read director: {:?}", e),
+        .dispatch_event();
     }
 }
 
-async fn process_entry(entry: DirEntry, db_manager: &DbManager, config: &Config) {
-    let exts = config.audio_format();
+async fn scan_path(
+    root: PathBuf,
+    db_manager: &DbManager,
+    config: &Config,
+    report: &mut ScanReport,
+) {
+    log::info!("scanning: {:?}", root);
+    let files = find_media_files(root.clone(), config, report).await;
+    process_files(&files, db_manager, config, report).await;
+
+    // When the path cannot be read, a drive that is not mounted for example, every
+    // file would look deleted
+    if tokio::fs::read_dir(&root).await.is_ok() {
+        prune::prune(&[root], db_manager, report).await;
+    } else {
+        log::warn!(
+            "skipping the removal of deleted files, {:?} cannot be read",
+            &root
+        );
+    }
+}
 
-    if let Some(ext) = entry.path().extension() {
-        if exts.contains(&ext.to_str().unwrap()) {
-            let path = entry.path();
-            println!("processing file: {:?}", entry.file_name());
+/// The media files under the folder. The folders and files that cannot be read
+/// are noted in the report
+pub(crate) async fn find_media_files(
+    root: PathBuf,
+    config: &Config,
+    report: &mut ScanReport,
+) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut folders = vec![root];
 
-            let media_metadata = lofty_tag_processor(&path, db_manager, config).await;
+    while let Some(folder) = folders.pop() {
+        let mut entries = match tokio::fs::read_dir(&folder).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                report.error(ScanError::io(&folder, ScanErrorKind::UnreadableFolder, &e));
+                continue;
+            }
+        };
 
-            if let Some(the_media) = db_manager
-                .media_repo()
-                .create_or_update(InMediaEntityDto::new_from_str(
-                    entry.file_name().to_str().unwrap(),
-                    ext.to_str().unwrap(),
-                    Some(entry.path().to_str().unwrap().to_owned()),
-                    Some(media_metadata),
-                ))
-                .await
-            {
-                if the_media.is_audio() {
-                    let add_track_result = add_track(&the_media, db_manager).await;
-                    if add_track_result.1.is_some() {
-                        add_album(
-                            &the_media,
-                            add_track_result.0.as_ref().unwrap(),
-                            add_track_result.1.as_ref().unwrap(),
-                            db_manager,
-                        )
-                        .await;
+        loop {
+            match entries.next_entry().await {
+                Ok(Some(an_entry)) => {
+                    let path = an_entry.path();
+                    match an_entry.file_type().await {
+                        Ok(file_type) if file_type.is_dir() => {
+                            log::debug!("reading directory: {:?}", &path);
+                            folders.push(path);
+                        }
+                        Ok(_) => {
+                            if is_media_file(&path, config) {
+                                files.push(path);
+                            }
+                        }
+                        Err(e) => {
+                            report.error(ScanError::io(&path, ScanErrorKind::UnreadableFile, &e))
+                        }
                     }
                 }
+                Ok(None) => break,
+                Err(e) => {
+                    report.error(ScanError::io(&folder, ScanErrorKind::UnreadableFolder, &e));
+                    break;
+                }
             }
         }
     }
+
+    report.found(files.len());
+    files
+}
+
+fn is_media_file(path: &Path, config: &Config) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| config.audio_format().contains(&ext))
+        .unwrap_or_default()
+}
+
+/// Scans the files, as many at the same time as there are scan workers. Reading
+/// the files happens on the blocking pool, while the database work is interleaved
+pub(crate) async fn process_files(
+    files: &[PathBuf],
+    db_manager: &DbManager,
+    config: &Config,
+    report: &mut ScanReport,
+) {
+    let mut scans = futures::stream::iter(files)
+        .map(|file| process_entry(file, db_manager, config))
+        .buffer_unordered(config.scan_workers());
+
+    while let Some(scan) = scans.next().await {
+        if let Some(scan) = scan {
+            report.record(scan);
+        }
+    }
+}
+
+/// Adds or updates the media of the file. Returns `None` when the file is not a media file
+async fn process_entry(entry: &Path, db_manager: &DbManager, config: &Config) -> Option<FileScan> {
+    if !is_media_file(entry, config) {
+        return None;
+    }
+
+    let (Some(filename), Some(full_path), Some(ext)) = (
+        entry.file_name().and_then(|name| name.to_str()),
+        entry.to_str(),
+        entry.extension().and_then(|ext| ext.to_str()),
+    ) else {
+        return Some(FileScan::failed(ScanError::new(
+            entry,
+            ScanErrorKind::InvalidPath,
+            "the path is not valid UTF-8",
+        )));
+    };
+    let path = entry.to_path_buf();
+    let filename = filename.to_owned();
+    let full_path = full_path.to_owned();
+
+    let file_metadata = match tokio::fs::metadata(&path).await {
+        Ok(file_metadata) => file_metadata,
+        Err(e) => {
+            return Some(FileScan::failed(ScanError::io(
+                entry,
+                ScanErrorKind::UnreadableFile,
+                &e,
+            )))
+        }
+    };
+    let (mtime, size) = file_stats(&file_metadata);
+
+    let existing = db_manager
+        .media_repo()
+        .find_by_filename_and_path(&filename, &full_path)
+        .await;
+    if let Some(media) = &existing {
+        if media.is_unchanged(mtime, size) {
+            log::debug!("skipping unchanged file: {:?}", &path);
+            return Some(FileScan::new(entry, FileOutcome::Unchanged));
+        }
+    }
+
+    let hash = match content_hash(&path).await {
+        Ok(hash) => hash,
+        Err(e) => {
+            return Some(FileScan::failed(ScanError::io(
+                entry,
+                ScanErrorKind::UnreadableFile,
+                &e,
+            )))
+        }
+    };
+
+    // The file was touched without its content changing, or it was moved.
+    // Either way what was read from it before still holds
+    let unchanged = match &existing {
+        Some(media) if media.hash == hash => Some((media.id.clone(), FileOutcome::Unchanged)),
+        Some(_) => None,
+        None => find_moved(&hash, db_manager).await.map(|media| {
+            log::info!("file moved: {:?} -> {:?}", &media.path, &path);
+            (media.id, FileOutcome::Moved)
+        }),
+    };
+
+    if let Some((id, outcome)) = unchanged {
+        db_manager
+            .media_repo()
+            .update_file(&id, &filename, &full_path, mtime, size, &hash)
+            .await;
+        return Some(FileScan::new(entry, outcome));
+    }
+
+    log::debug!("processing file: {:?}", &filename);
+
+    let mut scan = FileScan::new(
+        entry,
+        if existing.is_some() {
+            FileOutcome::Updated
+        } else {
+            FileOutcome::Added
+        },
+    );
+
+    // A file whose tags cannot be read is still imported, under its filename
+    let mut media_metadata = match lofty_tag_processor(&path, db_manager, config).await {
+        Ok(media_metadata) => media_metadata,
+        Err(error) => {
+            scan.errors.push(error);
+            MediaMetadata::default()
+        }
+    };
+    let peaks = analyze_audio(&path, &mut media_metadata).await;
+
+    let mut in_media =
+        InMediaEntityDto::new_from_str(&filename, ext, Some(full_path), Some(media_metadata));
+    in_media.mtime = Some(mtime);
+    in_media.size = Some(size);
+    in_media.hash = Some(hash);
+
+    let Some(the_media) = db_manager.media_repo().create_or_update(in_media).await else {
+        return Some(FileScan::failed(ScanError::new(
+            entry,
+            ScanErrorKind::NotStored,
+            "the media could not be saved",
+        )));
+    };
+
+    if !peaks.is_empty() {
+        db_manager.waveform_repo().set(&the_media.id, &peaks).await;
+    }
+
+    if the_media.is_audio() {
+        let add_track_result = add_track(&the_media, db_manager).await;
+        if let (None, Some(track)) = (&existing, &add_track_result.0) {
+            scan.added_track = Some(track.id.clone());
+        }
+        if let (Some(track), Some(artists)) = &add_track_result {
+            add_album(&the_media, track, artists, db_manager).await;
+        }
+    }
+
+    Some(scan)
+}
+
+/// The modification time, in milliseconds since the epoch, and the size of the file
+fn file_stats(metadata: &std::fs::Metadata) -> (i64, i64) {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default();
+
+    (mtime, metadata.len() as i64)
+}
+
+/// SHA-256 of the content of the file
+async fn content_hash(path: &Path) -> std::io::Result<String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || sha256::try_digest(path.as_path()))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// A media with the same content whose file is gone, which means it was moved
+async fn find_moved(hash: &str, db_manager: &DbManager) -> Option<MediaEntity> {
+    for media in db_manager.media_repo().find_by_hash(hash).await {
+        if !tokio::fs::try_exists(&media.path).await.unwrap_or(true) {
+            return Some(media);
+        }
+    }
+
+    None
 }
 
 async fn add_track(
@@ -202,83 +448,219 @@ async fn add_artist_to_search(artist: &ArtistEntity, db_manager: &DbManager) {
         .await;
 }
 
+/// Decodes the file to fill in the audio properties and the loudness. The
+/// track ReplayGain values are computed from the loudness when the tags have none.
+/// Returns the peaks of the waveform
+async fn analyze_audio(path: &Path, metadata: &mut MediaMetadata) -> Vec<(f32, f32)> {
+    let path = path.to_path_buf();
+    if let Ok(Some(result)) = tokio::task::spawn_blocking(move || analysis::analyze(&path)).await {
+        metadata.duration = result.duration;
+        metadata.sample_rate = result.sample_rate;
+        metadata.channels = result.channels;
+        metadata.bitrate = result.bitrate;
+        metadata.loudness = result.loudness;
+
+        if metadata.replay_gain.track_gain.is_none() {
+            let measured = result.replay_gain();
+            metadata.replay_gain.track_gain = measured.track_gain;
+            metadata.replay_gain.track_peak = measured.track_peak;
+        }
+
+        return result.peaks;
+    }
+
+    Vec::new()
+}
+
+/// Decodes the media to store its waveform. Used for the media scanned
+/// before waveforms were generated
+pub(crate) async fn generate_waveform(
+    media: &MediaEntity,
+    db_manager: &DbManager,
+) -> Option<WaveformEntity> {
+    let path = PathBuf::from(&media.path);
+    let peaks = tokio::task::spawn_blocking(move || analysis::analyze(&path))
+        .await
+        .ok()??
+        .peaks;
+
+    db_manager.waveform_repo().set(&media.id, &peaks).await
+}
+
+/// A picture embedded in the tags
+struct TagPicture {
+    /// `Cover Art (Other)` becomes `cover_art_other`
+    name: String,
+    extension: String,
+    data: Vec<u8>,
+}
+
+/// Reads the tags and stores their pictures as artwork
 async fn lofty_tag_processor(
-    path: &PathBuf,
+    path: &Path,
     db_manager: &DbManager,
     config: &Config,
-) -> MediaMetadata {
-    use lofty::{Probe, TaggedFileExt};
-    let mut metadata = MediaMetadata::default();
-
-    if let Ok(reader) = Probe::open(path) {
-        if let Ok(tagged_file) = reader.read() {
-            let mut option_tag = tagged_file.primary_tag();
-            let tag;
-            if option_tag.is_none() {
-                option_tag = tagged_file.first_tag();
-            }
+) -> Result<MediaMetadata, ScanError> {
+    let file = path.to_path_buf();
+    let (mut metadata, pictures) = match tokio::task::spawn_blocking(move || read_tags(&file)).await
+    {
+        Ok(Ok(tags)) => tags,
+        Ok(Err(e)) => return Err(ScanError::new(path, ScanErrorKind::UnreadableTags, e)),
+        Err(e) => return Err(ScanError::new(path, ScanErrorKind::UnreadableTags, e)),
+    };
 
-            if let Some(t) = option_tag {
-                tag = t;
-            } else {
-                return metadata;
-            }
+    for picture in pictures {
+        let dir = config.artwork_path();
+        let filename = format!("{}{}", sha256::digest(&metadata.album), &picture.extension);
+        let path = format!("{}/{}", dir, filename);
 
-            metadata = MediaMetadata::from(tag);
-
-            for a_picture in tag.pictures() {
-                let extension = if a_picture.mime_type().is_some() {
-                    match a_picture.mime_type().unwrap() {
-                        MimeType::Png => ".png",
-                        MimeType::Jpeg => ".jpg",
-                        MimeType::Tiff => ".jpg",
-                        MimeType::Bmp => ".bmp",
-                        MimeType::Gif => ".gif",
-                        MimeType::Unknown(t) => t.as_str(),
-                        _ => "",
-                    }
-                } else {
-                    ""
-                };
-
-                let pic_type = a_picture.pic_type();
-                let media_type = pic_type.as_ape_key();
-                if !extension.is_empty() && media_type.is_some() {
-                    let dir = config.artwork_path();
-                    let filename = format!("{}{}", sha256::digest(&metadata.album), extension);
-                    let path = format!("{}/{}", dir, filename);
-
-                    // Transforms `Cover Art (Other)` to `cover_art_other`
-                    let pict_type_name = if let Some(t) = media_type {
-                        t.replace(['(', ')'], "").replace(' ', "_").to_lowercase()
-                    } else {
-                        String::new()
-                    };
-
-                    if let Some(media) = db_manager
+        if let Some(media) = db_manager
+            .media_repo()
+            .find_by_filename_and_path(&filename, &path)
+            .await
+        {
+            metadata.pictures.insert(picture.name, media.id);
+        } else if tokio::fs::write(&path, &picture.data).await.is_ok() {
+            let created = db_manager
+                .media_repo()
+                .create_or_update(InMediaEntityDto {
+                    filename: filename.clone(),
+                    media_type: Some(MediaType::Photo),
+                    path: Some(path.clone()),
+                    metadata: None,
+                    mtime: None,
+                    size: None,
+                    hash: None,
+                })
+                .await;
+            let media = match created {
+                // Another file of the album, scanned at the same time, stored it first
+                None => {
+                    db_manager
                         .media_repo()
                         .find_by_filename_and_path(&filename, &path)
                         .await
-                    {
-                        metadata.pictures.insert(pict_type_name, media.id);
-                    } else if tokio::fs::write(&path, a_picture.data()).await.is_ok() {
-                        if let Some(media) = db_manager
-                            .media_repo()
-                            .create_or_update(InMediaEntityDto {
-                                filename,
-                                media_type: Some(MediaType::Photo),
-                                path: Some(path),
-                                metadata: None,
-                            })
-                            .await
-                        {
-                            metadata.pictures.insert(pict_type_name, media.id);
-                        }
-                    };
                 }
+                media => media,
+            };
+
+            if let Some(media) = media {
+                metadata.pictures.insert(picture.name, media.id);
+            }
+        };
+    }
+
+    Ok(metadata)
+}
+
+/// Reads the tags of the file. This blocks, it runs on the blocking pool
+fn read_tags(path: &Path) -> Result<(MediaMetadata, Vec<TagPicture>), lofty::LoftyError> {
+    use lofty::{Probe, TaggedFileExt};
+
+    let tagged_file = Probe::open(path)?.read()?;
+    let Some(tag) = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())
+    else {
+        return Ok((MediaMetadata::default(), Vec::new()));
+    };
+
+    let metadata = MediaMetadata::from(tag);
+    let mut pictures = Vec::new();
+
+    for a_picture in tag.pictures() {
+        let extension = if a_picture.mime_type().is_some() {
+            match a_picture.mime_type().unwrap() {
+                MimeType::Png => ".png",
+                MimeType::Jpeg => ".jpg",
+                MimeType::Tiff => ".jpg",
+                MimeType::Bmp => ".bmp",
+                MimeType::Gif => ".gif",
+                MimeType::Unknown(t) => t.as_str(),
+                _ => "",
+            }
+        } else {
+            ""
+        };
+
+        let pic_type = a_picture.pic_type();
+        if let Some(media_type) = pic_type.as_ape_key() {
+            if !extension.is_empty() {
+                pictures.push(TagPicture {
+                    name: media_type
+                        .replace(['(', ')'], "")
+                        .replace(' ', "_")
+                        .to_lowercase(),
+                    extension: extension.to_string(),
+                    data: a_picture.data().to_vec(),
+                });
             }
         }
     }
 
-    metadata
+    Ok((metadata, pictures))
+}
+
+#[cfg(test)]
+mod tests {
+    use lofty::{Accessor, Tag, TagExt, TagType};
+
+    use crate::{
+        config::ConfigBuilder,
+        testing::{setup_test_db, temp_dir, write_sine_wav},
+    };
+
+    use super::{find_media_files, process_files, report::ScanReport};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scans_the_tracks_of_an_album_concurrently() {
+        let dir = temp_dir();
+        let music = dir.join("music");
+        std::fs::create_dir_all(&music).unwrap();
+        for index in 1..=8 {
+            let path = music.join(format!("{}.wav", index));
+            write_sine_wav(&path, 8000, 1, 0.2, 0.25);
+
+            let mut tag = Tag::new(TagType::Id3v2);
+            tag.set_title(format!("Track {}", index));
+            tag.set_artist("John Coltrane, Lee Morgan".to_string());
+            tag.set_album("Blue Train".to_string());
+            tag.set_year(1957);
+            tag.save_to_path(&path).unwrap();
+        }
+
+        let db_manager = setup_test_db(&dir).await;
+        let config = ConfigBuilder::new().scan_workers(8).build();
+        let mut report = ScanReport::default();
+        let files = find_media_files(music, &config, &mut report).await;
+        process_files(&files, &db_manager, &config, &mut report).await;
+
+        assert_eq!(report.total, 8);
+        assert_eq!(report.added, 8);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+
+        // Every track ends up on the one album and with the same two artists
+        let album = db_manager
+            .album_repo()
+            .find_by_title_and_year("Blue Train", 1957)
+            .await
+            .unwrap();
+        let coltrane = db_manager
+            .artist_repo()
+            .find_by_name("John Coltrane")
+            .await
+            .unwrap();
+        assert_eq!(report.added_tracks.len(), 8);
+        for track_id in &report.added_tracks {
+            let albums = db_manager.album_repo().find_by_track_id(track_id).await;
+            assert_eq!(albums.len(), 1);
+            assert_eq!(albums[0].id, album.id);
+
+            let artists = db_manager.artist_repo().find_by_track_id(track_id).await;
+            assert_eq!(artists.len(), 2);
+            assert!(artists.iter().any(|artist| artist.id == coltrane.id));
+        }
+
+        _ = std::fs::remove_dir_all(dir);
+    }
 }
@@ -0,0 +1,441 @@
+use std::{fs::File, path::Path};
+
+use symphonia::core::{
+    audio::{AudioBuffer, Channels, Signal, SignalSpec},
+    codecs::{DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+use crate::{
+    entity::{
+        media::ReplayGain,
+        waveform::{downsample_peaks, WAVEFORM_RESOLUTION},
+    },
+    player::to_f32_buffer,
+};
+
+/// Loudness ReplayGain brings tracks to, in LUFS
+const REFERENCE_LOUDNESS: f32 = -18.0;
+
+/// What the scanner learns by decoding a file
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Analysis {
+    /// Duration in seconds
+    pub(crate) duration: f64,
+    pub(crate) sample_rate: u32,
+    pub(crate) channels: u32,
+    /// Average bitrate, in bits per second
+    pub(crate) bitrate: u32,
+    /// EBU R128 integrated loudness, in LUFS. Not set when the file is silent
+    pub(crate) loudness: Option<f32>,
+    /// Highest sample value, where 1.0 is full scale
+    pub(crate) peak: f32,
+    /// Lowest and highest sample values across the file, for its waveform
+    pub(crate) peaks: Vec<(f32, f32)>,
+}
+
+impl Analysis {
+    /// ReplayGain track values computed from the measured loudness
+    pub(crate) fn replay_gain(&self) -> ReplayGain {
+        ReplayGain {
+            track_gain: self.loudness.map(|loudness| REFERENCE_LOUDNESS - loudness),
+            track_peak: Some(self.peak),
+            ..Default::default()
+        }
+    }
+}
+
+/// Decodes the whole file. Returns `None` when the file cannot be decoded
+pub(crate) fn analyze(path: &Path) -> Option<Analysis> {
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let source = File::open(path).ok()?;
+    let file_size = source.metadata().map(|meta| meta.len()).unwrap_or_default();
+    let mss = MediaSourceStream::new(Box::new(source), Default::default());
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+
+    let mut reader = probed.format;
+    let track = reader
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .ok()?;
+
+    let mut meter: Option<LoudnessMeter> = None;
+    let mut peaks: Option<PeakMeter> = None;
+    let mut sample_buf = None;
+    let mut frames = 0_u64;
+
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+            // The end of the stream
+            Err(Error::IoError(_)) => break,
+            Err(_) => return None,
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                let buffer = to_f32_buffer(&decoded, &mut sample_buf);
+                frames += buffer.frames() as u64;
+                meter
+                    .get_or_insert_with(|| LoudnessMeter::new(buffer.spec()))
+                    .add(buffer);
+                peaks
+                    .get_or_insert_with(|| PeakMeter::new(buffer.spec()))
+                    .add(buffer);
+            }
+            Err(Error::DecodeError(err)) => log::debug!("skipping bad packet: {}", err),
+            Err(_) => return None,
+        }
+    }
+
+    let meter = meter?;
+    let duration = frames as f64 / meter.sample_rate as f64;
+
+    Some(Analysis {
+        duration,
+        sample_rate: meter.sample_rate,
+        channels: meter.weights.len() as u32,
+        bitrate: if duration > 0.0 {
+            (file_size as f64 * 8.0 / duration) as u32
+        } else {
+            0
+        },
+        loudness: meter.loudness(),
+        peak: meter.peak,
+        peaks: peaks
+            .map(|peaks| downsample_peaks(&peaks.finish(), WAVEFORM_RESOLUTION))
+            .unwrap_or_default(),
+    })
+}
+
+/// Collects the lowest and highest sample values of every 10ms, across all
+/// the channels. They are merged down to the waveform resolution once the
+/// length of the file is known
+#[derive(Debug)]
+struct PeakMeter {
+    block_size: usize,
+    block_frames: usize,
+    block: (f32, f32),
+    blocks: Vec<(f32, f32)>,
+}
+
+impl PeakMeter {
+    const BLOCK_SECONDS: f64 = 0.01;
+
+    fn new(spec: &SignalSpec) -> Self {
+        Self {
+            block_size: ((spec.rate as f64 * Self::BLOCK_SECONDS) as usize).max(1),
+            block_frames: 0,
+            block: (0.0, 0.0),
+            blocks: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, buffer: &AudioBuffer<f32>) {
+        let channels = buffer.spec().channels.count();
+
+        for frame in 0..buffer.frames() {
+            for channel in 0..channels {
+                let sample = buffer.chan(channel)[frame];
+                self.block.0 = self.block.0.min(sample);
+                self.block.1 = self.block.1.max(sample);
+            }
+
+            self.block_frames += 1;
+            if self.block_frames == self.block_size {
+                self.blocks.push(std::mem::take(&mut self.block));
+                self.block_frames = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<(f32, f32)> {
+        if self.block_frames > 0 {
+            self.blocks.push(self.block);
+        }
+
+        self.blocks
+    }
+}
+
+/// A second order IIR filter
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.z[0];
+        self.z[0] = self.b[1] * input - self.a[0] * output + self.z[1];
+        self.z[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+/// The K-weighting filter of ITU-R BS.1770: a high shelf modelling the head
+/// followed by a high pass, for any sample rate
+#[derive(Debug, Clone, Copy)]
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    fn new(rate: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (std::f64::consts::PI * f0 / rate).tan();
+        let vh = 10_f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (std::f64::consts::PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(input))
+    }
+}
+
+/// Measures the integrated loudness as described in EBU R128.
+///
+/// The K-weighted signal is measured in 400ms blocks overlapping by 75%.
+/// Blocks under -70 LUFS, then blocks more than 10 LU under the loudness of
+/// the remaining blocks, are left out so that silences and quiet passages do
+/// not lower the result.
+#[derive(Debug)]
+struct LoudnessMeter {
+    sample_rate: u32,
+    filters: Vec<KWeighting>,
+    /// How much each channel contributes. Surround channels count more and the LFE is ignored
+    weights: Vec<f64>,
+    /// Weighted sum of the squared samples of the 100ms step being filled
+    step: f64,
+    step_frames: usize,
+    step_size: usize,
+    /// Mean square of each completed 100ms step
+    steps: Vec<f64>,
+    peak: f32,
+}
+
+impl LoudnessMeter {
+    const STEP_SECONDS: f64 = 0.1;
+    /// Number of steps in a 400ms block
+    const BLOCK_STEPS: usize = 4;
+    const ABSOLUTE_GATE: f64 = -70.0;
+    const RELATIVE_GATE: f64 = -10.0;
+
+    fn new(spec: &SignalSpec) -> Self {
+        let surround =
+            Channels::SIDE_LEFT | Channels::SIDE_RIGHT | Channels::REAR_LEFT | Channels::REAR_RIGHT;
+        let lfe = Channels::LFE1 | Channels::LFE2;
+        let weights = spec
+            .channels
+            .iter()
+            .map(|channel| {
+                if lfe.contains(channel) {
+                    0.0
+                } else if surround.contains(channel) {
+                    1.41
+                } else {
+                    1.0
+                }
+            })
+            .collect::<Vec<f64>>();
+
+        Self {
+            sample_rate: spec.rate,
+            filters: vec![KWeighting::new(spec.rate as f64); weights.len()],
+            weights,
+            step: 0.0,
+            step_frames: 0,
+            step_size: ((spec.rate as f64 * Self::STEP_SECONDS) as usize).max(1),
+            steps: Vec::new(),
+            peak: 0.0,
+        }
+    }
+
+    fn add(&mut self, buffer: &AudioBuffer<f32>) {
+        let channels = buffer.spec().channels.count().min(self.weights.len());
+
+        for frame in 0..buffer.frames() {
+            for channel in 0..channels {
+                let sample = buffer.chan(channel)[frame];
+                self.peak = self.peak.max(sample.abs());
+                let filtered = self.filters[channel].process(sample as f64);
+                self.step += self.weights[channel] * filtered * filtered;
+            }
+
+            self.step_frames += 1;
+            if self.step_frames == self.step_size {
+                self.steps.push(self.step / self.step_size as f64);
+                self.step = 0.0;
+                self.step_frames = 0;
+            }
+        }
+    }
+
+    fn loudness(&self) -> Option<f32> {
+        let blocks = self
+            .steps
+            .windows(Self::BLOCK_STEPS)
+            .map(|steps| steps.iter().sum::<f64>() / Self::BLOCK_STEPS as f64)
+            .filter(|power| Self::to_lufs(*power) > Self::ABSOLUTE_GATE)
+            .collect::<Vec<f64>>();
+
+        if blocks.is_empty() {
+            return None;
+        }
+
+        let threshold = Self::to_lufs(Self::mean(&blocks)) + Self::RELATIVE_GATE;
+        let gated = blocks
+            .into_iter()
+            .filter(|power| Self::to_lufs(*power) > threshold)
+            .collect::<Vec<f64>>();
+
+        if gated.is_empty() {
+            return None;
+        }
+
+        Some(Self::to_lufs(Self::mean(&gated)) as f32)
+    }
+
+    fn mean(powers: &[f64]) -> f64 {
+        powers.iter().sum::<f64>() / powers.len() as f64
+    }
+
+    fn to_lufs(power: f64) -> f64 {
+        -0.691 + 10.0 * power.log10()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use symphonia::core::audio::{Channels, SignalSpec};
+
+    use crate::testing::{audio_buffer, sine, temp_dir, write_sine_wav};
+
+    use super::{analyze, LoudnessMeter, PeakMeter};
+
+    const RATE: u32 = 48000;
+
+    fn stereo() -> SignalSpec {
+        SignalSpec::new(RATE, Channels::FRONT_LEFT | Channels::FRONT_RIGHT)
+    }
+
+    /// A 1 kHz sine on both channels, `level` dB under full scale
+    fn measure_sine(level: f32, seconds: f64) -> LoudnessMeter {
+        let amplitude = 10_f32.powf(level / 20.0);
+        let samples = sine(RATE, (RATE as f64 * seconds) as usize, 1000.0, amplitude);
+        let mut meter = LoudnessMeter::new(&stereo());
+        for block in samples.chunks(4096) {
+            meter.add(&audio_buffer(RATE, &[block.to_vec(), block.to_vec()]));
+        }
+
+        meter
+    }
+
+    #[test]
+    fn measures_a_reference_sine() {
+        // EBU Tech 3341 case 1: a stereo 1 kHz sine at -23 dBFS measures -23 LUFS
+        let loudness = measure_sine(-23.0, 5.0).loudness().unwrap();
+        assert!((loudness + 23.0).abs() < 0.1, "{}", loudness);
+
+        let loudness = measure_sine(-33.0, 5.0).loudness().unwrap();
+        assert!((loudness + 33.0).abs() < 0.1, "{}", loudness);
+    }
+
+    #[test]
+    fn leaves_silence_out() {
+        let mut meter = measure_sine(-23.0, 5.0);
+        let silence = vec![0.0; RATE as usize * 5];
+        meter.add(&audio_buffer(RATE, &[silence.clone(), silence]));
+
+        // Only the few blocks across the end of the sine count with the silence
+        let loudness = meter.loudness().unwrap();
+        assert!((loudness + 23.0).abs() < 0.2, "{}", loudness);
+        assert!(measure_sine(-100.0, 1.0).loudness().is_none());
+    }
+
+    #[test]
+    fn collects_the_peaks_of_every_block() {
+        let spec = SignalSpec::new(1000, Channels::FRONT_LEFT | Channels::FRONT_RIGHT);
+        let mut meter = PeakMeter::new(&spec);
+        let left = (0..25)
+            .map(|frame| frame as f32 / 100.0)
+            .collect::<Vec<f32>>();
+        let right = left.iter().map(|sample| -sample).collect::<Vec<f32>>();
+        meter.add(&audio_buffer(1000, &[left, right]));
+
+        // Blocks of 10ms are 10 frames at 1 kHz, the last one is partial
+        assert_eq!(
+            meter.finish(),
+            vec![(-0.09, 0.09), (-0.19, 0.19), (-0.24, 0.24)]
+        );
+    }
+
+    #[test]
+    fn analyzes_a_file() {
+        let dir = temp_dir();
+        let path = dir.join("sine.wav");
+        write_sine_wav(&path, 44100, 2, 2.0, 0.5);
+
+        let analysis = analyze(&path).unwrap();
+        assert!((analysis.duration - 2.0).abs() < 0.01);
+        assert_eq!(analysis.sample_rate, 44100);
+        assert_eq!(analysis.channels, 2);
+        assert!((analysis.peak - 0.5).abs() < 0.01);
+        assert!(analysis.loudness.is_some());
+        assert!(!analysis.peaks.is_empty());
+
+        _ = std::fs::remove_dir_all(dir);
+    }
+}
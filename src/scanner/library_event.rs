@@ -0,0 +1,16 @@
+use super::scan_job::ScanStatus;
+
+/// Dispatched when the watcher added tracks to the library or removed some from it
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub(crate) struct LibraryUpdatedEvent {
+    pub(crate) added_track_ids: Vec<String>,
+    pub(crate) removed_track_ids: Vec<String>,
+}
+impl orsomafo::Dispatchable for LibraryUpdatedEvent {}
+
+/// Dispatched as the scan started from the API makes progress
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub(crate) struct ScanProgressEvent {
+    pub(crate) status: ScanStatus,
+}
+impl orsomafo::Dispatchable for ScanProgressEvent {}
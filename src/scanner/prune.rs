@@ -0,0 +1,78 @@
+use std::path::{Path, PathBuf};
+
+use crate::db::DbManager;
+
+use super::report::ScanReport;
+
+/// Removes the media under the roots whose file is gone, along with their tracks.
+/// Then removes the albums and artists left without tracks
+pub(crate) async fn prune(roots: &[PathBuf], db_manager: &DbManager, report: &mut ScanReport) {
+    for media in db_manager.media_repo().find_audio().await {
+        let path = Path::new(&media.path);
+        if !roots.iter().any(|root| path.starts_with(root)) {
+            continue;
+        }
+
+        // A file that cannot be checked, for lack of permission for example, is kept
+        if tokio::fs::try_exists(&media.path).await.unwrap_or(true) {
+            continue;
+        }
+
+        log::info!("file removed: {:?}", &media.path);
+        for track in db_manager.track_repo().find_by_media_id(&media.id).await {
+            remove_track(&track.id, db_manager, report).await;
+        }
+        db_manager
+            .waveform_repo()
+            .delete_by_media_id(&media.id)
+            .await;
+        if db_manager.media_repo().delete(&media.id).await.is_some() {
+            report.media_removed += 1;
+        }
+    }
+
+    for album in db_manager.album_repo().find_without_tracks().await {
+        db_manager
+            .album_artist_repo()
+            .delete_by_album_id(&album.id)
+            .await;
+        if db_manager.album_repo().delete(&album.id).await.is_some() {
+            report.albums_removed += 1;
+        }
+    }
+
+    for artist in db_manager.artist_repo().find_without_tracks().await {
+        db_manager
+            .album_artist_repo()
+            .delete_by_artist_id(&artist.id)
+            .await;
+        if db_manager.artist_repo().delete(&artist.id).await.is_some() {
+            report.artists_removed += 1;
+        }
+    }
+
+    // The deleted events take care of the search hits while the app runs. This
+    // catches the ones left behind when the scan exits before they are handled
+    report.search_hits_removed = db_manager.search_repo().delete_orphans().await;
+}
+
+async fn remove_track(track_id: &str, db_manager: &DbManager, report: &mut ScanReport) {
+    db_manager
+        .album_track_repo()
+        .delete_by_track_id(track_id)
+        .await;
+    db_manager
+        .artist_track_repo()
+        .delete_by_track_id(track_id)
+        .await;
+    report.playlist_entries_removed += db_manager
+        .playlist_track_repo()
+        .delete_by_track_id(track_id)
+        .await
+        .len() as u64;
+    report.queue_entries_removed += db_manager.queue_repo().delete_by_track_id(track_id).await;
+
+    if let Some(track) = db_manager.track_repo().delete(track_id).await {
+        report.removed_tracks.push(track.id);
+    }
+}
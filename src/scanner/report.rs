@@ -0,0 +1,196 @@
+use std::{fmt::Display, path::Path};
+
+use super::scan_job::ScanJob;
+
+/// What happened to a file during a scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileOutcome {
+    Added,
+    Updated,
+    /// The file was moved, only its location was updated
+    Moved,
+    Unchanged,
+    /// The file could not be read or stored
+    Failed,
+}
+
+/// What went wrong while scanning a file or a folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub(crate) enum ScanErrorKind {
+    #[serde(rename = "permission_denied")]
+    PermissionDenied,
+    /// The path is not valid UTF-8, it cannot be stored
+    #[serde(rename = "invalid_path")]
+    InvalidPath,
+    #[serde(rename = "unreadable_folder")]
+    UnreadableFolder,
+    #[serde(rename = "unreadable_file")]
+    UnreadableFile,
+    /// The file was imported without its tags
+    #[serde(rename = "unreadable_tags")]
+    UnreadableTags,
+    #[serde(rename = "not_stored")]
+    NotStored,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub(crate) struct ScanError {
+    pub(crate) path: String,
+    pub(crate) kind: ScanErrorKind,
+    pub(crate) message: String,
+}
+
+impl ScanError {
+    pub(crate) fn new(path: &Path, kind: ScanErrorKind, message: impl Display) -> Self {
+        Self {
+            path: path.to_string_lossy().to_string(),
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    /// An I/O error, `kind` is used unless the permission was denied
+    pub(crate) fn io(path: &Path, kind: ScanErrorKind, error: &std::io::Error) -> Self {
+        let kind = if error.kind() == std::io::ErrorKind::PermissionDenied {
+            ScanErrorKind::PermissionDenied
+        } else {
+            kind
+        };
+
+        Self::new(path, kind, error)
+    }
+}
+
+/// What scanning a file did
+#[derive(Debug)]
+pub(crate) struct FileScan {
+    pub(crate) path: String,
+    pub(crate) outcome: FileOutcome,
+    /// The ID of the track, when the file was added
+    pub(crate) added_track: Option<String>,
+    /// The file may have been imported despite them
+    pub(crate) errors: Vec<ScanError>,
+}
+
+impl FileScan {
+    pub(crate) fn new(path: &Path, outcome: FileOutcome) -> Self {
+        Self {
+            path: path.to_string_lossy().to_string(),
+            outcome,
+            added_track: None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub(crate) fn failed(error: ScanError) -> Self {
+        Self {
+            path: error.path.clone(),
+            outcome: FileOutcome::Failed,
+            added_track: None,
+            errors: vec![error],
+        }
+    }
+}
+
+/// Counts of what a scan did, printed once it is done
+#[derive(Debug, Default)]
+pub(crate) struct ScanReport {
+    /// Media files found under the folders scanned
+    pub(crate) total: u64,
+    pub(crate) files: u64,
+    pub(crate) added: u64,
+    pub(crate) updated: u64,
+    pub(crate) moved: u64,
+    pub(crate) unchanged: u64,
+    pub(crate) failed: u64,
+    pub(crate) errors: Vec<ScanError>,
+    /// IDs of the tracks of the files added
+    pub(crate) added_tracks: Vec<String>,
+    /// Media whose file is gone
+    pub(crate) media_removed: u64,
+    /// IDs of the tracks removed with their media
+    pub(crate) removed_tracks: Vec<String>,
+    pub(crate) albums_removed: u64,
+    pub(crate) artists_removed: u64,
+    pub(crate) playlist_entries_removed: u64,
+    pub(crate) queue_entries_removed: u64,
+    pub(crate) search_hits_removed: u64,
+    /// The job the progress is reported to, for the scans started from the API
+    pub(crate) job: Option<ScanJob>,
+}
+
+impl ScanReport {
+    pub(crate) fn for_job(job: ScanJob) -> Self {
+        Self {
+            job: Some(job),
+            ..Default::default()
+        }
+    }
+
+    /// Notes the media files found, before they are scanned
+    pub(crate) fn found(&mut self, count: usize) {
+        self.total += count as u64;
+
+        if let Some(job) = &self.job {
+            job.progress(self, None);
+        }
+    }
+
+    /// Notes an error that is not about a single file, a folder that cannot be read for example
+    pub(crate) fn error(&mut self, error: ScanError) {
+        log::warn!("{}: {}", &error.path, &error.message);
+        self.errors.push(error);
+    }
+
+    pub(crate) fn record(&mut self, scan: FileScan) {
+        self.files += 1;
+        match scan.outcome {
+            FileOutcome::Added => self.added += 1,
+            FileOutcome::Updated => self.updated += 1,
+            FileOutcome::Moved => self.moved += 1,
+            FileOutcome::Unchanged => self.unchanged += 1,
+            FileOutcome::Failed => self.failed += 1,
+        }
+        self.added_tracks.extend(scan.added_track);
+        for error in scan.errors {
+            self.error(error);
+        }
+
+        if let Some(job) = &self.job {
+            job.progress(self, Some(&scan.path));
+        }
+    }
+}
+
+impl Display for ScanReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "scan summary:")?;
+        writeln!(
+            f,
+            "  files: {} ({} added, {} updated, {} moved, {} unchanged, {} failed)",
+            self.files, self.added, self.updated, self.moved, self.unchanged, self.failed
+        )?;
+        writeln!(
+            f,
+            "  removed: {} media, {} tracks, {} albums, {} artists",
+            self.media_removed,
+            self.removed_tracks.len(),
+            self.albums_removed,
+            self.artists_removed
+        )?;
+        write!(
+            f,
+            "  cleaned up: {} playlist entries, {} queue entries, {} stale search hits",
+            self.playlist_entries_removed, self.queue_entries_removed, self.search_hits_removed
+        )?;
+
+        if !self.errors.is_empty() {
+            write!(f, "\n  errors: {}", self.errors.len())?;
+            for error in &self.errors {
+                write!(f, "\n    {}: {}", &error.path, &error.message)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use orsomafo::Dispatchable;
+
+use crate::helper::generate_id;
+
+use super::{
+    library_event::ScanProgressEvent,
+    report::{ScanError, ScanReport},
+};
+
+/// Least time between two progress events, so that scanning many small files
+/// does not flood the websocket
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// Number of errors sent with the progress events. The full list is returned by the API
+const PUBLISHED_ERRORS: usize = 10;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub(crate) enum ScanState {
+    /// No scan was started since the application started
+    #[default]
+    #[serde(rename = "idle")]
+    Idle,
+    #[serde(rename = "running")]
+    Running,
+    #[serde(rename = "finished")]
+    Finished,
+}
+
+/// Progress of the scan started from the API
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
+pub(crate) struct ScanStatus {
+    pub(crate) id: Option<String>,
+    pub(crate) state: ScanState,
+    pub(crate) paths: Vec<String>,
+    /// The file being scanned
+    pub(crate) current_path: Option<String>,
+    /// Media files found so far, the scan is done with `files` of them
+    pub(crate) total: u64,
+    pub(crate) files: u64,
+    pub(crate) added: u64,
+    pub(crate) updated: u64,
+    pub(crate) moved: u64,
+    pub(crate) unchanged: u64,
+    /// Files that could not be read or stored
+    pub(crate) failed: u64,
+    /// Media removed because their file is gone
+    pub(crate) removed: u64,
+    /// Number of things that went wrong with the files and the folders
+    pub(crate) error_count: u64,
+    /// What went wrong with the files and the folders. Progress events only
+    /// carry the latest errors
+    pub(crate) errors: Vec<ScanError>,
+    /// Seconds since the epoch
+    pub(crate) started_at: Option<i64>,
+    pub(crate) finished_at: Option<i64>,
+}
+
+impl ScanStatus {
+    fn count(&mut self, report: &ScanReport) {
+        self.total = report.total;
+        self.files = report.files;
+        self.added = report.added;
+        self.updated = report.updated;
+        self.moved = report.moved;
+        self.unchanged = report.unchanged;
+        self.failed = report.failed;
+        self.removed = report.media_removed;
+        self.error_count = report.errors.len() as u64;
+        if let Some(new_errors) = report.errors.get(self.errors.len()..) {
+            self.errors.extend_from_slice(new_errors);
+        }
+    }
+
+    /// A copy of the status for the progress events, with the latest errors only
+    fn summary(&mut self) -> ScanStatus {
+        let errors = std::mem::take(&mut self.errors);
+        let mut summary = self.clone();
+        summary.errors = errors[errors.len().saturating_sub(PUBLISHED_ERRORS)..].to_vec();
+        self.errors = errors;
+
+        summary
+    }
+}
+
+#[derive(Debug, Default)]
+struct ScanJobState {
+    status: ScanStatus,
+    published_at: Option<Instant>,
+}
+
+/// The scan started from the API. Only one runs at a time
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ScanJob(Arc<RwLock<ScanJobState>>);
+
+impl ScanJob {
+    pub(crate) fn status(&self) -> ScanStatus {
+        self.0
+            .read()
+            .map(|state| state.status.clone())
+            .unwrap_or_default()
+    }
+
+    /// Marks a scan of the paths as running. Returns `None` when a scan is already running
+    pub(crate) fn start(&self, paths: Vec<String>) -> Option<ScanStatus> {
+        let mut state = self.0.write().ok()?;
+        if state.status.state == ScanState::Running {
+            return None;
+        }
+
+        state.status = ScanStatus {
+            id: Some(generate_id()),
+            state: ScanState::Running,
+            paths,
+            started_at: Some(now()),
+            ..Default::default()
+        };
+        state.published_at = Some(Instant::now());
+        let status = state.status.clone();
+        drop(state);
+
+        publish(status.clone());
+        Some(status)
+    }
+
+    /// Takes the counts of the report. They are published every so often
+    pub(crate) fn progress(&self, report: &ScanReport, current_path: Option<&str>) {
+        let Ok(mut state) = self.0.write() else {
+            return;
+        };
+
+        state.status.count(report);
+        if let Some(path) = current_path {
+            state.status.current_path = Some(path.to_string());
+        }
+
+        if state
+            .published_at
+            .map(|at| at.elapsed() >= PROGRESS_INTERVAL)
+            .unwrap_or(true)
+        {
+            state.published_at = Some(Instant::now());
+            let status = state.status.summary();
+            drop(state);
+            publish(status);
+        }
+    }
+
+    pub(crate) fn finish(&self, report: &ScanReport) {
+        let Ok(mut state) = self.0.write() else {
+            return;
+        };
+
+        state.status.count(report);
+        state.status.state = ScanState::Finished;
+        state.status.current_path = None;
+        state.status.finished_at = Some(now());
+        let status = state.status.summary();
+        drop(state);
+
+        publish(status);
+    }
+}
+
+fn publish(status: ScanStatus) {
+    ScanProgressEvent { status }.dispatch_event();
+}
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::scanner::report::{ScanError, ScanErrorKind, ScanReport};
+
+    use super::{ScanStatus, PUBLISHED_ERRORS};
+
+    fn report_with_errors(count: usize) -> ScanReport {
+        let mut report = ScanReport::default();
+        for index in 0..count {
+            report.errors.push(ScanError::new(
+                Path::new(&format!("/music/{}.flac", index)),
+                ScanErrorKind::UnreadableFile,
+                "unreadable",
+            ));
+        }
+
+        report
+    }
+
+    #[test]
+    fn publishes_the_latest_errors_only() {
+        let mut status = ScanStatus::default();
+        status.count(&report_with_errors(3));
+        status.count(&report_with_errors(25));
+
+        let summary = status.summary();
+        assert_eq!(summary.error_count, 25);
+        assert_eq!(summary.errors.len(), PUBLISHED_ERRORS);
+        assert_eq!(summary.errors[0].path, "/music/15.flac");
+        assert_eq!(summary.errors[PUBLISHED_ERRORS - 1].path, "/music/24.flac");
+
+        // The status returned by the API keeps every error
+        assert_eq!(status.errors.len(), 25);
+        assert_eq!(status.errors[3].path, "/music/3.flac");
+    }
+
+    #[test]
+    fn publishes_every_error_when_there_are_few() {
+        let mut status = ScanStatus::default();
+        status.count(&report_with_errors(2));
+
+        let summary = status.summary();
+        assert_eq!(summary.error_count, 2);
+        assert_eq!(summary.errors.len(), 2);
+    }
+}
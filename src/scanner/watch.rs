@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use orsomafo::Dispatchable;
+use tokio::sync::mpsc;
+
+use crate::{config::Config, db::DbManager};
+
+use super::{
+    find_media_files, library_event::LibraryUpdatedEvent, process_files, prune, report::ScanReport,
+    scan_path,
+};
+
+/// How long a path has to go without events before it is processed. Copying a
+/// file fires events until the copy is done, the file is read once
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Watches the library folders and imports the files created, modified or
+/// moved under them. The media of the deleted files are removed
+pub(crate) async fn watch(db_manager: Arc<DbManager>, config: Config) {
+    let roots = config
+        .library_paths()
+        .into_iter()
+        .map(PathBuf::from)
+        .collect::<Vec<PathBuf>>();
+    if roots.is_empty() {
+        log::warn!("no library folder to watch, set PARTY_LIBRARY_PATHS or pass --path");
+        return;
+    }
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = match notify::recommended_watcher(move |result| {
+        _ = sender.send(result);
+    }) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            log::error!("could not watch the library: {}", e);
+            return;
+        }
+    };
+
+    let mut watched = Vec::new();
+    for root in roots {
+        match watcher.watch(&root, RecursiveMode::Recursive) {
+            Ok(()) => {
+                log::info!("watching: {:?}", &root);
+                watched.push(root);
+            }
+            Err(e) => log::error!("could not watch {:?}: {}", &root, e),
+        }
+    }
+    if watched.is_empty() {
+        return;
+    }
+
+    // Catch up with what changed while nothing was watching
+    let mut report = ScanReport::default();
+    for root in &watched {
+        scan_path(root.clone(), &db_manager, &config, &mut report).await;
+    }
+    log::info!("{}", report);
+    notify_library_updated(report);
+
+    // The paths that changed, with the time of their last event
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    let mut tick = tokio::time::interval(DEBOUNCE / 4);
+
+    loop {
+        tokio::select! {
+            received = receiver.recv() => match received {
+                Some(Ok(event)) => {
+                    for path in changed_paths(event, &watched) {
+                        pending.insert(path, Instant::now());
+                    }
+                }
+                Some(Err(e)) => log::error!("library watcher error: {}", e),
+                None => break,
+            },
+            _ = tick.tick() => {
+                let now = Instant::now();
+                let mut ready = pending
+                    .iter()
+                    .filter(|(_, last)| now.duration_since(**last) >= DEBOUNCE)
+                    .map(|(path, _)| path.clone())
+                    .collect::<Vec<PathBuf>>();
+                if ready.is_empty() {
+                    continue;
+                }
+
+                ready.sort();
+                for path in &ready {
+                    pending.remove(path);
+                }
+                import(ready, &db_manager, &config).await;
+            }
+        }
+    }
+}
+
+/// The paths an event is about. All the folders are scanned again when the
+/// watcher missed events
+fn changed_paths(event: Event, roots: &[PathBuf]) -> Vec<PathBuf> {
+    if event.need_rescan() {
+        return roots.to_vec();
+    }
+
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => event.paths,
+        _ => Vec::new(),
+    }
+}
+
+/// Imports the paths that changed and removes the ones that are gone. The
+/// existing paths go first, so that a moved file is found at its new place
+/// before its old place is seen missing
+async fn import(paths: Vec<PathBuf>, db_manager: &DbManager, config: &Config) {
+    let mut report = ScanReport::default();
+    let mut files = Vec::new();
+    let mut gone = Vec::new();
+
+    for path in paths {
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_dir() => {
+                files.extend(find_media_files(path, config, &mut report).await)
+            }
+            Ok(_) => files.push(path),
+            Err(_) => gone.push(path),
+        }
+    }
+    process_files(&files, db_manager, config, &mut report).await;
+
+    if !gone.is_empty() {
+        prune::prune(&gone, db_manager, &mut report).await;
+    }
+
+    if report.files == 0 && report.media_removed == 0 && report.errors.is_empty() {
+        return;
+    }
+    log::info!("{}", report);
+    notify_library_updated(report);
+}
+
+/// Lets the rest of the server know about the tracks added and removed
+fn notify_library_updated(report: ScanReport) {
+    if !report.added_tracks.is_empty() || !report.removed_tracks.is_empty() {
+        LibraryUpdatedEvent {
+            added_track_ids: report.added_tracks,
+            removed_track_ids: report.removed_tracks,
+        }
+        .dispatch_event();
+    }
+}
@@ -68,6 +68,9 @@ async fn seed_tracks(db_manager: &DbManager, total: u64) {
             path,
             media_type: Some(MediaType::Audio),
             metadata: None,
+            mtime: None,
+            size: None,
+            hash: None,
         };
 
         if let Some(media) = media_repo.create_or_update(media).await {
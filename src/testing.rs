@@ -0,0 +1,92 @@
+//! Helpers shared by the tests
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use symphonia::core::audio::{AudioBuffer, Channels, Signal, SignalSpec};
+
+use crate::{
+    config::ConfigBuilder,
+    db::{setup_db_connection, DbManager},
+    helper::generate_id,
+};
+
+/// A new empty folder under the temporary folder of the system
+pub(crate) fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("party_chrasher_{}", generate_id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A database of its own in the folder, with the tables set up. Events are
+/// dispatched to no one
+pub(crate) async fn setup_test_db(dir: &Path) -> Arc<DbManager> {
+    orsomafo::setup().await;
+    let config = ConfigBuilder::new().db_path(dir.to_str().unwrap()).build();
+    let db_manager = setup_db_connection(&config).await;
+    db_manager.setup_db().await;
+
+    db_manager
+}
+
+/// Samples of a sine wave
+pub(crate) fn sine(rate: u32, frames: usize, frequency: f32, amplitude: f32) -> Vec<f32> {
+    (0..frames)
+        .map(|frame| {
+            let time = frame as f32 / rate as f32;
+            amplitude * (2.0 * std::f32::consts::PI * frequency * time).sin()
+        })
+        .collect()
+}
+
+/// Writes a 16 bit WAV file of a sine wave, the same on every channel.
+/// Returns the number of frames written
+pub(crate) fn write_sine_wav(
+    path: &Path,
+    rate: u32,
+    channels: u16,
+    seconds: f64,
+    amplitude: f32,
+) -> usize {
+    let frames = (rate as f64 * seconds) as usize;
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate: rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for sample in sine(rate, frames, 440.0, amplitude) {
+        for _ in 0..channels {
+            writer
+                .write_sample((sample * i16::MAX as f32) as i16)
+                .unwrap();
+        }
+    }
+    writer.finalize().unwrap();
+
+    frames
+}
+
+/// A buffer holding the samples of each channel
+pub(crate) fn audio_buffer(rate: u32, planes: &[Vec<f32>]) -> AudioBuffer<f32> {
+    let channels = Channels::from_bits_truncate((1 << planes.len()) - 1);
+    let frames = planes.first().map(Vec::len).unwrap_or_default();
+    let mut buffer = AudioBuffer::new(frames.max(1) as u64, SignalSpec::new(rate, channels));
+    buffer.render_silence(Some(frames));
+    for (channel, samples) in planes.iter().enumerate() {
+        buffer.chan_mut(channel).copy_from_slice(samples);
+    }
+
+    buffer
+}
+
+/// The samples of each channel of the buffer
+pub(crate) fn planes(buffer: &AudioBuffer<f32>) -> Vec<Vec<f32>> {
+    (0..buffer.spec().channels.count())
+        .map(|channel| buffer.chan(channel).to_vec())
+        .collect()
+}
@@ -1,15 +1,19 @@
 #![allow(dead_code, unused)]
 
+use std::sync::Arc;
+
 use crate::{
     cli,
     config::Config,
-    player::{self, PlayerCommand},
+    db::DbManager,
+    player::{self, LiveStream, PlayerCommand, SharedPlayerState, TrackEnded},
     queue_manager::{self, QueueManagerCommand},
     websocket::websocket_message::WebsocketMessage,
 };
 
 pub(crate) fn setup_threads(
     config: &Config,
+    db_manager: Arc<DbManager>,
 ) -> (
     tokio::sync::mpsc::UnboundedReceiver<WebsocketMessage>,
     std::sync::mpsc::Sender<PlayerCommand>,
@@ -17,9 +21,33 @@ pub(crate) fn setup_threads(
 ) {
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<WebsocketMessage>();
     let update_tx = setup_progress_thread(tx);
-    let cmd_tx = setup_player_thread(update_tx);
+    let (ended_tx, ended_rx) = std::sync::mpsc::channel::<TrackEnded>();
+
+    let player_state = SharedPlayerState::default();
+    busybody::helpers::service_container().set_type(player_state.clone());
+
+    let live = LiveStream::new(player::output_spec(
+        config.output_sample_rate(),
+        config.output_channels(),
+    ));
+    busybody::helpers::service_container().set_type(live.clone());
+
+    let cmd_tx = setup_player_thread(
+        update_tx.clone(),
+        ended_tx,
+        player_state.clone(),
+        live,
+        config.clone(),
+    );
 
-    let manager_tx = queue_manager::setup_queue_manager(cmd_tx.clone());
+    let manager_tx = queue_manager::setup_queue_manager(
+        cmd_tx.clone(),
+        ended_rx,
+        update_tx,
+        db_manager,
+        player_state,
+        config,
+    );
 
     #[cfg(feature = "server-play")]
     if config.is_cli_enabled() {
@@ -46,11 +74,15 @@ fn setup_progress_thread(
 
 fn setup_player_thread(
     progress_tx: std::sync::mpsc::Sender<WebsocketMessage>,
+    ended_tx: std::sync::mpsc::Sender<TrackEnded>,
+    player_state: SharedPlayerState,
+    live: LiveStream,
+    config: Config,
 ) -> std::sync::mpsc::Sender<PlayerCommand> {
     let (sender, receiver) = std::sync::mpsc::channel::<PlayerCommand>();
 
     std::thread::spawn(move || {
-        player::handle_request(receiver, progress_tx);
+        player::handle_request(receiver, progress_tx, ended_tx, player_state, live, config);
     });
 
     sender
@@ -0,0 +1,577 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use mp3lame_encoder::{Bitrate, DualPcm, FlushNoGap, MonoPcm};
+
+use symphonia::core::{
+    audio::{AudioBuffer, Signal},
+    codecs::{DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+use crate::player::{output_spec, to_f32_buffer, Converter};
+
+mod cache;
+
+pub(crate) use cache::TranscodeCache;
+
+/// Sample rates tried, from the best, to fit PCM under the maximum bitrate.
+/// Under the last one PCM sounds worse than a compressed format at the same bitrate
+const SAMPLE_RATES: [u32; 5] = [48000, 44100, 32000, 24000, 22050];
+const BITS_PER_SAMPLE: u16 = 16;
+/// Highest sample rate handed to the MP3 encoder, which lowers it further for low bitrates
+const MP3_MAX_SAMPLE_RATE: u32 = 48000;
+/// Bitrates the MP3 encoder supports, in kbps, from the best
+const MP3_BITRATES: [(u32, Bitrate); 13] = [
+    (320, Bitrate::Kbps320),
+    (256, Bitrate::Kbps256),
+    (224, Bitrate::Kbps224),
+    (192, Bitrate::Kbps192),
+    (160, Bitrate::Kbps160),
+    (128, Bitrate::Kbps128),
+    (112, Bitrate::Kbps112),
+    (96, Bitrate::Kbps96),
+    (80, Bitrate::Kbps80),
+    (64, Bitrate::Kbps64),
+    (48, Bitrate::Kbps48),
+    (40, Bitrate::Kbps40),
+    (32, Bitrate::Kbps32),
+];
+/// Bitrate of MP3 files when no maximum is given, in kbps
+const MP3_DEFAULT_BITRATE: u32 = 192;
+
+/// The formats files can be transcoded to
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TranscodeFormat {
+    /// Constant bitrate MP3, which every browser can play
+    #[default]
+    Mp3,
+    /// 16 bit PCM, lossless but large
+    Wav,
+}
+
+impl TranscodeFormat {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            Self::Mp3 => "mp3",
+            Self::Wav => "wav",
+        }
+    }
+
+    /// Lowest maximum bitrate the format can be sent under, in kbps
+    pub(crate) fn min_bitrate(&self) -> u32 {
+        match self {
+            Self::Mp3 => MP3_BITRATES[MP3_BITRATES.len() - 1].0,
+            Self::Wav => pcm_bitrate(SAMPLE_RATES[SAMPLE_RATES.len() - 1], 1) as u32,
+        }
+    }
+}
+
+impl FromStr for TranscodeFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.trim().to_lowercase().as_str() {
+            "mp3" => Ok(Self::Mp3),
+            "wav" => Ok(Self::Wav),
+            _ => Err(format!(
+                "cannot transcode to {}, supported formats: mp3, wav",
+                name
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TranscodeOptions {
+    pub(crate) format: TranscodeFormat,
+    /// Maximum bitrate in kbps. MP3 files are encoded at the highest bitrate under it.
+    /// For WAV files the sample rate and then the channels are lowered until the output fits
+    pub(crate) max_bitrate: Option<u32>,
+}
+
+impl TranscodeOptions {
+    /// Checks that the format can be sent under the maximum bitrate
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let min_bitrate = self.format.min_bitrate();
+        match self.max_bitrate {
+            Some(max_bitrate) if max_bitrate < min_bitrate => Err(format!(
+                "{} cannot be sent under {} kbps",
+                self.format.extension(),
+                min_bitrate
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum TranscodeError {
+    /// The source cannot be decoded
+    Unsupported(String),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsupported(reason) => write!(f, "cannot transcode the file: {}", reason),
+            Self::Io(err) => write!(f, "could not write the transcoded file: {}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for TranscodeError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<hound::Error> for TranscodeError {
+    fn from(err: hound::Error) -> Self {
+        match err {
+            hound::Error::IoError(err) => Self::Io(err),
+            err => Self::Unsupported(err.to_string()),
+        }
+    }
+}
+
+/// Returns the path of the transcoded file. Files transcoded before are taken
+/// from the cache
+pub(crate) fn transcode(
+    source: &Path,
+    options: TranscodeOptions,
+    cache: &TranscodeCache,
+) -> Result<PathBuf, TranscodeError> {
+    // The key changes with the file, so that an updated file is transcoded again
+    let modified = std::fs::metadata(source)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let key = sha256::digest(format!(
+        "{}:{}:{:?}:{}",
+        source.to_string_lossy(),
+        modified,
+        options.format,
+        options.max_bitrate.unwrap_or_default()
+    ));
+    let extension = options.format.extension();
+
+    if let Some(path) = cache.get(&key, extension) {
+        return Ok(path);
+    }
+
+    let partial = cache.partial_path(&key, extension)?;
+    if let Err(err) = encode(source, &partial, options) {
+        _ = std::fs::remove_file(&partial);
+        return Err(err);
+    }
+
+    Ok(cache.insert(&key, extension, &partial)?)
+}
+
+/// Bitrate of 16 bit PCM, in kbps
+fn pcm_bitrate(rate: u32, channels: usize) -> u64 {
+    rate as u64 * channels as u64 * BITS_PER_SAMPLE as u64 / 1000
+}
+
+/// The sample rate and number of channels handed to the encoder: those of the source,
+/// lowered until the bitrate fits for PCM
+fn target_format(
+    format: TranscodeFormat,
+    rate: u32,
+    channels: usize,
+    max_bitrate: Option<u32>,
+) -> (u32, usize) {
+    let channels = channels.clamp(1, 2);
+    let max_bitrate = match (format, max_bitrate) {
+        (TranscodeFormat::Mp3, _) => return (rate.min(MP3_MAX_SAMPLE_RATE), channels),
+        (TranscodeFormat::Wav, None) => return (rate, channels),
+        (TranscodeFormat::Wav, Some(max_bitrate)) => max_bitrate as u64,
+    };
+
+    let rates = SAMPLE_RATES
+        .iter()
+        .copied()
+        .filter(|candidate| *candidate < rate)
+        .collect::<Vec<u32>>();
+
+    for channels in (1..=channels).rev() {
+        for rate in std::iter::once(rate).chain(rates.iter().copied()) {
+            if pcm_bitrate(rate, channels) <= max_bitrate {
+                return (rate, channels);
+            }
+        }
+    }
+
+    (rates.last().copied().unwrap_or(rate), 1)
+}
+
+/// The highest MP3 bitrate under the maximum one
+fn mp3_bitrate(max_bitrate: Option<u32>) -> Bitrate {
+    let max_bitrate = max_bitrate.unwrap_or(MP3_DEFAULT_BITRATE);
+    MP3_BITRATES
+        .iter()
+        .find(|(bitrate, _)| *bitrate <= max_bitrate)
+        .unwrap_or(&MP3_BITRATES[MP3_BITRATES.len() - 1])
+        .1
+}
+
+/// Writes the decoded audio in the format asked for
+enum Encoder {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    Mp3 {
+        encoder: mp3lame_encoder::Encoder,
+        file: BufWriter<File>,
+        /// Encoded bytes not written yet
+        buffer: Vec<u8>,
+    },
+}
+
+impl Encoder {
+    fn new(
+        destination: &Path,
+        options: TranscodeOptions,
+        rate: u32,
+        channels: usize,
+    ) -> Result<Self, TranscodeError> {
+        match options.format {
+            TranscodeFormat::Wav => Ok(Self::Wav(hound::WavWriter::create(
+                destination,
+                hound::WavSpec {
+                    channels: channels as u16,
+                    sample_rate: rate,
+                    bits_per_sample: BITS_PER_SAMPLE,
+                    sample_format: hound::SampleFormat::Int,
+                },
+            )?)),
+            TranscodeFormat::Mp3 => {
+                let mut builder = mp3lame_encoder::Builder::new().ok_or_else(|| {
+                    TranscodeError::Unsupported("the MP3 encoder is not available".to_string())
+                })?;
+                builder.set_sample_rate(rate).map_err(mp3_error)?;
+                builder
+                    .set_num_channels(channels as u8)
+                    .map_err(mp3_error)?;
+                builder
+                    .set_mode(if channels == 1 {
+                        mp3lame_encoder::Mode::Mono
+                    } else {
+                        mp3lame_encoder::Mode::JointStereo
+                    })
+                    .map_err(mp3_error)?;
+                builder
+                    .set_brate(mp3_bitrate(options.max_bitrate))
+                    .map_err(mp3_error)?;
+                builder
+                    .set_quality(mp3lame_encoder::Quality::Good)
+                    .map_err(mp3_error)?;
+
+                Ok(Self::Mp3 {
+                    encoder: builder.build().map_err(mp3_error)?,
+                    file: BufWriter::new(File::create(destination)?),
+                    buffer: Vec::new(),
+                })
+            }
+        }
+    }
+
+    fn write(&mut self, buffer: &AudioBuffer<f32>) -> Result<(), TranscodeError> {
+        match self {
+            Self::Wav(writer) => write_samples(writer, buffer),
+            Self::Mp3 {
+                encoder,
+                file,
+                buffer: encoded,
+            } => {
+                encoded.clear();
+                encoded.reserve(mp3lame_encoder::max_required_buffer_size(buffer.frames()));
+                if buffer.spec().channels.count() == 1 {
+                    encoder.encode_to_vec(MonoPcm(buffer.chan(0)), encoded)
+                } else {
+                    encoder.encode_to_vec(
+                        DualPcm {
+                            left: buffer.chan(0),
+                            right: buffer.chan(1),
+                        },
+                        encoded,
+                    )
+                }
+                .map_err(mp3_error)?;
+                file.write_all(encoded)?;
+
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), TranscodeError> {
+        match self {
+            Self::Wav(writer) => writer.finalize()?,
+            Self::Mp3 {
+                mut encoder,
+                mut file,
+                mut buffer,
+            } => {
+                buffer.clear();
+                buffer.reserve(mp3lame_encoder::max_required_buffer_size(0));
+                encoder
+                    .flush_to_vec::<FlushNoGap>(&mut buffer)
+                    .map_err(mp3_error)?;
+                file.write_all(&buffer)?;
+                file.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn mp3_error(err: impl std::fmt::Display) -> TranscodeError {
+    TranscodeError::Unsupported(err.to_string())
+}
+
+fn encode(
+    source: &Path,
+    destination: &Path,
+    options: TranscodeOptions,
+) -> Result<(), TranscodeError> {
+    let mut hint = Hint::new();
+    if let Some(extension) = source.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let mss = MediaSourceStream::new(Box::new(File::open(source)?), Default::default());
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|err| TranscodeError::Unsupported(err.to_string()))?;
+
+    let mut reader = probed.format;
+    let track = reader
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| TranscodeError::Unsupported("no supported audio track".to_string()))?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|err| TranscodeError::Unsupported(err.to_string()))?;
+
+    let mut sample_buf = None;
+    let mut output: Option<(Converter, Encoder)> = None;
+
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+            // The end of the stream
+            Err(Error::IoError(_)) => break,
+            Err(err) => return Err(TranscodeError::Unsupported(err.to_string())),
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(Error::DecodeError(err)) => {
+                log::debug!("skipping bad packet: {}", err);
+                continue;
+            }
+            Err(err) => return Err(TranscodeError::Unsupported(err.to_string())),
+        };
+
+        let buffer = to_f32_buffer(&decoded, &mut sample_buf);
+        if output.is_none() {
+            let spec = buffer.spec();
+            let (rate, channels) = target_format(
+                options.format,
+                spec.rate,
+                spec.channels.count(),
+                options.max_bitrate,
+            );
+            let encoder = Encoder::new(destination, options, rate, channels)?;
+            output = Some((Converter::new(output_spec(rate, channels)), encoder));
+        }
+
+        if let Some((converter, encoder)) = output.as_mut() {
+            encoder.write(converter.convert(buffer))?;
+        }
+    }
+
+    let (mut converter, mut encoder) =
+        output.ok_or_else(|| TranscodeError::Unsupported("the file has no audio".to_string()))?;
+    encoder.write(converter.flush())?;
+    encoder.finish()
+}
+
+fn write_samples<W: std::io::Write + std::io::Seek>(
+    writer: &mut hound::WavWriter<W>,
+    buffer: &AudioBuffer<f32>,
+) -> Result<(), TranscodeError> {
+    let channels = buffer.spec().channels.count();
+    for frame in 0..buffer.frames() {
+        for channel in 0..channels {
+            let sample = buffer.chan(channel)[frame].clamp(-1.0, 1.0);
+            writer.write_sample((sample * i16::MAX as f32) as i16)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use symphonia::core::{
+        codecs::{DecoderOptions, CODEC_TYPE_MP3},
+        formats::FormatOptions,
+        io::MediaSourceStream,
+        meta::MetadataOptions,
+        probe::Hint,
+    };
+
+    use crate::testing::{temp_dir, write_sine_wav};
+
+    use super::{
+        mp3_bitrate, target_format, transcode, TranscodeCache, TranscodeFormat, TranscodeOptions,
+    };
+
+    #[test]
+    fn keeps_the_source_format_without_a_maximum() {
+        assert_eq!(
+            target_format(TranscodeFormat::Wav, 96000, 6, None),
+            (96000, 2)
+        );
+        assert_eq!(
+            target_format(TranscodeFormat::Wav, 44100, 1, None),
+            (44100, 1)
+        );
+    }
+
+    #[test]
+    fn lowers_the_pcm_rate_then_the_channels() {
+        // 44.1 kHz stereo is 1411 kbps
+        assert_eq!(
+            target_format(TranscodeFormat::Wav, 44100, 2, Some(1411)),
+            (44100, 2)
+        );
+        assert_eq!(
+            target_format(TranscodeFormat::Wav, 44100, 2, Some(1100)),
+            (32000, 2)
+        );
+        assert_eq!(
+            target_format(TranscodeFormat::Wav, 44100, 2, Some(706)),
+            (22050, 2)
+        );
+        assert_eq!(
+            target_format(TranscodeFormat::Wav, 44100, 2, Some(600)),
+            (32000, 1)
+        );
+        assert_eq!(
+            target_format(TranscodeFormat::Wav, 44100, 2, Some(352)),
+            (22050, 1)
+        );
+    }
+
+    #[test]
+    fn hands_mp3_the_source_rate() {
+        assert_eq!(
+            target_format(TranscodeFormat::Mp3, 44100, 2, Some(64)),
+            (44100, 2)
+        );
+        assert_eq!(
+            target_format(TranscodeFormat::Mp3, 96000, 1, None),
+            (48000, 1)
+        );
+    }
+
+    #[test]
+    fn picks_the_highest_mp3_bitrate_under_the_maximum() {
+        assert_eq!(mp3_bitrate(None) as u16, 192);
+        assert_eq!(mp3_bitrate(Some(1000)) as u16, 320);
+        assert_eq!(mp3_bitrate(Some(128)) as u16, 128);
+        assert_eq!(mp3_bitrate(Some(100)) as u16, 96);
+        assert_eq!(mp3_bitrate(Some(32)) as u16, 32);
+    }
+
+    #[test]
+    fn rejects_bitrates_the_format_cannot_meet() {
+        let options = |format, max_bitrate| TranscodeOptions {
+            format,
+            max_bitrate,
+        };
+
+        assert!(options(TranscodeFormat::Wav, None).validate().is_ok());
+        assert!(options(TranscodeFormat::Wav, Some(352)).validate().is_ok());
+        assert!(options(TranscodeFormat::Wav, Some(128)).validate().is_err());
+        assert!(options(TranscodeFormat::Mp3, Some(32)).validate().is_ok());
+        assert!(options(TranscodeFormat::Mp3, Some(16)).validate().is_err());
+    }
+
+    #[test]
+    fn transcodes_to_mp3_once() {
+        let dir = temp_dir();
+        let source = dir.join("source.wav");
+        write_sine_wav(&source, 44100, 2, 2.0, 0.5);
+        let cache = TranscodeCache::new(dir.join("cache").to_str().unwrap(), 1024 * 1024);
+        let options = TranscodeOptions {
+            format: TranscodeFormat::Mp3,
+            max_bitrate: Some(128),
+        };
+
+        let path = transcode(&source, options, &cache).unwrap();
+        assert_eq!(path.extension().unwrap(), "mp3");
+        // Two seconds at 128 kbps, give or take the frame padding
+        let size = std::fs::metadata(&path).unwrap().len();
+        assert!((30_000..36_000).contains(&size), "{} bytes", size);
+
+        let mss = MediaSourceStream::new(
+            Box::new(std::fs::File::open(&path).unwrap()),
+            Default::default(),
+        );
+        let mut reader = symphonia::default::get_probe()
+            .format(
+                Hint::new().with_extension("mp3"),
+                mss,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .unwrap()
+            .format;
+        let params = reader.default_track().unwrap().codec_params.clone();
+        assert_eq!(params.codec, CODEC_TYPE_MP3);
+        assert_eq!(params.sample_rate, Some(44100));
+
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&params, &DecoderOptions::default())
+            .unwrap();
+        let mut frames = 0;
+        while let Ok(packet) = reader.next_packet() {
+            frames += decoder.decode(&packet).unwrap().frames();
+        }
+        assert!(frames.abs_diff(88200) < 2 * 1152, "{} frames", frames);
+
+        // The second request is served from the cache
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(transcode(&source, options, &cache).unwrap(), path);
+        assert!(std::fs::metadata(&path).unwrap().modified().unwrap() >= modified);
+
+        _ = std::fs::remove_dir_all(dir);
+    }
+}
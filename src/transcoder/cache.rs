@@ -0,0 +1,171 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Transcoded files kept on disk. Once the files take more than the maximum
+/// size, the least recently used ones are removed
+#[derive(Debug, Clone)]
+pub(crate) struct TranscodeCache {
+    dir: PathBuf,
+    /// Maximum size of the cache, in bytes
+    max_size: u64,
+}
+
+impl TranscodeCache {
+    pub(crate) fn new(dir: &str, max_size: u64) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            max_size,
+        }
+    }
+
+    /// Returns the cached file and marks it as used
+    pub(crate) fn get(&self, key: &str, extension: &str) -> Option<PathBuf> {
+        let path = self.path(key, extension);
+        let file = File::options().append(true).open(&path).ok()?;
+        _ = file.set_modified(SystemTime::now());
+
+        Some(path)
+    }
+
+    /// Where a file is written while it is being transcoded. Each call gets its own
+    /// path so that requests transcoding the same file do not write over each other
+    pub(crate) fn partial_path(&self, key: &str, extension: &str) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+
+        Ok(self
+            .dir
+            .join(format!("{}.{}.{}.part", key, ulid::Ulid::new(), extension)))
+    }
+
+    /// Moves the transcoded file into the cache and makes room for it
+    pub(crate) fn insert(&self, key: &str, extension: &str, partial: &Path) -> io::Result<PathBuf> {
+        let path = self.path(key, extension);
+        fs::rename(partial, &path)?;
+        self.evict();
+
+        Ok(path)
+    }
+
+    fn path(&self, key: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, extension))
+    }
+
+    /// Removes the least recently used files until the cache fits. The most
+    /// recent file is always kept
+    fn evict(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+
+        let mut files = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().map(|ext| ext != "part") == Some(true))
+            .filter_map(|entry| {
+                let meta = entry.metadata().ok()?;
+                Some((entry.path(), meta.modified().ok()?, meta.len()))
+            })
+            .collect::<Vec<(PathBuf, SystemTime, u64)>>();
+        files.sort_by_key(|(_, modified, _)| *modified);
+
+        let mut size = files.iter().map(|(_, _, len)| len).sum::<u64>();
+        for (path, _, len) in files.iter().take(files.len().saturating_sub(1)) {
+            if size <= self.max_size {
+                break;
+            }
+
+            log::debug!("removing transcoded file: {:?}", path);
+            if fs::remove_file(path).is_ok() {
+                size -= len;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::testing::temp_dir;
+
+    use super::TranscodeCache;
+
+    /// Puts a file of `len` bytes in the cache, last used `age` seconds ago
+    fn cached(cache: &TranscodeCache, key: &str, len: usize, age: u64) {
+        let partial = cache.partial_path(key, "mp3").unwrap();
+        fs::write(&partial, vec![0; len]).unwrap();
+        let path = cache.insert(key, "mp3", &partial).unwrap();
+        set_age(&path, age);
+    }
+
+    fn set_age(path: &Path, age: u64) {
+        File::options()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(age))
+            .unwrap();
+    }
+
+    fn keys(cache: &TranscodeCache) -> Vec<String> {
+        let mut keys = fs::read_dir(&cache.dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                path.file_stem().unwrap().to_str().unwrap().to_string()
+            })
+            .collect::<Vec<String>>();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn removes_the_least_recently_used_files() {
+        let dir = temp_dir();
+        let cache = TranscodeCache::new(dir.to_str().unwrap(), 300);
+        cached(&cache, "a", 100, 30);
+        cached(&cache, "b", 100, 20);
+        cached(&cache, "c", 100, 10);
+        assert_eq!(keys(&cache), ["a", "b", "c"]);
+
+        // Reading a file makes it the most recently used one
+        assert!(cache.get("a", "mp3").is_some());
+        cached(&cache, "d", 150, 0);
+        assert_eq!(keys(&cache), ["a", "d"]);
+        assert!(cache.get("b", "mp3").is_none());
+
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn keeps_the_newest_file_even_when_too_large() {
+        let dir = temp_dir();
+        let cache = TranscodeCache::new(dir.to_str().unwrap(), 100);
+        cached(&cache, "a", 50, 10);
+        cached(&cache, "b", 500, 0);
+        assert_eq!(keys(&cache), ["b"]);
+
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn leaves_files_being_transcoded_alone() {
+        let dir = temp_dir();
+        let cache = TranscodeCache::new(dir.to_str().unwrap(), 100);
+        let partial = cache.partial_path("a", "mp3").unwrap();
+        fs::write(&partial, vec![0; 500]).unwrap();
+        cached(&cache, "b", 50, 0);
+
+        assert!(partial.exists());
+        assert_eq!(cache.get("b", "mp3"), Some(dir.join("b.mp3")));
+
+        _ = fs::remove_dir_all(dir);
+    }
+}
@@ -11,6 +11,7 @@ use crate::{
     entity::client::ClientEntity,
     player::PlayerCommand,
     queue_manager::QueueManagerCommand,
+    scanner::ScanJob,
     websocket::{server, websocket_message::WebsocketMessage},
 };
 use actix_web::{
@@ -20,10 +21,7 @@ use actix_web::{
 };
 
 use self::{
-    admin::handle_admin_command,
-    api_response::ApiResponse,
-    docs::dev_docs_index_handler,
-    query::{handle_get_playlist_query, handle_next_query, handle_previous_query},
+    admin::handle_admin_command, api_response::ApiResponse, docs::dev_docs_index_handler,
     user::handle_user_command,
 };
 
@@ -32,7 +30,6 @@ mod api;
 mod api_response;
 mod auth_middleware;
 mod docs;
-mod query;
 mod user;
 pub(crate) mod web_app_event_handler;
 
@@ -52,6 +49,9 @@ pub(crate) async fn start_webapp(
     // share a copy with the rest of the app
     busybody::helpers::register_type(server_copy.clone());
 
+    // the scans started from the API
+    busybody::helpers::register_type(ScanJob::default());
+
     std::thread::spawn(move || loop {
         if let Ok(msg) = b_receiver.try_recv() {
             server_copy.do_send(msg)
@@ -80,10 +80,7 @@ pub(crate) async fn start_webapp(
                 web::scope("/api/v1")
                     .wrap(auth_middleware::Auth)
                     .route("/user-command", web::post().to(handle_user_command))
-                    .route("/admin-command", web::post().to(handle_admin_command))
-                    .route("/query/next", web::get().to(handle_next_query))
-                    .route("/query/previous", web::get().to(handle_previous_query))
-                    .route("/query/playlist", web::get().to(handle_get_playlist_query)),
+                    .route("/admin-command", web::post().to(handle_admin_command)),
             )
             .route("/play", web::post().to(play_track))
             .route("/cmd", web::post().to(command))
@@ -133,7 +130,11 @@ async fn play_track(
     payload: web::Json<PlayTrackPayload>,
 ) -> impl actix_web::Responder {
     let track = payload.0;
-    let result = sender.send(PlayerCommand::Play(track.path.clone()));
+    let result = sender.send(PlayerCommand::Play {
+        path: track.path.clone(),
+        track_id: None,
+        replay_gain: Default::default(),
+    });
 
     if result.is_err() {
         log::error!("channel broken? : {:?}", result.is_err());
@@ -174,7 +175,11 @@ async fn command(
 ) -> impl actix_web::Responder {
     match &payload.cmd {
         Command::Play if !payload.data.is_empty() => {
-            _ = sender.send(PlayerCommand::Play(payload.data.clone()));
+            _ = sender.send(PlayerCommand::Play {
+                path: payload.data.clone(),
+                track_id: None,
+                replay_gain: Default::default(),
+            });
             "handled play command"
         }
         Command::PlayQueue => {
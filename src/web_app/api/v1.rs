@@ -6,8 +6,11 @@ mod v1_album;
 mod v1_artist;
 mod v1_client;
 mod v1_file_server;
+mod v1_library;
+mod v1_live;
 mod v1_player;
 mod v1_playlist;
+mod v1_queue;
 mod v1_search;
 mod v1_track;
 mod v1_websocket;
@@ -36,6 +39,12 @@ pub(crate) fn config_api_service(config: &mut web::ServiceConfig) {
     api_routes = v1_websocket::register_routes(api_routes);
     // Player routes
     api_routes = v1_player::register_routes(api_routes);
+    // Queue routes
+    api_routes = v1_queue::register_routes(api_routes);
+    // Live stream routes
+    api_routes = v1_live::register_routes(api_routes);
+    // Library routes
+    api_routes = v1_library::register_routes(api_routes);
 
     config.service(
         api_routes
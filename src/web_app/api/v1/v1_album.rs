@@ -4,7 +4,7 @@ use actix_web::{delete, get, post, put, web, HttpRequest, Responder, Scope};
 
 use crate::{
     db::{DbManager, PaginatedResult, Paginator},
-    entity::album::{InAlbumEntityDto, OutAlbumEntityDto},
+    entity::album::{AlbumEntity, InAlbumEntityDto, OutAlbumEntityDto},
     web_app::{api_response::ApiResponse, when_admin, when_user},
 };
 
@@ -31,13 +31,11 @@ async fn get_albums(req: HttpRequest) -> impl Responder {
     let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
 
     PaginatedResult::<Vec<OutAlbumEntityDto>>::new(
-        db_manager
-            .album_repo()
-            .paginate(&mut paginator)
-            .await
-            .into_iter()
-            .map(OutAlbumEntityDto::from)
-            .collect::<Vec<OutAlbumEntityDto>>(),
+        with_durations(
+            db_manager,
+            db_manager.album_repo().paginate(&mut paginator).await,
+        )
+        .await,
         &paginator,
     )
     .into_response()
@@ -53,13 +51,13 @@ async fn get_an_album(req: HttpRequest, id: web::Path<String>) -> impl Responder
 
     let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
 
-    ApiResponse::into_response(
-        db_manager
-            .album_repo()
-            .find_by_id(&id.into_inner())
-            .await
-            .map(OutAlbumEntityDto::from),
-    )
+    let album = db_manager.album_repo().find_by_id(&id.into_inner()).await;
+    let result = match album {
+        Some(album) => with_durations(db_manager, vec![album]).await.pop(),
+        None => None,
+    };
+
+    ApiResponse::into_response(result)
 }
 
 #[get("/albums/track/{track_id}")]
@@ -73,13 +71,14 @@ async fn get_albums_by_track(req: HttpRequest, track_id: web::Path<String>) -> i
     let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
 
     ApiResponse::success_response(
-        db_manager
-            .album_repo()
-            .find_by_track_id(&track_id.into_inner())
-            .await
-            .into_iter()
-            .map(OutAlbumEntityDto::from)
-            .collect::<Vec<OutAlbumEntityDto>>(),
+        with_durations(
+            db_manager,
+            db_manager
+                .album_repo()
+                .find_by_track_id(&track_id.into_inner())
+                .await,
+        )
+        .await,
     )
 }
 
@@ -93,13 +92,14 @@ async fn get_albums_by_artist(req: HttpRequest, artist_id: web::Path<String>) ->
     let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
 
     ApiResponse::success_response(
-        db_manager
-            .album_repo()
-            .find_by_artist_id(&artist_id.into_inner())
-            .await
-            .into_iter()
-            .map(OutAlbumEntityDto::from)
-            .collect::<Vec<OutAlbumEntityDto>>(),
+        with_durations(
+            db_manager,
+            db_manager
+                .album_repo()
+                .find_by_artist_id(&artist_id.into_inner())
+                .await,
+        )
+        .await,
     )
 }
 
@@ -162,3 +162,20 @@ async fn delete(req: HttpRequest, id: web::Path<String>) -> impl Responder {
             .map(OutAlbumEntityDto::from),
     )
 }
+
+async fn with_durations(
+    db_manager: &DbManager,
+    albums: Vec<AlbumEntity>,
+) -> Vec<OutAlbumEntityDto> {
+    let repo = db_manager.album_repo();
+    let mut results = Vec::new();
+    for album in albums {
+        let duration = repo.duration(&album.id).await;
+        results.push(OutAlbumEntityDto {
+            duration,
+            ..album.into()
+        });
+    }
+
+    results
+}
@@ -1,20 +1,37 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 use actix_web::{
-    error::{ErrorNotFound, ErrorUnauthorized},
+    error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound, ErrorUnauthorized},
     get, web, HttpRequest, Responder, Scope,
 };
 
-use crate::{db::DbManager, entity::client::OutClientEntityDto, web_app::when_user};
+use crate::{
+    config::Config,
+    db::DbManager,
+    entity::{client::OutClientEntityDto, media::MediaEntity},
+    transcoder::{self, TranscodeCache, TranscodeError, TranscodeFormat, TranscodeOptions},
+    web_app::when_user,
+};
 
 pub(crate) fn register_routes(scope: Scope) -> Scope {
     scope.service(serve).service(serve_file)
 }
 
+#[derive(Debug, Default, serde::Deserialize)]
+pub(crate) struct StreamOptions {
+    /// Transcode the file to this format. Files over the maximum bitrate are
+    /// transcoded to MP3 when no format is given
+    format: Option<String>,
+    /// Transcode the file when its bitrate is over this one, in kbps
+    max_bitrate: Option<u32>,
+}
+
 #[get("/stream/{id}")]
 pub(crate) async fn serve(
     id: web::Path<String>,
     req: HttpRequest,
+    options: web::Query<StreamOptions>,
+    config: web::Data<Config>,
 ) -> actix_web::Result<actix_files::NamedFile> {
     let (_, response) = when_user::<OutClientEntityDto>(&req).await;
 
@@ -26,7 +43,7 @@ pub(crate) async fn serve(
 
     let the_id = id.into_inner();
     if let Some(media) = db_manager.media_repo().find_media_by_track(&the_id).await {
-        actix_files::NamedFile::open(media.path).map_err(Into::into)
+        open_media(media, &options, &config).await
     } else {
         Err(ErrorNotFound(format!(
             "file with ID: {:?} not found",
@@ -36,7 +53,12 @@ pub(crate) async fn serve(
 }
 
 #[get("serve/{media_id}")]
-pub(crate) async fn serve_file(req: HttpRequest, media_id: web::Path<String>) -> impl Responder {
+pub(crate) async fn serve_file(
+    req: HttpRequest,
+    media_id: web::Path<String>,
+    options: web::Query<StreamOptions>,
+    config: web::Data<Config>,
+) -> impl Responder {
     let (_, response) = when_user::<OutClientEntityDto>(&req).await;
 
     if response.is_some() {
@@ -45,7 +67,7 @@ pub(crate) async fn serve_file(req: HttpRequest, media_id: web::Path<String>) ->
     let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
 
     if let Some(media) = db_manager.media_repo().find_by_id(media_id.as_str()).await {
-        actix_files::NamedFile::open(media.path).map_err(Into::into)
+        open_media(media, &options, &config).await
     } else {
         Err(ErrorNotFound(format!(
             "file with ID: {:?} not found",
@@ -53,3 +75,42 @@ pub(crate) async fn serve_file(req: HttpRequest, media_id: web::Path<String>) ->
         )))
     }
 }
+
+/// Opens the media file, transcoded when the options ask for it. A file already
+/// under the maximum bitrate is sent as is
+async fn open_media(
+    media: MediaEntity,
+    options: &StreamOptions,
+    config: &Config,
+) -> actix_web::Result<actix_files::NamedFile> {
+    let format = match &options.format {
+        Some(name) => Some(name.parse::<TranscodeFormat>().map_err(ErrorBadRequest)?),
+        None => None,
+    };
+    let under_max_bitrate = options
+        .max_bitrate
+        .map(|max| media.metadata.bitrate > 0 && media.metadata.bitrate <= max * 1000)
+        .unwrap_or(true);
+
+    if format.is_none() && under_max_bitrate {
+        return actix_files::NamedFile::open(media.path).map_err(Into::into);
+    }
+
+    let options = TranscodeOptions {
+        format: format.unwrap_or_default(),
+        max_bitrate: options.max_bitrate,
+    };
+    options.validate().map_err(ErrorBadRequest)?;
+    let cache = TranscodeCache::new(
+        &config.transcode_cache_path(),
+        config.transcode_cache_size(),
+    );
+    let path = web::block(move || transcoder::transcode(Path::new(&media.path), options, &cache))
+        .await?
+        .map_err(|err| match err {
+            TranscodeError::Unsupported(_) => ErrorBadRequest(err.to_string()),
+            TranscodeError::Io(_) => ErrorInternalServerError(err.to_string()),
+        })?;
+
+    actix_files::NamedFile::open(path).map_err(Into::into)
+}
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use actix_web::{
+    get, post,
+    web::{self, Data},
+    HttpRequest, HttpResponse, Responder, Scope,
+};
+
+use crate::{
+    config::Config,
+    db::DbManager,
+    scanner::{self, ScanJob, ScanStatus},
+    web_app::{api_response::ApiResponse, when_admin, when_user},
+};
+
+pub(crate) fn register_routes(scope: Scope) -> Scope {
+    scope.service(start_scan).service(get_scan)
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct ScanPayload {
+    /// The folders to scan. Defaults to the library folders
+    #[serde(default)]
+    paths: Vec<String>,
+}
+
+/// Scans the library in the background. The progress is published on the
+/// websocket as "scan_progress" library events
+#[post("/library/scan")]
+async fn start_scan(
+    req: HttpRequest,
+    payload: Option<web::Json<ScanPayload>>,
+    config: Data<Config>,
+) -> impl Responder {
+    let (_, response) = when_admin::<ScanStatus>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let Some(job) = busybody::helpers::get_type::<ScanJob>() else {
+        return HttpResponse::ServiceUnavailable().json(ApiResponse::<ScanStatus>::error(
+            "scanning is not available",
+        ));
+    };
+
+    let mut paths = payload.map(|payload| payload.0).unwrap_or_default().paths;
+    if paths.is_empty() {
+        paths = config
+            .library_paths()
+            .into_iter()
+            .map(String::from)
+            .collect();
+    }
+    if paths.is_empty() {
+        return HttpResponse::BadRequest().json(ApiResponse::<ScanStatus>::error(
+            "no path to scan, pass some or set PARTY_LIBRARY_PATHS",
+        ));
+    }
+    for path in &paths {
+        if !tokio::fs::metadata(path)
+            .await
+            .map(|meta| meta.is_dir())
+            .unwrap_or_default()
+        {
+            return HttpResponse::BadRequest().json(ApiResponse::<ScanStatus>::error(&format!(
+                "{} is not a folder",
+                path
+            )));
+        }
+    }
+
+    let Some(status) = job.start(paths.clone()) else {
+        return HttpResponse::Conflict().json(ApiResponse::<ScanStatus>::error(
+            "a scan is already running",
+        ));
+    };
+
+    let db_manager = req.app_data::<Arc<DbManager>>().unwrap().clone();
+    actix_web::rt::spawn(scanner::run_scan_job(
+        job,
+        paths,
+        db_manager,
+        config.get_ref().clone(),
+    ));
+
+    HttpResponse::Accepted().json(ApiResponse::success(status))
+}
+
+/// The status of the last scan started from the API
+#[get("/library/scan")]
+async fn get_scan(req: HttpRequest) -> impl Responder {
+    let (_, response) = when_user::<ScanStatus>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let status = busybody::helpers::get_type::<ScanJob>()
+        .map(|job| job.status())
+        .unwrap_or_default();
+
+    ApiResponse::success_response(status)
+}
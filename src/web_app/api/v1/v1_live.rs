@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use actix_web::{get, web::Bytes, HttpRequest, HttpResponse, Responder, Scope};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    db::DbManager,
+    player::{LiveStream, SharedPlayerState},
+    web_app::{api_response::ApiResponse, when_user},
+};
+
+/// Number of audio bytes between two ICY metadata blocks
+const ICY_METAINT: usize = 16000;
+
+pub(crate) fn register_routes(scope: Scope) -> Scope {
+    scope.service(live_stream)
+}
+
+/// The mix played by the server, as an endless WAV stream.
+///
+/// Clients sending `Icy-MetaData: 1` get the title of the track being played
+/// in ICY metadata blocks, as served by internet radios.
+#[get("/live/stream")]
+async fn live_stream(req: HttpRequest) -> impl Responder {
+    let (_, response) = when_user::<String>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let Some(live) = busybody::helpers::get_type::<LiveStream>() else {
+        return HttpResponse::ServiceUnavailable().json(ApiResponse::<String>::error(
+            "the live stream is not available",
+        ));
+    };
+
+    let metadata = req
+        .headers()
+        .get("icy-metadata")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim() == "1")
+        .unwrap_or_default();
+
+    let listener = Listener {
+        receiver: live.subscribe(),
+        pending: live.wav_header(),
+        metadata,
+        until_metadata: ICY_METAINT,
+        track_id: None,
+        db_manager: req.app_data::<Arc<DbManager>>().cloned(),
+    };
+
+    let stream = futures::stream::unfold(listener, |mut listener| async move {
+        let chunk = listener.next().await?;
+        Some((Ok::<Bytes, actix_web::Error>(chunk), listener))
+    });
+
+    let mut builder = HttpResponse::Ok();
+    builder
+        .content_type("audio/wav")
+        .insert_header(("Cache-Control", "no-cache, no-store"))
+        .insert_header(("icy-name", "Party Chrasher"));
+    if metadata {
+        builder.insert_header(("icy-metaint", ICY_METAINT.to_string()));
+    }
+
+    builder.streaming(stream)
+}
+
+/// A client listening to the live stream
+struct Listener {
+    receiver: broadcast::Receiver<Bytes>,
+    /// Audio received but not sent yet
+    pending: Bytes,
+    metadata: bool,
+    /// Number of audio bytes to send before the next metadata block
+    until_metadata: usize,
+    /// The track the last metadata block was sent for
+    track_id: Option<String>,
+    db_manager: Option<Arc<DbManager>>,
+}
+
+impl Listener {
+    /// The next chunk to send. `None` once the live stream is closed
+    async fn next(&mut self) -> Option<Bytes> {
+        while self.pending.is_empty() {
+            match self.receiver.recv().await {
+                Ok(chunk) => self.pending = chunk,
+                // A listener that falls behind skips to the live position
+                Err(RecvError::Lagged(_)) => (),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+
+        if !self.metadata {
+            return Some(std::mem::take(&mut self.pending));
+        }
+
+        let count = self.pending.len().min(self.until_metadata);
+        let audio = self.pending.split_to(count);
+        self.until_metadata -= count;
+        if self.until_metadata > 0 {
+            return Some(audio);
+        }
+
+        self.until_metadata = ICY_METAINT;
+        let mut chunk = audio.to_vec();
+        chunk.extend_from_slice(&self.metadata_block().await);
+
+        Some(Bytes::from(chunk))
+    }
+
+    /// An ICY metadata block with the title of the track being played. The
+    /// block is empty when the track has not changed
+    async fn metadata_block(&mut self) -> Vec<u8> {
+        let track_id = busybody::helpers::get_type::<SharedPlayerState>()
+            .and_then(|state| state.snapshot().track_id);
+        if track_id == self.track_id {
+            return vec![0];
+        }
+        self.track_id = track_id;
+
+        let mut title = String::new();
+        if let (Some(track_id), Some(db_manager)) = (&self.track_id, &self.db_manager) {
+            if let Some(track) = db_manager.track_repo().find_by_id(track_id).await {
+                title = if track.metadata.artist.is_empty() {
+                    track.title
+                } else {
+                    format!("{} - {}", track.metadata.artist, track.title)
+                };
+            }
+        }
+
+        let text = format!("StreamTitle='{}';", title.replace('\'', "’"));
+        let mut bytes = text.into_bytes();
+        bytes.truncate(255 * 16);
+        let blocks = bytes.len().div_ceil(16);
+        bytes.resize(blocks * 16, 0);
+        bytes.insert(0, blocks as u8);
+
+        bytes
+    }
+}
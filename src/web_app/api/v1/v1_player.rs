@@ -1,10 +1,23 @@
+use std::sync::Arc;
+
+use actix::Addr;
 use actix_web::{
-    post,
-    web::{self},
+    delete, get, post,
+    web::{self, Data},
     HttpRequest, Responder, Scope,
 };
 
-use crate::web_app::when_admin;
+use crate::{
+    db::DbManager,
+    entity::{client::ClientEntity, track::TrackEntity},
+    player::{self, PlayerCommand},
+    queue_manager::{QueueManagerCommand, RepeatMode, SleepTimer},
+    web_app::{api_response::ApiResponse, when_admin, when_user},
+    websocket::{
+        server::ChatServer,
+        websocket_message::{PlayerEvent, WebsocketMessage},
+    },
+};
 
 pub(crate) fn register_routes(scope: Scope) -> Scope {
     scope
@@ -15,6 +28,16 @@ pub(crate) fn register_routes(scope: Scope) -> Scope {
         .service(control_repeat)
         .service(control_skip)
         .service(control_volume)
+        .service(control_seek)
+        .service(control_crossfade)
+        .service(control_shuffle)
+        .service(get_state)
+        .service(get_sleep_timer)
+        .service(set_sleep_timer)
+        .service(cancel_sleep_timer)
+        .service(get_equalizer)
+        .service(control_equalizer)
+        .service(get_equalizer_presets)
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -46,7 +69,11 @@ struct PlayAlbum {
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct Skip {
     location: PlayerLocation,
+    /// Skip to this track in the queue. When empty, skips to the next or previous track
+    #[serde(default)]
     to_track_id: String,
+    #[serde(default)]
+    previous: bool,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -55,12 +82,60 @@ struct Play {
     location: PlayerLocation,
 }
 
+const DEFAULT_VOLUME_STEP: u8 = 5;
+
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct Volume {
-    increase: bool,
+    /// Step the volume up (true) or down (false)
+    increase: Option<bool>,
+    /// The size of a step. Defaults to 5
+    step: Option<u8>,
+    /// Absolute volume level from 0 to 100
+    level: Option<u8>,
+    mute: Option<bool>,
+    location: PlayerLocation,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct Seek {
+    /// Position in seconds. When `relative` is true, the position is an offset
+    /// (can be negative) from the current position
+    position: f64,
+    #[serde(default)]
+    relative: bool,
     location: PlayerLocation,
 }
 
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct Crossfade {
+    /// Number of seconds two tracks overlap. 0 disables crossfading
+    seconds: f64,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct Equalizer {
+    enabled: Option<bool>,
+    /// Takes the band gains from the preset
+    preset: Option<String>,
+    /// Gain of each band, in dB. Setting them leaves the preset
+    bands: Option<[f32; player::EQUALIZER_BANDS]>,
+    preamp: Option<f32>,
+    bass_boost: Option<f32>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct EqualizerPreset {
+    name: &'static str,
+    bands: [f32; player::EQUALIZER_BANDS],
+}
+
+#[derive(Debug, serde::Serialize)]
+struct EqualizerPresets {
+    /// Centre frequency of each band, in Hz
+    frequencies: [f32; player::EQUALIZER_BANDS],
+    presets: Vec<EqualizerPreset>,
+}
+
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct Repeat {
     one: bool,
@@ -68,43 +143,598 @@ struct Repeat {
     location: PlayerLocation,
 }
 
+impl Repeat {
+    fn mode(&self) -> RepeatMode {
+        if self.one {
+            RepeatMode::One
+        } else if self.all {
+            RepeatMode::All
+        } else {
+            RepeatMode::Off
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct Shuffle {
+    shuffle: bool,
+    location: PlayerLocation,
+}
+
+#[get("/player/state")]
+async fn get_state(req: HttpRequest) -> impl Responder {
+    let (_, response) = when_user::<player::PlayerState>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    ApiResponse::into_response(
+        busybody::helpers::get_type::<player::SharedPlayerState>().map(|state| state.snapshot()),
+    )
+}
+
 #[post("/player/play-track")]
-async fn play_track(req: HttpRequest, payload: web::Json<PlayTrack>) -> impl Responder {
-    // let (_, response) = when_admin::<String>(&req).await;
+async fn play_track(
+    req: HttpRequest,
+    payload: web::Json<PlayTrack>,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+    ws_server: Data<Addr<ChatServer>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<PlayTrack>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
+    if db_manager
+        .track_repo()
+        .find_by_id(&payload.track_id)
+        .await
+        .is_none()
+    {
+        return ApiResponse::<PlayTrack>::not_found_response(Some("track not found"));
+    }
+
+    if let PlayerLocation::Server = payload.location {
+        if db_manager
+            .media_repo()
+            .find_media_by_track(&payload.track_id)
+            .await
+            .is_none()
+        {
+            return ApiResponse::<PlayTrack>::not_found_response(Some("media not found"));
+        }
+
+        let queued_by = ClientEntity::try_from(&req)
+            .map(|client| client.id)
+            .unwrap_or_default();
+        let command = QueueManagerCommand::PlayNow {
+            track_id: payload.track_id.clone(),
+            queued_by,
+        };
 
-    // if let Some(r) = response {
-    //     return r;
-    // }
+        if queue_sender.send(command).is_err() {
+            log::error!("could not send play command to the queue manager");
+        }
+    }
 
-    format!("play track {:?}", payload)
+    ws_server.do_send(WebsocketMessage::PlayerEvent {
+        event: PlayerEvent::PlayTrack {
+            track_id: payload.track_id.clone(),
+        },
+    });
+
+    ApiResponse::success_response(payload.0)
 }
 
 #[post("/player/play-album")]
-async fn play_album(req: HttpRequest, payload: web::Json<PlayAlbum>) -> impl Responder {
-    format!("play album {:?}", payload)
+async fn play_album(
+    req: HttpRequest,
+    payload: web::Json<PlayAlbum>,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+    ws_server: Data<Addr<ChatServer>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<PlayAlbum>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
+    let tracks = db_manager
+        .track_repo()
+        .find_by_album_id(&payload.album_id)
+        .await;
+
+    if tracks.is_empty() {
+        return ApiResponse::<PlayAlbum>::not_found_response(Some("album has no tracks"));
+    }
+
+    if let PlayerLocation::Server = payload.location {
+        play_tracks(&req, tracks, &queue_sender);
+    }
+
+    ws_server.do_send(WebsocketMessage::PlayerEvent {
+        event: PlayerEvent::PlayAlbum {
+            album_id: payload.album_id.clone(),
+        },
+    });
+
+    ApiResponse::success_response(payload.0)
 }
 
 #[post("/player/play-playlist")]
-async fn play_playlist(req: HttpRequest, payload: web::Json<PlayPlaylist>) -> impl Responder {
-    format!("play playlist {:?}", payload)
+async fn play_playlist(
+    req: HttpRequest,
+    payload: web::Json<PlayPlaylist>,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+    ws_server: Data<Addr<ChatServer>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<PlayPlaylist>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
+    let tracks = db_manager
+        .track_repo()
+        .find_by_playlist_id(&payload.playlist_id)
+        .await;
+
+    if tracks.is_empty() {
+        return ApiResponse::<PlayPlaylist>::not_found_response(Some("playlist has no tracks"));
+    }
+
+    if let PlayerLocation::Server = payload.location {
+        play_tracks(&req, tracks, &queue_sender);
+    }
+
+    ws_server.do_send(WebsocketMessage::PlayerEvent {
+        event: PlayerEvent::PlayPlaylist {
+            playlist_id: payload.playlist_id.clone(),
+        },
+    });
+
+    ApiResponse::success_response(payload.0)
 }
 
 #[post("/player/control-skip")]
-async fn control_skip(req: HttpRequest, payload: web::Json<Skip>) -> impl Responder {
-    format!("skip {:?}", payload)
+async fn control_skip(
+    req: HttpRequest,
+    payload: web::Json<Skip>,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+    ws_server: Data<Addr<ChatServer>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<Skip>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    if let PlayerLocation::Server = payload.location {
+        let command = if !payload.to_track_id.is_empty() {
+            QueueManagerCommand::SkipTo(payload.to_track_id.clone())
+        } else if payload.previous {
+            QueueManagerCommand::Previous
+        } else {
+            QueueManagerCommand::Next
+        };
+
+        if queue_sender.send(command).is_err() {
+            log::error!("could not send skip command to the queue manager");
+        }
+    }
+
+    ws_server.do_send(WebsocketMessage::PlayerEvent {
+        event: PlayerEvent::Skip {
+            next: !payload.previous,
+        },
+    });
+
+    ApiResponse::success_response(payload.0)
 }
 
 #[post("/player/control-play")]
-async fn control_play(req: HttpRequest, payload: web::Json<Play>) -> impl Responder {
-    format!("control play {:?}", payload)
+async fn control_play(
+    req: HttpRequest,
+    payload: web::Json<Play>,
+    sender: Data<std::sync::mpsc::Sender<PlayerCommand>>,
+    ws_server: Data<Addr<ChatServer>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<Play>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    if let PlayerLocation::Server = payload.location {
+        let command = if payload.play {
+            PlayerCommand::Resume
+        } else {
+            PlayerCommand::Pause
+        };
+
+        if sender.send(command).is_err() {
+            log::error!("could not send play command to the player");
+        }
+    }
+
+    ws_server.do_send(WebsocketMessage::PlayerEvent {
+        event: PlayerEvent::Play { play: payload.play },
+    });
+
+    ApiResponse::success_response(payload.0)
 }
 
 #[post("/player/control-volume")]
-async fn control_volume(req: HttpRequest, payload: web::Json<Volume>) -> impl Responder {
-    format!("control volume {:?}", payload)
+async fn control_volume(
+    req: HttpRequest,
+    payload: web::Json<Volume>,
+    sender: Data<std::sync::mpsc::Sender<PlayerCommand>>,
+    ws_server: Data<Addr<ChatServer>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<player::Volume>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let setting_name = match payload.location {
+        PlayerLocation::Server => player::Volume::SETTING_NAME,
+        PlayerLocation::Client => player::Volume::CLIENT_SETTING_NAME,
+    };
+
+    let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
+    let repo = db_manager.setting_repo();
+
+    let mut volume = repo
+        .get::<player::Volume>(setting_name)
+        .await
+        .unwrap_or_default();
+
+    if let Some(level) = payload.level {
+        volume.set_level(level);
+    }
+
+    if let Some(increase) = payload.increase {
+        let step = payload.step.unwrap_or(DEFAULT_VOLUME_STEP);
+        if increase {
+            volume.increase(step);
+        } else {
+            volume.decrease(step);
+        }
+    }
+
+    if let Some(mute) = payload.mute {
+        volume.muted = mute;
+    }
+
+    repo.set(setting_name, &volume).await;
+
+    match payload.location {
+        // The player lets the websocket listeners know once the volume is applied
+        PlayerLocation::Server => {
+            if sender.send(PlayerCommand::Volume(volume)).is_err() {
+                log::error!("could not send volume command to the player");
+            }
+        }
+        PlayerLocation::Client => ws_server.do_send(WebsocketMessage::PlayerEvent {
+            event: PlayerEvent::Volume {
+                level: volume.level,
+                muted: volume.muted,
+            },
+        }),
+    }
+
+    ApiResponse::success_response(volume)
 }
 
 #[post("/player/control-repeat")]
-async fn control_repeat(req: HttpRequest, payload: web::Json<Repeat>) -> impl Responder {
-    format!("control repeat {:?}", payload)
+async fn control_repeat(
+    req: HttpRequest,
+    payload: web::Json<Repeat>,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+    ws_server: Data<Addr<ChatServer>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<Repeat>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let mode = payload.mode();
+    match payload.location {
+        PlayerLocation::Server => {
+            if queue_sender
+                .send(QueueManagerCommand::Repeat(mode))
+                .is_err()
+            {
+                log::error!("could not send repeat command to the queue manager");
+            }
+        }
+        PlayerLocation::Client => ws_server.do_send(WebsocketMessage::PlayerEvent {
+            event: PlayerEvent::Repeat { mode },
+        }),
+    }
+
+    ApiResponse::success_response(payload.0)
+}
+
+#[post("/player/control-shuffle")]
+async fn control_shuffle(
+    req: HttpRequest,
+    payload: web::Json<Shuffle>,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+    ws_server: Data<Addr<ChatServer>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<Shuffle>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    match payload.location {
+        PlayerLocation::Server => {
+            if queue_sender
+                .send(QueueManagerCommand::Shuffle(payload.shuffle))
+                .is_err()
+            {
+                log::error!("could not send shuffle command to the queue manager");
+            }
+        }
+        PlayerLocation::Client => ws_server.do_send(WebsocketMessage::PlayerEvent {
+            event: PlayerEvent::Shuffle {
+                enabled: payload.shuffle,
+            },
+        }),
+    }
+
+    ApiResponse::success_response(payload.0)
+}
+
+#[post("/player/control-seek")]
+async fn control_seek(
+    req: HttpRequest,
+    payload: web::Json<Seek>,
+    sender: Data<std::sync::mpsc::Sender<PlayerCommand>>,
+    ws_server: Data<Addr<ChatServer>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<Seek>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    match payload.location {
+        PlayerLocation::Server => {
+            let command = if payload.relative {
+                PlayerCommand::SeekBy(payload.position)
+            } else {
+                PlayerCommand::Seek(payload.position)
+            };
+            if sender.send(command).is_err() {
+                log::error!("could not send seek command to the player");
+            }
+        }
+        PlayerLocation::Client => ws_server.do_send(WebsocketMessage::PlayerEvent {
+            event: PlayerEvent::Seek {
+                position: payload.position,
+                relative: payload.relative,
+            },
+        }),
+    }
+
+    ApiResponse::success_response(payload.0)
+}
+
+#[post("/player/control-crossfade")]
+async fn control_crossfade(
+    req: HttpRequest,
+    payload: web::Json<Crossfade>,
+    sender: Data<std::sync::mpsc::Sender<PlayerCommand>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<Crossfade>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let seconds = payload.seconds.max(0.0);
+    let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
+    db_manager
+        .setting_repo()
+        .set(player::CROSSFADE_SETTING_NAME, &seconds)
+        .await;
+
+    if sender.send(PlayerCommand::Crossfade(seconds)).is_err() {
+        log::error!("could not send crossfade command to the player");
+    }
+
+    ApiResponse::success_response(Crossfade { seconds })
+}
+
+#[get("/player/sleep-timer")]
+async fn get_sleep_timer(req: HttpRequest) -> impl Responder {
+    let (_, response) = when_user::<SleepTimer>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    ApiResponse::into_response(
+        busybody::helpers::get_type::<player::SharedPlayerState>()
+            .and_then(|state| state.snapshot().sleep_timer),
+    )
+}
+
+/// Stops the server player at a given time, after a number of tracks or after
+/// the current track, fading the music out
+#[post("/player/sleep-timer")]
+async fn set_sleep_timer(
+    req: HttpRequest,
+    payload: web::Json<SleepTimer>,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<SleepTimer>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let mut timer = payload.0;
+    timer.fade = timer.fade.max(0.0);
+
+    if queue_sender
+        .send(QueueManagerCommand::SleepTimer(Some(timer)))
+        .is_err()
+    {
+        log::error!("could not send sleep timer command to the queue manager");
+    }
+
+    ApiResponse::success_response(timer)
+}
+
+#[delete("/player/sleep-timer")]
+async fn cancel_sleep_timer(
+    req: HttpRequest,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<SleepTimer>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    if queue_sender
+        .send(QueueManagerCommand::SleepTimer(None))
+        .is_err()
+    {
+        log::error!("could not send sleep timer command to the queue manager");
+    }
+
+    ApiResponse::<Option<SleepTimer>>::success_response(None)
+}
+
+#[get("/player/equalizer")]
+async fn get_equalizer(req: HttpRequest) -> impl Responder {
+    let (_, response) = when_user::<player::DspSettings>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
+    ApiResponse::success_response(
+        db_manager
+            .setting_repo()
+            .get::<player::DspSettings>(player::DspSettings::SETTING_NAME)
+            .await
+            .unwrap_or_default(),
+    )
+}
+
+#[get("/player/equalizer/presets")]
+async fn get_equalizer_presets(req: HttpRequest) -> impl Responder {
+    let (_, response) = when_user::<EqualizerPresets>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    ApiResponse::success_response(EqualizerPresets {
+        frequencies: player::EQUALIZER_FREQUENCIES,
+        presets: player::EQUALIZER_PRESETS
+            .iter()
+            .map(|(name, bands)| EqualizerPreset {
+                name,
+                bands: *bands,
+            })
+            .collect(),
+    })
+}
+
+/// Changes the equalizer and the other DSP stages of the server player. Fields
+/// left out keep their value
+#[post("/player/control-equalizer")]
+async fn control_equalizer(
+    req: HttpRequest,
+    payload: web::Json<Equalizer>,
+    sender: Data<std::sync::mpsc::Sender<PlayerCommand>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<player::DspSettings>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
+    let repo = db_manager.setting_repo();
+
+    let mut settings = repo
+        .get::<player::DspSettings>(player::DspSettings::SETTING_NAME)
+        .await
+        .unwrap_or_default();
+
+    if let Some(name) = &payload.preset {
+        match player::equalizer_preset(name) {
+            Some(bands) => {
+                settings.bands = bands;
+                settings.preset = Some(name.trim().to_lowercase());
+            }
+            None => {
+                return ApiResponse::<player::DspSettings>::not_found_response(Some(
+                    "equalizer preset not found",
+                ))
+            }
+        }
+    }
+
+    if let Some(bands) = payload.bands {
+        settings.bands = bands;
+        settings.preset = None;
+    }
+
+    if let Some(enabled) = payload.enabled {
+        settings.enabled = enabled;
+    }
+
+    if let Some(preamp) = payload.preamp {
+        settings.preamp = preamp;
+    }
+
+    if let Some(bass_boost) = payload.bass_boost {
+        settings.bass_boost = bass_boost;
+    }
+
+    settings.clamp();
+    repo.set(player::DspSettings::SETTING_NAME, &settings).await;
+
+    if sender.send(PlayerCommand::Dsp(settings.clone())).is_err() {
+        log::error!("could not send equalizer command to the player");
+    }
+
+    ApiResponse::success_response(settings)
+}
+
+/// Replaces the server queue with the tracks and starts playing them
+fn play_tracks(
+    req: &HttpRequest,
+    tracks: Vec<TrackEntity>,
+    queue_sender: &std::sync::mpsc::Sender<QueueManagerCommand>,
+) {
+    let queued_by = ClientEntity::try_from(req)
+        .map(|client| client.id)
+        .unwrap_or_default();
+
+    let command = QueueManagerCommand::PlayTracks {
+        track_ids: tracks.into_iter().map(|track| track.id).collect(),
+        queued_by,
+    };
+
+    if queue_sender.send(command).is_err() {
+        log::error!("could not send play command to the queue manager");
+    }
 }
@@ -5,7 +5,7 @@ use actix_web::{delete, get, post, put, web, HttpRequest, Responder, Scope};
 use crate::{
     db::{DbManager, PaginatedResult, Paginator},
     entity::{
-        playlist::{InPlaylistEntityDto, OutPlaylistEntityDto},
+        playlist::{InPlaylistEntityDto, OutPlaylistEntityDto, PlaylistEntity},
         playlist_tracks::{InPlaylistTrackEntityDto, OutPlaylistTrackEntityDto},
     },
     web_app::{api_response::ApiResponse, when_admin, when_user},
@@ -35,13 +35,11 @@ async fn get_playlists(req: HttpRequest) -> impl Responder {
     let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
 
     PaginatedResult::<Vec<OutPlaylistEntityDto>>::new(
-        db_manager
-            .playlist_repo()
-            .paginate(&mut paginator)
-            .await
-            .into_iter()
-            .map(OutPlaylistEntityDto::from)
-            .collect::<Vec<OutPlaylistEntityDto>>(),
+        with_durations(
+            db_manager,
+            db_manager.playlist_repo().paginate(&mut paginator).await,
+        )
+        .await,
         &paginator,
     )
     .into_response()
@@ -57,13 +55,16 @@ async fn get_a_playlist(req: HttpRequest, id: web::Path<String>) -> impl Respond
 
     let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
 
-    ApiResponse::into_response(
-        db_manager
-            .playlist_repo()
-            .find_by_id(id.into_inner().as_str())
-            .await
-            .map(OutPlaylistEntityDto::from),
-    )
+    let playlist = db_manager
+        .playlist_repo()
+        .find_by_id(id.into_inner().as_str())
+        .await;
+    let result = match playlist {
+        Some(playlist) => with_durations(db_manager, vec![playlist]).await.pop(),
+        None => None,
+    };
+
+    ApiResponse::into_response(result)
 }
 
 #[get("/playlists/default")]
@@ -76,13 +77,13 @@ async fn get_default(req: HttpRequest) -> impl Responder {
 
     let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
 
-    ApiResponse::into_response(
-        db_manager
-            .playlist_repo()
-            .get_default_playlist()
-            .await
-            .map(OutPlaylistEntityDto::from),
-    )
+    let playlist = db_manager.playlist_repo().get_default_playlist().await;
+    let result = match playlist {
+        Some(playlist) => with_durations(db_manager, vec![playlist]).await.pop(),
+        None => None,
+    };
+
+    ApiResponse::into_response(result)
 }
 
 #[post("/playlists")]
@@ -193,3 +194,20 @@ async fn remove_tracks(
 
     ApiResponse::into_response(Some(results))
 }
+
+async fn with_durations(
+    db_manager: &DbManager,
+    playlists: Vec<PlaylistEntity>,
+) -> Vec<OutPlaylistEntityDto> {
+    let repo = db_manager.playlist_repo();
+    let mut results = Vec::new();
+    for playlist in playlists {
+        let duration = repo.duration(&playlist.id).await;
+        results.push(OutPlaylistEntityDto {
+            duration,
+            ..playlist.into()
+        });
+    }
+
+    results
+}
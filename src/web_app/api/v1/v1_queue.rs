@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use actix_web::{
+    delete, get, post, put,
+    web::{self, Data},
+    HttpRequest, HttpResponse, Responder, Scope,
+};
+
+use crate::{
+    db::DbManager,
+    entity::{
+        client::ClientEntity,
+        queue::{OutQueueEntityDto, QueueEntity},
+    },
+    queue_manager::{QueueManagerCommand, CURRENT_SETTING_NAME, SHUFFLE_SETTING_NAME},
+    web_app::{api_response::ApiResponse, when_admin, when_user},
+};
+
+pub(crate) fn register_routes(scope: Scope) -> Scope {
+    scope
+        .service(get_queue)
+        .service(append_track)
+        .service(play_next)
+        .service(clear_queue)
+        .service(remove_track)
+        .service(move_track)
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct QueueTrack {
+    track_id: String,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct MoveTrack {
+    /// The new position in the play order, starting from 1
+    to: usize,
+}
+
+#[get("/queue")]
+async fn get_queue(req: HttpRequest) -> impl Responder {
+    let (_, response) = when_user::<Vec<OutQueueEntityDto>>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
+    let settings = db_manager.setting_repo();
+    let shuffle = settings
+        .get::<bool>(SHUFFLE_SETTING_NAME)
+        .await
+        .unwrap_or_default();
+    let current = settings
+        .get::<Option<i64>>(CURRENT_SETTING_NAME)
+        .await
+        .flatten();
+
+    let track_repo = db_manager.track_repo();
+    let mut results = Vec::new();
+    for (index, entry) in db_manager
+        .queue_repo()
+        .all(shuffle)
+        .await
+        .into_iter()
+        .enumerate()
+    {
+        if let Some(track) = track_repo.find_by_id(&entry.track_id).await {
+            results.push(OutQueueEntityDto {
+                position: index + 1,
+                current: current == Some(entry.internal_id),
+                queued_by: entry.queued_by,
+                queued_at: entry.queued_at,
+                track: track.into(),
+            });
+        }
+    }
+
+    ApiResponse::success_response(results)
+}
+
+#[post("/queue")]
+async fn append_track(
+    req: HttpRequest,
+    payload: web::Json<QueueTrack>,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+) -> impl Responder {
+    let (_, response) = when_user::<QueueEntity>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    add_track(&req, &payload.track_id, &queue_sender, false).await
+}
+
+#[post("/queue/next")]
+async fn play_next(
+    req: HttpRequest,
+    payload: web::Json<QueueTrack>,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<QueueEntity>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    add_track(&req, &payload.track_id, &queue_sender, true).await
+}
+
+#[delete("/queue")]
+async fn clear_queue(
+    req: HttpRequest,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<bool>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    if queue_sender.send(QueueManagerCommand::Reset).is_err() {
+        log::error!("could not send reset command to the queue manager");
+    }
+
+    ApiResponse::success_response(true)
+}
+
+#[delete("/queue/{position}")]
+async fn remove_track(
+    req: HttpRequest,
+    position: web::Path<usize>,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<QueueEntity>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let (reply, result) = tokio::sync::oneshot::channel();
+    _ = queue_sender.send(QueueManagerCommand::Remove {
+        position: position.into_inner(),
+        reply,
+    });
+
+    ApiResponse::into_response(result.await.ok().flatten())
+}
+
+#[put("/queue/{position}")]
+async fn move_track(
+    req: HttpRequest,
+    position: web::Path<usize>,
+    payload: web::Json<MoveTrack>,
+    queue_sender: Data<std::sync::mpsc::Sender<QueueManagerCommand>>,
+) -> impl Responder {
+    let (_, response) = when_admin::<bool>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let (reply, result) = tokio::sync::oneshot::channel();
+    _ = queue_sender.send(QueueManagerCommand::Move {
+        from: position.into_inner(),
+        to: payload.to,
+        reply,
+    });
+
+    if result.await.unwrap_or_default() {
+        ApiResponse::success_response(true)
+    } else {
+        ApiResponse::<bool>::not_found_response(Some("queue position not found"))
+    }
+}
+
+async fn add_track(
+    req: &HttpRequest,
+    track_id: &str,
+    queue_sender: &std::sync::mpsc::Sender<QueueManagerCommand>,
+    next: bool,
+) -> HttpResponse {
+    let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
+    if db_manager.track_repo().find_by_id(track_id).await.is_none() {
+        return ApiResponse::<QueueEntity>::not_found_response(Some("track not found"));
+    }
+
+    let queued_by = ClientEntity::try_from(req)
+        .map(|client| client.id)
+        .unwrap_or_default();
+
+    let (reply, result) = tokio::sync::oneshot::channel();
+    _ = queue_sender.send(QueueManagerCommand::Add {
+        track_id: track_id.to_string(),
+        queued_by,
+        next,
+        reply,
+    });
+
+    ApiResponse::into_response(result.await.ok().flatten())
+}
@@ -8,7 +8,11 @@ use actix_web::{
 
 use crate::{
     db::{DbManager, PaginatedResult, Paginator},
-    entity::track::{InTrackEntityDto, OutTrackEntityDto},
+    entity::{
+        track::{InTrackEntityDto, OutTrackEntityDto},
+        waveform::{OutWaveformEntityDto, WAVEFORM_RESOLUTION},
+    },
+    scanner::generate_waveform,
     web_app::{api_response::ApiResponse, when_admin, when_user},
 };
 
@@ -19,6 +23,7 @@ pub(crate) fn register_routes(scope: Scope) -> Scope {
         .service(delete_tracks)
         .service(search)
         .service(get_a_track)
+        .service(get_track_waveform)
         .service(get_tracks_by_album)
         .service(get_tracks_by_playlist)
         .service(get_tracks_by_artist)
@@ -64,6 +69,50 @@ async fn get_a_track(id: web::Path<String>, req: HttpRequest) -> impl Responder
     )
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct WaveformQuery {
+    /// Number of points wanted, at most the stored resolution
+    points: Option<usize>,
+}
+
+/// The peaks of the track, to draw its waveform. Tracks scanned before
+/// waveforms existed get theirs generated on the first request
+#[get("tracks/{id}/waveform")]
+async fn get_track_waveform(
+    id: web::Path<String>,
+    query: Query<WaveformQuery>,
+    req: HttpRequest,
+) -> impl Responder {
+    let (_, response) = when_user::<OutWaveformEntityDto>(&req).await;
+
+    if let Some(err_resp) = response {
+        return err_resp;
+    }
+
+    let db_manager = req.app_data::<Arc<DbManager>>().unwrap();
+    let Some(track) = db_manager.track_repo().find_by_id(&id.into_inner()).await else {
+        return ApiResponse::<OutWaveformEntityDto>::not_found_response(Some("track not found"));
+    };
+
+    let mut waveform = db_manager
+        .waveform_repo()
+        .find_by_media_id(&track.media_id)
+        .await;
+    if waveform.is_none() {
+        if let Some(media) = db_manager.media_repo().find_by_id(&track.media_id).await {
+            waveform = generate_waveform(&media, db_manager).await;
+        }
+    }
+
+    let points = query
+        .points
+        .unwrap_or(WAVEFORM_RESOLUTION)
+        .clamp(1, WAVEFORM_RESOLUTION);
+    ApiResponse::into_response(
+        waveform.map(|waveform| OutWaveformEntityDto::new(&waveform, points)),
+    )
+}
+
 #[get("tracks/album/{album_id}")]
 async fn get_tracks_by_album(album_id: web::Path<String>, req: HttpRequest) -> impl Responder {
     let (_, response) = when_user::<OutTrackEntityDto>(&req).await;
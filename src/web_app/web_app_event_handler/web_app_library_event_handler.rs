@@ -0,0 +1,64 @@
+use actix::Addr;
+use orsomafo::EventDispatcherBuilder;
+
+use crate::{
+    scanner::{LibraryUpdatedEvent, ScanProgressEvent},
+    websocket::{
+        server::ChatServer,
+        websocket_message::{LibraryEvent, WebsocketMessage},
+    },
+};
+
+pub(crate) fn register(builder: EventDispatcherBuilder) -> EventDispatcherBuilder {
+    builder
+        .listen_with::<LibraryUpdatedEvent>(HandleLibraryUpdated)
+        .listen_with::<ScanProgressEvent>(HandleScanProgress)
+}
+
+#[derive(Debug)]
+struct HandleLibraryUpdated;
+
+#[orsomafo::async_trait]
+impl orsomafo::EventHandler for HandleLibraryUpdated {
+    async fn handle(&self, dispatched: &orsomafo::DispatchedEvent) {
+        if let Some(event) = dispatched.the_event::<LibraryUpdatedEvent>() {
+            // Not set when the watcher runs without the web application
+            if let Some(ws_server) = busybody::helpers::get_type::<Addr<ChatServer>>() {
+                if !event.added_track_ids.is_empty() {
+                    ws_server.do_send(WebsocketMessage::LibraryEvent {
+                        event: LibraryEvent::TracksAdded {
+                            track_ids: event.added_track_ids,
+                        },
+                    });
+                }
+                if !event.removed_track_ids.is_empty() {
+                    ws_server.do_send(WebsocketMessage::LibraryEvent {
+                        event: LibraryEvent::TracksRemoved {
+                            track_ids: event.removed_track_ids,
+                        },
+                    });
+                }
+            }
+        }
+    }
+}
+
+// ---
+
+#[derive(Debug)]
+struct HandleScanProgress;
+
+#[orsomafo::async_trait]
+impl orsomafo::EventHandler for HandleScanProgress {
+    async fn handle(&self, dispatched: &orsomafo::DispatchedEvent) {
+        if let Some(event) = dispatched.the_event::<ScanProgressEvent>() {
+            if let Some(ws_server) = busybody::helpers::get_type::<Addr<ChatServer>>() {
+                ws_server.do_send(WebsocketMessage::LibraryEvent {
+                    event: LibraryEvent::ScanProgress {
+                        status: event.status,
+                    },
+                });
+            }
+        }
+    }
+}
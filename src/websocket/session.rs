@@ -3,7 +3,12 @@ use std::time::{Duration, Instant};
 use actix::prelude::*;
 use actix_web_actors::ws;
 
-use super::server;
+use crate::player::SharedPlayerState;
+
+use super::{
+    server,
+    websocket_message::{PlayerEvent, WebsocketMessage},
+};
 
 /// How often heartbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
@@ -78,7 +83,23 @@ impl Actor for WsChatSession {
             .into_actor(self)
             .then(|res, act, ctx| {
                 match res {
-                    Ok(res) => act.id = res,
+                    Ok(res) => {
+                        act.id = res;
+
+                        // Let the new session know what is playing right away
+                        if let Some(player_state) =
+                            busybody::helpers::get_type::<SharedPlayerState>()
+                        {
+                            ctx.text(
+                                WebsocketMessage::PlayerEvent {
+                                    event: PlayerEvent::State {
+                                        state: player_state.snapshot(),
+                                    },
+                                }
+                                .to_string(),
+                            );
+                        }
+                    }
                     // something is wrong with chat server
                     _ => ctx.stop(),
                 }
@@ -1,12 +1,23 @@
 use actix::Message;
 
+use crate::{
+    player::{DspSettings, PlayerState},
+    queue_manager::{RepeatMode, SleepTimer},
+    scanner::ScanStatus,
+};
+
 #[derive(Debug, serde::Serialize, Message)]
 #[rtype(result = "()")]
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum WebsocketMessage {
     #[serde(rename(serialize = "player_event"))]
     PlayerEvent { event: PlayerEvent },
     #[serde(rename(serialize = "playlist_event"))]
     PlaylistEvent { event: PlaylistEvent },
+    #[serde(rename(serialize = "queue_event"))]
+    QueueEvent { event: QueueEvent },
+    #[serde(rename(serialize = "library_event"))]
+    LibraryEvent { event: LibraryEvent },
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -26,6 +37,35 @@ pub(crate) enum PlayerEvent {
     Play { play: bool },
     #[serde(rename(serialize = "skip"))]
     Skip { next: bool },
+    #[serde(rename(serialize = "seek"))]
+    Seek { position: f64, relative: bool },
+    #[serde(rename(serialize = "volume"))]
+    Volume { level: u8, muted: bool },
+    #[serde(rename(serialize = "equalizer"))]
+    Equalizer { settings: DspSettings },
+    #[serde(rename(serialize = "crossfade"))]
+    Crossfade { seconds: f64 },
+    #[serde(rename(serialize = "track_ended"))]
+    TrackEnded {},
+    /// The track could not be played. `track_id` is not set for files played by path
+    #[serde(rename(serialize = "error"))]
+    Error {
+        track_id: Option<String>,
+        reason: String,
+    },
+    #[serde(rename(serialize = "repeat"))]
+    Repeat { mode: RepeatMode },
+    #[serde(rename(serialize = "shuffle"))]
+    Shuffle { enabled: bool },
+    /// The sleep timer was set, or cancelled when `timer` is not set
+    #[serde(rename(serialize = "sleep_timer"))]
+    SleepTimer { timer: Option<SleepTimer> },
+    /// The sleep timer went off and the music is stopping
+    #[serde(rename(serialize = "sleep_timer_expired"))]
+    SleepTimerExpired {},
+    /// A snapshot of the whole player state
+    #[serde(rename(serialize = "state"))]
+    State { state: PlayerState },
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -46,6 +86,32 @@ pub(crate) enum PlaylistEvent {
     DefaultPlaylist { playlist_id: String },
 }
 
+/// Changes to the play queue. Positions are in play order and start from 1
+#[derive(Debug, serde::Serialize)]
+pub(crate) enum QueueEvent {
+    #[serde(rename(serialize = "track_added"))]
+    TrackAdded { position: usize, track_id: String },
+    #[serde(rename(serialize = "track_removed"))]
+    TrackRemoved { position: usize, track_id: String },
+    #[serde(rename(serialize = "track_moved"))]
+    TrackMoved { from: usize, to: usize },
+    #[serde(rename(serialize = "cleared"))]
+    Cleared {},
+}
+
+/// Changes to the library made while the library folders are watched, and
+/// the progress of the scans
+#[derive(Debug, serde::Serialize)]
+pub(crate) enum LibraryEvent {
+    #[serde(rename(serialize = "tracks_added"))]
+    TracksAdded { track_ids: Vec<String> },
+    #[serde(rename(serialize = "tracks_removed"))]
+    TracksRemoved { track_ids: Vec<String> },
+    /// Progress of the scan started from the API
+    #[serde(rename(serialize = "scan_progress"))]
+    ScanProgress { status: ScanStatus },
+}
+
 impl ToString for WebsocketMessage {
     fn to_string(&self) -> String {
         serde_json::to_string(self).unwrap()